@@ -0,0 +1,18 @@
+use std::fmt::Write;
+
+/// Source with `n_fns` functions of eight lines each, mixing the expressions,
+/// calls and conditionals a real program would have
+pub fn generate_source(n_fns: usize) -> String {
+    let mut src = String::new();
+    for i in 0..n_fns {
+        writeln!(src, "def f{}(a: int, b: int) -> int:", i).unwrap();
+        writeln!(src, "    x = a * {} + b // 2 - (a % 7);", i + 1).unwrap();
+        writeln!(src, "    y = clamp(x, lo=0, hi={}) + \"str{}\".len();", i * 3, i).unwrap();
+        writeln!(src, "    if x == y and not a != b:").unwrap();
+        writeln!(src, "        x").unwrap();
+        writeln!(src, "    else:").unwrap();
+        writeln!(src, "        y").unwrap();
+        writeln!(src).unwrap();
+    }
+    src
+}
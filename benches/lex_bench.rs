@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use interp_rs::lexer::{get_keyword, Lexer};
+use interp_rs::token::Token;
+use interp_rs_bench::generate_source;
+
+fn lex(c: &mut Criterion) {
+    // 10,000 lines
+    let src = generate_source(1_250);
+
+    let mut group = c.benchmark_group("lex");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.bench_function("fns", |b| b.iter(|| Lexer::new(&src).collect::<Vec<_>>()));
+    group.finish();
+}
+
+// Ways `get_keyword` could look up each identifier the lexer reads
+fn keywords(c: &mut Criterion) {
+    let src = generate_source(1_250);
+    let idents: Vec<&str> = Lexer::new(&src)
+        .filter_map(|tok| match tok {
+            Ok((ix, tok)) if matches!(tok, Token::Ident(_)) || tok.keyword().is_some() => {
+                Some(&src[ix..ix + tok.src_len()])
+            }
+            _ => None,
+        })
+        .collect();
+    let table: HashMap<&str, &Token> = Token::keywords()
+        .iter()
+        .filter_map(|tok| Some((tok.keyword()?, tok)))
+        .collect();
+
+    let mut group = c.benchmark_group("keywords");
+    group.throughput(Throughput::Elements(idents.len() as u64));
+    group.bench_function("match", |b| {
+        b.iter(|| idents.iter().filter(|i| get_keyword(black_box(i)).is_some()).count())
+    });
+    group.bench_function("hashmap", |b| {
+        b.iter(|| idents.iter().filter(|i| table.contains_key(black_box(*i))).count())
+    });
+    // Every keyword is 2 to 6 bytes, so other lengths can skip the match
+    group.bench_function("len_then_match", |b| {
+        b.iter(|| {
+            idents
+                .iter()
+                .filter(|i| (2..=6).contains(&i.len()) && get_keyword(black_box(i)).is_some())
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, lex, keywords);
+criterion_main!(benches);
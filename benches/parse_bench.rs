@@ -0,0 +1,18 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use interp_rs::lexer::Lexer;
+use interp_rs::parser::parse;
+use interp_rs_bench::generate_source;
+
+fn parse_fns(c: &mut Criterion) {
+    // 10,000 lines
+    let src = generate_source(1_250);
+    assert!(parse(Lexer::new(&src)).is_ok(), "Generated source should parse");
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.bench_function("fns", |b| b.iter(|| parse(Lexer::new(&src))));
+    group.finish();
+}
+
+criterion_group!(benches, parse_fns);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+#![no_main]
+
+use interp_rs::lexer::Lexer;
+use interp_rs::parser::parse;
+use libfuzzer_sys::fuzz_target;
+
+// Any input should come back as tokens or a LexErr/ParseErr, never a panic
+fuzz_target!(|data: &[u8]| {
+    let Ok(src) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    let _ = Lexer::new(src).collect::<Vec<_>>();
+    let _ = parse(Lexer::new(src));
+});
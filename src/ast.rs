@@ -1,7 +1,11 @@
+use crate::pretty::{fmt_str, Formatter};
+use crate::span::{Span, Spanned};
 use crate::token::{Operator, Token};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum TypeAnnotation<'src> {
+    /// What a function returns when it doesn't declare a type
+    Unit,
     Int,
     Str,
     Bool,
@@ -21,14 +25,16 @@ impl std::fmt::Display for TypeAnnotation<'_> {
             "{}",
             match self {
                 Self::Union(l, r) => return write!(f, "{} | {}", l, r),
+                Self::Unit => "()",
                 Self::Int => "int",
                 Self::Str => "str",
                 Self::Bool => "bool",
                 Self::Dynamic(d) => d,
                 Self::Mut(t) => return write!(f, "mut {}", t),
                 Self::Parameterized { parent, params } => {
-                    let params = params.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ");
-                    return write!(f, "{}[{}]", parent, params);
+                    write!(f, "{}[", parent)?;
+                    write_comma_separated(f, params)?;
+                    return f.write_str("]");
                 }
             }
         )
@@ -39,9 +45,10 @@ impl std::fmt::Display for TypeAnnotation<'_> {
 pub enum AstLiteral<'src> {
     Int(Token<'src>),
     Str(Token<'src>),
-    Ident(Token<'src>),
+    /// Keeps where the name appears, for diagnostics that point at a use
+    Ident(Spanned<Token<'src>>),
     TypedIdent {
-        name: Token<'src>,
+        name: Spanned<Token<'src>>,
         type_annotation: TypeAnnotation<'src>,
     },
 }
@@ -52,6 +59,18 @@ impl<'src> From<AstLiteral<'src>> for AstExpr<'src> {
     }
 }
 
+// Writes `items` straight to `f` with `, ` between them, rather than
+// collecting each into a `String` to join
+fn write_comma_separated<T: std::fmt::Display>(f: &mut std::fmt::Formatter<'_>, items: &[T]) -> std::fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
 impl std::fmt::Display for AstLiteral<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -59,8 +78,9 @@ impl std::fmt::Display for AstLiteral<'_> {
             "{}",
             match self {
                 Self::Int(i) => i,
+                &Self::Str(Token::StrLiteral { value, triple }) => return f.write_str(&fmt_str(value, triple)),
                 Self::Str(s) => s,
-                Self::Ident(name) => name,
+                Self::Ident(name) => &name.node,
                 Self::TypedIdent { name, type_annotation } => return write!(f, "{}: {}", name, type_annotation),
             }
         )
@@ -78,31 +98,85 @@ pub struct AstBinExpr<'src> {
 impl<'src> From<(Token<'src>, Operator, Token<'src>)> for AstBinExpr<'src> {
     fn from(value: (Token<'src>, Operator, Token<'src>)) -> Self {
         let (l, op, r) = value;
-        let l_lit = AstLiteral::Ident(l);
-        let r_lit = AstLiteral::Ident(r);
-
-        let l_expr = AstExpr::LitExpr(l_lit);
-        let r_expr = AstExpr::LitExpr(r_lit);
-
         return AstBinExpr {
             op,
-            l: Box::new(l_expr),
-            r: Box::new(r_expr),
+            l: Box::new(AstLiteral::from(l).into()),
+            r: Box::new(AstLiteral::from(r).into()),
         };
     }
 }
 
+// For tests, picks the literal kind the parser would for a lone token
+impl<'src> From<Token<'src>> for AstLiteral<'src> {
+    fn from(tok: Token<'src>) -> Self {
+        match tok {
+            Token::IntLiteral { .. } => AstLiteral::Int(tok),
+            Token::StrLiteral { .. } => AstLiteral::Str(tok),
+            _ => AstLiteral::Ident(Spanned::new(tok, Span::default())),
+        }
+    }
+}
+
+impl<'src> AstLiteral<'src> {
+    /// A name read from the token at `ix`
+    pub fn ident(tok: Token<'src>, ix: usize) -> Self {
+        AstLiteral::Ident(spanned_token(tok, ix))
+    }
+
+    /// A name read from the token at `ix`, followed by its annotation
+    pub fn typed_ident(tok: Token<'src>, ix: usize, type_annotation: TypeAnnotation<'src>) -> Self {
+        AstLiteral::TypedIdent {
+            name: spanned_token(tok, ix),
+            type_annotation,
+        }
+    }
+}
+
+/// `tok`, spanning its source text from `ix`
+pub fn spanned_token(tok: Token<'_>, ix: usize) -> Spanned<Token<'_>> {
+    let span = Span::new(ix, ix + tok.src_len());
+    Spanned::new(tok, span)
+}
+
 impl std::fmt::Display for AstBinExpr<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "({} {} {})", self.l, self.op, self.r)
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct AstUnaryExpr<'src> {
+    pub op: Operator,
+    pub expr: Box<AstExpr<'src>>,
+}
+
+impl std::fmt::Display for AstUnaryExpr<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.op, self.expr)
+    }
+}
+
+impl<'src> From<AstUnaryExpr<'src>> for AstExpr<'src> {
+    fn from(value: AstUnaryExpr<'src>) -> Self {
+        AstExpr::UnaryExpr(value)
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct AstConditional<'src> {
     pub condition: Box<AstExpr<'src>>,
     pub if_block: AstBlock<'src>,
+    /// `elif` conditions and bodies, in order
+    pub elif_branches: Vec<(AstExpr<'src>, AstBlock<'src>)>,
     pub else_block: Option<Box<AstExpr<'src>>>,
+    /// Written on one line, either `if c: stmt` or, with an else, `a if c else b`
+    pub inline: bool,
+}
+
+impl AstConditional<'_> {
+    pub fn is_ternary(&self) -> bool {
+        self.inline && self.else_block.is_some()
+    }
 }
 
 impl<'src> From<AstConditional<'src>> for AstExpr<'src> {
@@ -112,19 +186,25 @@ impl<'src> From<AstConditional<'src>> for AstExpr<'src> {
 }
 impl std::fmt::Display for AstConditional<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let else_ = if let Some(expr) = &self.else_block {
-            let indent = if self.if_block.indent > 0 {
-                self.if_block.indent - 1
-            } else {
-                0
-            };
-            let spaces = std::iter::repeat(" ").take(indent * 4).collect::<String>();
-            format!("{}else:\n{}", spaces, expr)
-        } else {
-            "".to_string()
-        };
+        // On its own, the best guess at where it sits is just outside its body
+        f.write_str(&Formatter::default().fmt_conditional(self, self.if_block.indent.saturating_sub(1)))
+    }
+}
 
-        write!(f, "if {}:\n{}{}", self.condition, self.if_block, else_)
+/// `*args` splats a sequence into positional arguments, `**kwargs` a mapping
+/// into keyword arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spread {
+    Positional,
+    Keyword,
+}
+
+impl std::fmt::Display for Spread {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Positional => write!(f, "*"),
+            Self::Keyword => write!(f, "**"),
+        }
     }
 }
 
@@ -132,13 +212,16 @@ impl std::fmt::Display for AstConditional<'_> {
 pub struct CallArg<'src> {
     pub name: Option<AstExpr<'src>>,
     pub expr: AstExpr<'src>,
+    // Spread arguments never have a name
+    pub spread: Option<Spread>,
 }
 
 impl std::fmt::Display for CallArg<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self.name {
-            Some(name) => write!(f, "{}={}", name, self.expr),
-            None => write!(f, "{}", self.expr),
+        match (&self.name, self.spread) {
+            (Some(name), _) => write!(f, "{}={}", name, self.expr),
+            (None, Some(spread)) => write!(f, "{}{}", spread, self.expr),
+            (None, None) => write!(f, "{}", self.expr),
         }
     }
 }
@@ -147,18 +230,46 @@ impl std::fmt::Display for CallArg<'_> {
 pub struct AstCallExpr<'src> {
     pub called_expr: Box<AstExpr<'src>>,
     pub args: Vec<CallArg<'src>>,
+    /// `f?()`, which is `nil` without calling anything when `f` is
+    pub optional: bool,
 }
 
 impl std::fmt::Display for AstCallExpr<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let args = self.args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ");
-        write!(f, "{}({})", self.called_expr, args)
+        write!(f, "{}{}(", self.called_expr, if self.optional { "?" } else { "" })?;
+        write_comma_separated(f, &self.args)?;
+        f.write_str(")")
+    }
+}
+
+/// A call to a declared struct's name, e.g. `Point(x=1, y=2)`. The parser
+/// can't tell these apart from function calls, see `resolve_struct_inits`.
+#[derive(Debug, PartialEq)]
+pub struct AstStructInit<'src> {
+    pub name: AstLiteral<'src>,
+    pub fields: Vec<CallArg<'src>>,
+}
+
+impl std::fmt::Display for AstStructInit<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}(", self.name)?;
+        write_comma_separated(f, &self.fields)?;
+        f.write_str(")")
+    }
+}
+
+impl<'src> From<AstStructInit<'src>> for AstExpr<'src> {
+    fn from(value: AstStructInit<'src>) -> Self {
+        AstExpr::StructInitExpr(value)
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum AstExpr<'src> {
     BinExpr(AstBinExpr<'src>),
+    UnaryExpr(AstUnaryExpr<'src>),
+    /// Logical `not`, kept apart from `UnaryExpr` since it only applies to bools
+    NotExpr(Box<AstExpr<'src>>),
     LitExpr(AstLiteral<'src>),
     ConditionalExpr(AstConditional<'src>),
     BlockExpr(AstBlock<'src>),
@@ -166,6 +277,38 @@ pub enum AstExpr<'src> {
     CallExpr(AstCallExpr<'src>),
 
     AttrAccessExpr(AttrAccess<'src>),
+
+    IndexExpr(AstIndex<'src>),
+    SliceExpr(AstSlice<'src>),
+
+    StructInitExpr(AstStructInit<'src>),
+
+    /// A `def` in expression position, e.g. passed straight to a call. It
+    /// still needs a name, but that name is only bound inside its own body
+    /// (so it can recurse); the enclosing scope just gets the value.
+    FnDefExpr(Box<FnDef<'src>>),
+}
+
+impl AstExpr<'_> {
+    /// Conditionals and blocks end in an indented block whose statements carry
+    /// their own semicolons, so none is written after the expression itself
+    pub fn ends_in_block(&self) -> bool {
+        match self {
+            AstExpr::ConditionalExpr(cond) => !cond.is_ternary(),
+            AstExpr::BlockExpr(_) | AstExpr::FnDefExpr(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the expression names a place that can be assigned to
+    pub fn is_lvalue(&self) -> bool {
+        matches!(
+            self,
+            AstExpr::LitExpr(AstLiteral::Ident(_) | AstLiteral::TypedIdent { .. })
+                | AstExpr::AttrAccessExpr(_)
+                | AstExpr::IndexExpr(_)
+        )
+    }
 }
 
 impl<'src> From<AstCallExpr<'src>> for AstExpr<'src> {
@@ -187,14 +330,7 @@ impl<'src> From<(AstExpr<'src>, Operator, AstExpr<'src>)> for AstExpr<'src> {
 
 impl std::fmt::Display for AstExpr<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::ConditionalExpr(c) => write!(f, "{}", c),
-            Self::BinExpr(bin) => write!(f, "{}", bin),
-            Self::LitExpr(lit) => write!(f, "{}", lit),
-            Self::BlockExpr(block) => write!(f, "{}", block),
-            Self::CallExpr(fn_) => write!(f, "{}", fn_),
-            Self::AttrAccessExpr(aa) => write!(f, "{}", aa),
-        }
+        f.write_str(&Formatter::default().fmt_expr(self, 0))
     }
 }
 
@@ -221,21 +357,104 @@ impl<'src> From<AstExpr<'src>> for AstNode<'src> {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct AstAttribute<'src> {
+    pub name: &'src str,
+    pub args: Vec<AstExpr<'src>>,
+}
+
+impl std::fmt::Display for AstAttribute<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.args.is_empty() {
+            return write!(f, "#[{}]", self.name);
+        }
+        write!(f, "#[{}(", self.name)?;
+        write_comma_separated(f, &self.args)?;
+        f.write_str(")]")
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct FnDef<'src> {
+    pub attributes: Vec<AstAttribute<'src>>,
+    /// The `StrLiteral` token, so it's printed back with the same quotes
+    pub docstring: Option<Token<'src>>,
     pub name: AstLiteral<'src>,
-    pub args: Vec<AstLiteral<'src>>,
+    pub type_params: Vec<&'src str>,
+    pub args: Vec<FnParam<'src>>,
     pub body: AstBlock<'src>,
     pub return_type: TypeAnnotation<'src>,
 }
 
+/// A function parameter, e.g. `x: int = -1`
+#[derive(Debug, PartialEq)]
+pub struct FnParam<'src> {
+    pub ident: AstLiteral<'src>,
+    pub default: Option<AstExpr<'src>>,
+}
+
+impl std::fmt::Display for FnParam<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.default {
+            Some(default) => write!(f, "{} = {}", self.ident, default),
+            None => write!(f, "{}", self.ident),
+        }
+    }
+}
+
 impl std::fmt::Display for FnDef<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let args = self.args.iter().map(|a| format!("{}", a)).collect::<Vec<_>>().join(",");
-        write!(f, "def {}({}) -> {}:\n{}", self.name, args, self.return_type, self.body)
+        f.write_str(&Formatter::default().fmt_fn_def(self))
+    }
+}
+
+/// A field in a struct definition, e.g. `x: int = 0`
+#[derive(Debug, PartialEq)]
+pub struct AstStructField<'src> {
+    /// Whole-line comments above the field, without their `#`
+    pub comments: Vec<&'src str>,
+    pub name: Token<'src>,
+    pub type_annotation: TypeAnnotation<'src>,
+    pub default: Option<AstExpr<'src>>,
+}
+
+impl std::fmt::Display for AstStructField<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.name, self.type_annotation)?;
+        match &self.default {
+            Some(default) => write!(f, " = {}", default),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What a `case` arm is checked against
+#[derive(Debug, PartialEq)]
+pub enum Pattern<'src> {
+    /// An int or string literal, compared by equality
+    Literal(AstLiteral<'src>),
+    /// Matches anything, binding it to the name for the arm's body
+    Binding(Spanned<Token<'src>>),
+    /// `_`, matches anything without binding it
+    Wildcard,
+}
+
+impl std::fmt::Display for Pattern<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Literal(lit) => write!(f, "{}", lit),
+            Self::Binding(name) => write!(f, "{}", name),
+            Self::Wildcard => write!(f, "_"),
+        }
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct MatchArm<'src> {
+    pub pattern: Pattern<'src>,
+    pub body: AstBlock<'src>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum AstStmt<'src> {
     Expr {
@@ -247,69 +466,132 @@ pub enum AstStmt<'src> {
         target: AstExpr<'src>,
         assigned: AstExpr<'src>,
     },
+    /// `let x = 1;`, `let mut x = 1;` or `const X = 1;`, which always bind a
+    /// new name where a bare assignment may update an existing one
+    Let {
+        name: AstLiteral<'src>,
+        mutable: bool,
+        constant: bool,
+        value: AstExpr<'src>,
+    },
     FnDef(FnDef<'src>),
     StructDef {
+        attributes: Vec<AstAttribute<'src>>,
         name: AstLiteral<'src>,
-        fields: Vec<AstLiteral<'src>>,
+        fields: Vec<AstStructField<'src>>,
         methods: Vec<FnDef<'src>>,
     },
+    /// Runs the first arm whose pattern matches the scrutinee
+    Match {
+        scrutinee: AstExpr<'src>,
+        arms: Vec<MatchArm<'src>>,
+    },
 }
 
 impl std::fmt::Display for AstStmt<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::StructDef { name, fields, methods } => {
-                let fields = fields
-                    .iter()
-                    .map(|a| format!("    {}", a.to_string()))
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                let methods = methods.iter().map(|m| format!("    {}", m)).collect::<String>();
-                write!(f, "struct {}:\n{}\n\n{}", name, fields, methods)
-            }
-            Self::FnDef(FnDef {
-                name,
-                args,
-                body,
-                return_type,
-            }) => {
-                // TODO: Make this efficient
-                let args_str = args
-                    .iter()
-                    .map(|a| format!("{}", a))
-                    .collect::<Vec<String>>()
-                    .join(", ");
-
-                return write!(f, "def {}({}) -> {}:\n{};", name, args_str, return_type, body);
-            }
-            Self::Assignment { target, assigned } => return write!(f, "{} = {};", target, assigned),
-            Self::Expr { expr, has_semi } => {
-                let mut expr = format!("{}", expr);
-                if *has_semi {
-                    expr.push(';');
-                }
-                return write!(f, "{}", expr);
-            }
-            Self::Return(e) => return write!(f, "return {};", e),
+        f.write_str(&Formatter::default().fmt_stmt(self, 0))
+    }
+}
+
+/// A `#!key: value` directive from the top of a file, e.g. `#!format: off`
+#[derive(Debug, PartialEq, Eq)]
+pub struct Pragma<'src> {
+    pub key: &'src str,
+    pub value: &'src str,
+}
+
+impl<'src> Pragma<'src> {
+    pub fn new(text: &'src str) -> Self {
+        match text.split_once(':') {
+            Some((key, value)) => Pragma {
+                key: key.trim(),
+                value: value.trim(),
+            },
+            None => Pragma {
+                key: text.trim(),
+                value: "",
+            },
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl std::fmt::Display for Pragma<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.value.is_empty() {
+            return write!(f, "#!{}", self.key);
+        }
+        write!(f, "#!{}: {}", self.key, self.value)
+    }
+}
+
+#[derive(Debug)]
 pub struct AstBlock<'src> {
+    pub pragmas: Vec<Pragma<'src>>,
     pub indent: usize,
-    pub stmts: Vec<AstStmt<'src>>,
+    pub stmts: Vec<Spanned<AstStmt<'src>>>,
     pub has_semi: bool,
+    /// From the start of the first statement to the end of the last
+    pub span: Span,
 }
 
-impl<'src> std::fmt::Display for AstBlock<'src> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut str = String::new();
-        let spaces = std::iter::repeat(" ").take(self.indent * 4).collect::<String>();
-        for stmt in &self.stmts {
-            str.push_str(format!("{}{}\n", spaces, stmt).as_str());
+// Like `Spanned`, the span is left out so reformatted source compares equal
+impl PartialEq for AstBlock<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.pragmas == other.pragmas
+            && self.indent == other.indent
+            && self.stmts == other.stmts
+            && self.has_semi == other.has_semi
+    }
+}
+
+impl<'src> AstBlock<'src> {
+    pub fn is_empty(&self) -> bool {
+        self.stmts.is_empty()
+    }
+
+    /// The block's only statement, if it is an expression without a semicolon
+    pub fn single_expr(&self) -> Option<&AstExpr<'src>> {
+        match self.stmts.as_slice() {
+            [stmt] => match &stmt.node {
+                AstStmt::Expr { expr, has_semi: false } => Some(expr),
+                _ => None,
+            },
+            _ => None,
         }
-        write!(f, "{}", str)
+    }
+
+    pub fn last_stmt(&self) -> Option<&AstStmt<'src>> {
+        self.stmts.last().map(|s| &s.node)
+    }
+
+    /// Whether the block evaluates to its final expression
+    pub fn is_tail_expression(&self) -> bool {
+        matches!(self.last_stmt(), Some(AstStmt::Expr { has_semi: false, .. }))
+    }
+
+    /// The statements without their spans
+    pub fn stmts(&self) -> impl Iterator<Item = &AstStmt<'src>> {
+        self.stmts.iter().map(|s| &s.node)
+    }
+
+    /// Functions defined directly in this block, not in nested ones
+    pub fn functions(&self) -> impl Iterator<Item = &FnDef<'src>> {
+        self.stmts().filter_map(|stmt| match stmt {
+            AstStmt::FnDef(fn_def) => Some(fn_def),
+            _ => None,
+        })
+    }
+
+    /// `StructDef`s defined directly in this block
+    pub fn structs(&self) -> impl Iterator<Item = &AstStmt<'src>> {
+        self.stmts().filter(|stmt| matches!(stmt, AstStmt::StructDef { .. }))
+    }
+}
+
+impl std::fmt::Display for AstBlock<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&Formatter::default().fmt_block(self))
     }
 }
 
@@ -317,11 +599,24 @@ impl<'src> std::fmt::Display for AstBlock<'src> {
 pub struct AttrAccess<'src> {
     pub attribute: AstLiteral<'src>,
     pub expr: Box<AstExpr<'src>>,
+    /// `a?.b`, which is `nil` when `a` is
+    pub optional: bool,
+}
+
+impl AttrAccess<'_> {
+    /// How the access is written between the object and attribute
+    pub fn accessor(&self) -> &'static str {
+        if self.optional {
+            "?."
+        } else {
+            "."
+        }
+    }
 }
 
 impl std::fmt::Display for AttrAccess<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}.{}", self.expr, self.attribute)
+        write!(f, "{}{}{}", self.expr, self.accessor(), self.attribute)
     }
 }
 
@@ -330,3 +625,101 @@ impl<'src> From<AttrAccess<'src>> for AstExpr<'src> {
         AstExpr::AttrAccessExpr(value)
     }
 }
+
+#[derive(Debug, PartialEq)]
+pub struct AstIndex<'src> {
+    pub target: Box<AstExpr<'src>>,
+    pub index: Box<AstExpr<'src>>,
+}
+
+impl std::fmt::Display for AstIndex<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]", self.target, self.index)
+    }
+}
+
+impl<'src> From<AstIndex<'src>> for AstExpr<'src> {
+    fn from(value: AstIndex<'src>) -> Self {
+        AstExpr::IndexExpr(value)
+    }
+}
+
+/// `a[start:end]`, where either bound may be left out
+#[derive(Debug, PartialEq)]
+pub struct AstSlice<'src> {
+    pub target: Box<AstExpr<'src>>,
+    pub start: Option<Box<AstExpr<'src>>>,
+    pub end: Option<Box<AstExpr<'src>>>,
+}
+
+impl std::fmt::Display for AstSlice<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bound = |b: &Option<Box<AstExpr<'_>>>| b.as_ref().map(|e| e.to_string()).unwrap_or_default();
+        write!(f, "{}[{}:{}]", self.target, bound(&self.start), bound(&self.end))
+    }
+}
+
+impl<'src> From<AstSlice<'src>> for AstExpr<'src> {
+    fn from(value: AstSlice<'src>) -> Self {
+        AstExpr::SliceExpr(value)
+    }
+}
+
+#[cfg(test)]
+fn parse_src(src: &str) -> AstBlock<'_> {
+    crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap()
+}
+
+#[test]
+fn test_block_is_empty() {
+    assert!(parse_src("").is_empty());
+    assert!(parse_src("\n\n").is_empty());
+    assert!(!parse_src("1;\n").is_empty());
+}
+
+#[test]
+fn test_block_single_expr() {
+    let one = AstExpr::LitExpr(AstLiteral::Int(Token::int(1)));
+    assert_eq!(parse_src("1\n").single_expr(), Some(&one));
+    assert_eq!(parse_src("1;\n").single_expr(), None);
+    assert_eq!(parse_src("x = 1;\n").single_expr(), None);
+    assert_eq!(parse_src("x = 1;\nx\n").single_expr(), None);
+    assert_eq!(parse_src("").single_expr(), None);
+}
+
+#[test]
+fn test_block_last_stmt() {
+    assert_eq!(parse_src("").last_stmt(), None);
+    assert!(matches!(
+        parse_src("1;\nx = 2;\n").last_stmt(),
+        Some(AstStmt::Assignment { .. })
+    ));
+    assert!(matches!(
+        parse_src("def f() -> int:\n    1\n").last_stmt(),
+        Some(AstStmt::FnDef(_))
+    ));
+}
+
+#[test]
+fn test_block_is_tail_expression() {
+    assert!(parse_src("1\n").is_tail_expression());
+    assert!(parse_src("x = 1;\nx\n").is_tail_expression());
+    assert!(!parse_src("1;\n").is_tail_expression());
+    assert!(!parse_src("x = 1;\n").is_tail_expression());
+    assert!(!parse_src("").is_tail_expression());
+}
+
+#[test]
+fn test_block_stmt_filters() {
+    let block = parse_src(
+        "struct P:\n    x: int\n\ndef f() -> int:\n    def inner() -> int:\n        1\n    inner()\n\nx = 1;\ndef g() -> int:\n    2\n\n",
+    );
+
+    assert_eq!(block.stmts().count(), 4);
+    let names: Vec<_> = block.functions().map(|f| f.name.to_string()).collect();
+    assert_eq!(names, vec!["f", "g"]);
+    assert!(matches!(
+        block.structs().collect::<Vec<_>>().as_slice(),
+        [AstStmt::StructDef { name, .. }] if name.to_string() == "P"
+    ));
+}
@@ -0,0 +1,227 @@
+use crate::ast::{AstBlock, AstConditional, AstExpr, AstStmt, FnDef, MatchArm, Pattern};
+
+// Only statement-level `if`s and `match`es branch. A conditional used as a
+// value (`y = if c: ...`) stays a single instruction of its block.
+
+/// A run of statements that always execute together, in order
+#[derive(Debug)]
+pub struct BasicBlock<'a, 'src> {
+    pub id: usize,
+    pub instructions: Vec<&'a AstStmt<'src>>,
+    pub successors: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct ControlFlowGraph<'a, 'src> {
+    /// Indexed by block id. The entry is always block 0.
+    pub blocks: Vec<BasicBlock<'a, 'src>>,
+    pub exit: usize,
+}
+
+pub const ENTRY: usize = 0;
+
+pub fn build_cfg<'a, 'src>(fn_def: &'a FnDef<'src>) -> ControlFlowGraph<'a, 'src> {
+    let mut builder = CfgBuilder::default();
+    let entry = builder.new_block();
+    let end = builder.block(&fn_def.body, entry);
+
+    // A `return` jumps straight to the exit, so it can't share a block with
+    // statements that other paths fall through
+    let exit = if builder.blocks[end].instructions.is_empty() {
+        end
+    } else {
+        let exit = builder.new_block();
+        builder.edge(end, exit);
+        exit
+    };
+    for from in std::mem::take(&mut builder.returns) {
+        builder.edge(from, exit);
+    }
+
+    ControlFlowGraph {
+        blocks: builder.blocks,
+        exit,
+    }
+}
+
+#[derive(Default)]
+struct CfgBuilder<'a, 'src> {
+    blocks: Vec<BasicBlock<'a, 'src>>,
+    // Blocks ending in a `return`, linked to the exit once it exists
+    returns: Vec<usize>,
+}
+
+impl<'a, 'src> CfgBuilder<'a, 'src> {
+    fn new_block(&mut self) -> usize {
+        let id = self.blocks.len();
+        self.blocks.push(BasicBlock {
+            id,
+            instructions: Vec::new(),
+            successors: Vec::new(),
+        });
+        id
+    }
+
+    fn edge(&mut self, from: usize, to: usize) {
+        self.blocks[from].successors.push(to);
+    }
+
+    // Adds `block`'s statements starting in `current`, returning the block
+    // control ends up in
+    fn block(&mut self, block: &'a AstBlock<'src>, mut current: usize) -> usize {
+        for stmt in block.stmts() {
+            self.blocks[current].instructions.push(stmt);
+            current = match stmt {
+                AstStmt::Return(_) => {
+                    self.returns.push(current);
+                    // Whatever follows can never run
+                    self.new_block()
+                }
+                AstStmt::Expr {
+                    expr: AstExpr::ConditionalExpr(cond),
+                    ..
+                } if !cond.is_ternary() => self.conditional(cond, current),
+                AstStmt::Match { arms, .. } => self.match_arms(arms, current),
+                _ => current,
+            };
+        }
+        current
+    }
+
+    fn branch(&mut self, block: &'a AstBlock<'src>, from: usize, join: usize) {
+        let start = self.new_block();
+        self.edge(from, start);
+        let end = self.block(block, start);
+        self.edge(end, join);
+    }
+
+    fn conditional(&mut self, cond: &'a AstConditional<'src>, from: usize) -> usize {
+        let join = self.new_block();
+        self.branch(&cond.if_block, from, join);
+        for (_, block) in &cond.elif_branches {
+            self.branch(block, from, join);
+        }
+
+        match cond.else_block.as_deref() {
+            Some(AstExpr::BlockExpr(block)) => self.branch(block, from, join),
+            Some(AstExpr::ConditionalExpr(chained)) => {
+                // `else if` is a conditional of its own, run on the else side
+                let start = self.new_block();
+                self.edge(from, start);
+                let end = self.conditional(chained, start);
+                self.edge(end, join);
+            }
+            // A plain expression doesn't branch any further
+            Some(_) | None => self.edge(from, join),
+        }
+        join
+    }
+
+    fn match_arms(&mut self, arms: &'a [MatchArm<'src>], from: usize) -> usize {
+        let join = self.new_block();
+        for arm in arms {
+            self.branch(&arm.body, from, join);
+        }
+
+        // Without a catch-all arm, a scrutinee can match nothing
+        let is_exhaustive = arms
+            .iter()
+            .any(|arm| matches!(arm.pattern, Pattern::Wildcard | Pattern::Binding(_)));
+        if !is_exhaustive {
+            self.edge(from, join);
+        }
+        join
+    }
+}
+
+impl ControlFlowGraph<'_, '_> {
+    /// Blocks no path from the entry reaches, other than empty ones
+    pub fn unreachable_blocks(&self) -> Vec<usize> {
+        let reached = self.reached();
+        self.blocks
+            .iter()
+            .filter(|b| !reached[b.id] && !b.instructions.is_empty())
+            .map(|b| b.id)
+            .collect()
+    }
+
+    /// Whether every way into the exit ends in a `return` or a tail expression
+    pub fn all_paths_return(&self) -> bool {
+        if self.exit == ENTRY {
+            return ends_in_value(&self.blocks[ENTRY]);
+        }
+        let reached = self.reached();
+        self.blocks
+            .iter()
+            .filter(|b| reached[b.id] && b.successors.contains(&self.exit))
+            .all(ends_in_value)
+    }
+
+    // Indexed by block id
+    fn reached(&self) -> Vec<bool> {
+        let mut reached = vec![false; self.blocks.len()];
+        let mut stack = vec![ENTRY];
+        while let Some(id) = stack.pop() {
+            if !std::mem::replace(&mut reached[id], true) {
+                stack.extend(&self.blocks[id].successors);
+            }
+        }
+        reached
+    }
+}
+
+fn ends_in_value(block: &BasicBlock<'_, '_>) -> bool {
+    match block.instructions.last() {
+        Some(AstStmt::Return(_)) => true,
+        // A branch's value comes from the blocks it branches to
+        Some(AstStmt::Expr {
+            expr: AstExpr::ConditionalExpr(cond),
+            ..
+        }) => cond.is_ternary(),
+        Some(AstStmt::Expr { has_semi, .. }) => !has_semi,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+fn fn_def(src: &str) -> FnDef<'_> {
+    let mut block = crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    match block.stmts.remove(0).node {
+        AstStmt::FnDef(fn_def) => fn_def,
+        stmt => panic!("Expected FnDef, got {:?}", stmt),
+    }
+}
+
+#[test]
+fn test_cfg_if_else() {
+    let f = fn_def("def f(c: bool) -> int:\n    if c:\n        1\n    else:\n        2\n");
+    let cfg = build_cfg(&f);
+
+    assert_eq!(cfg.blocks.len(), 4);
+    let successors: Vec<_> = cfg.blocks.iter().map(|b| b.successors.clone()).collect();
+    assert_eq!(successors, vec![vec![2, 3], vec![], vec![1], vec![1]]);
+    assert_eq!(cfg.exit, 1);
+    assert!(cfg.unreachable_blocks().is_empty());
+    assert!(cfg.all_paths_return());
+}
+
+#[test]
+fn test_cfg_unreachable_after_return() {
+    let f = fn_def("def f(c: bool) -> int:\n    return 1;\n    x = 2;\n    x\n");
+    let cfg = build_cfg(&f);
+
+    assert_eq!(cfg.unreachable_blocks(), vec![1]);
+    assert_eq!(cfg.blocks[1].instructions.len(), 2);
+}
+
+#[test]
+fn test_cfg_missing_return_path() {
+    let f = fn_def("def f(c: bool) -> int:\n    if c:\n        return 1;\n    x = 2;\n");
+    assert!(!build_cfg(&f).all_paths_return());
+
+    let f = fn_def("def f(c: bool) -> int:\n    if c:\n        return 1;\n    2\n");
+    assert!(build_cfg(&f).all_paths_return());
+
+    let f = fn_def("def f(c: int) -> int:\n    match c:\n        case 1:\n            return 1;\n        case _:\n            return 2;\n");
+    assert!(build_cfg(&f).all_paths_return());
+}
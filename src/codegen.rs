@@ -0,0 +1,18 @@
+pub mod c;
+pub mod wasm;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CodegenErr {
+    // Valid toki that the backend has no translation for yet
+    Unsupported(String),
+}
+
+impl std::fmt::Display for CodegenErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(what) => write!(f, "Code generation is not supported for {}", what),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, CodegenErr>;
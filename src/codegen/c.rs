@@ -0,0 +1,587 @@
+use std::collections::HashSet;
+
+use crate::ast::{
+    AstBlock, AstCallExpr, AstConditional, AstExpr, AstLiteral, AstStmt, AstStructField, AttrAccess, CallArg, FnDef,
+    FnParam, TypeAnnotation,
+};
+use crate::codegen::{CodegenErr, Result};
+use crate::span::Spanned;
+use crate::token::{Operator, Token};
+
+// What to do with the value of a block's tail expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tail {
+    Return,
+    Discard,
+}
+
+/// Emits C source for a whole program. Function definitions become C
+/// functions, structs become typedef'd structs and everything else at the top
+/// level goes into `main`.
+#[derive(Default)]
+pub struct CCodegen {
+    out: String,
+    indent: usize,
+    structs: HashSet<String>,
+    declared: HashSet<String>,
+}
+
+fn unsupported<T>(what: &str) -> Result<T> {
+    Err(CodegenErr::Unsupported(what.to_string()))
+}
+
+impl CCodegen {
+    pub fn generate(module: &AstBlock<'_>) -> Result<String> {
+        let mut gen = CCodegen::default();
+        gen.line("#include <stdint.h>");
+
+        for stmt in &module.stmts {
+            if let AstStmt::StructDef {
+                name, fields, methods, ..
+            } = &stmt.node
+            {
+                if !methods.is_empty() {
+                    return unsupported("struct methods");
+                }
+                gen.struct_def(name, fields)?;
+            }
+        }
+
+        let fn_defs = module
+            .stmts
+            .iter()
+            .filter_map(|s| match &s.node {
+                AstStmt::FnDef(f) => Some(f),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Prototypes first so definition order doesn't matter
+        gen.line("");
+        for fn_def in &fn_defs {
+            let signature = gen.fn_signature(fn_def)?;
+            gen.line(&format!("{};", signature));
+        }
+        for fn_def in &fn_defs {
+            gen.line("");
+            gen.fn_def(fn_def)?;
+        }
+
+        gen.line("");
+        gen.line("int main(void) {");
+        gen.indent += 1;
+        gen.declared.clear();
+        let top_level = module
+            .stmts
+            .iter()
+            .filter(|s| !matches!(s.node, AstStmt::FnDef(_) | AstStmt::StructDef { .. }))
+            .collect::<Vec<_>>();
+        gen.declare_locals(top_level.iter().map(|s| &s.node))?;
+        for (i, stmt) in top_level.iter().enumerate() {
+            let is_tail = i == top_level.len() - 1 && !module.has_semi;
+            gen.stmt(stmt, is_tail, Tail::Discard)?;
+        }
+        gen.line("return 0;");
+        gen.indent -= 1;
+        gen.line("}");
+
+        Ok(gen.out)
+    }
+
+    fn line(&mut self, line: &str) {
+        if !line.is_empty() {
+            self.out.push_str(&"    ".repeat(self.indent));
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    fn struct_def(&mut self, name: &AstLiteral<'_>, fields: &[AstStructField<'_>]) -> Result<()> {
+        self.line("");
+        self.line("typedef struct {");
+        self.indent += 1;
+        for field in fields {
+            if field.default.is_some() {
+                return unsupported("default field values");
+            }
+            let field = format!("{} {};", c_type(&field.type_annotation)?, field.name);
+            self.line(&field);
+        }
+        self.indent -= 1;
+        self.line(&format!("}} {};", name));
+        self.structs.insert(name.to_string());
+        Ok(())
+    }
+
+    fn fn_signature(&self, fn_def: &FnDef<'_>) -> Result<String> {
+        if !fn_def.type_params.is_empty() {
+            return unsupported("generic functions");
+        }
+
+        let params = fn_def
+            .args
+            .iter()
+            .map(|arg| match arg {
+                FnParam { default: Some(_), .. } => unsupported("default parameter values"),
+                FnParam {
+                    ident: AstLiteral::TypedIdent { name, type_annotation },
+                    ..
+                } => Ok(format!("{} {}", c_type(type_annotation)?, name)),
+                _ => unsupported("untyped parameters"),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+
+        Ok(format!("{} {}({})", c_type(&fn_def.return_type)?, fn_def.name, params))
+    }
+
+    fn fn_def(&mut self, fn_def: &FnDef<'_>) -> Result<()> {
+        let signature = self.fn_signature(fn_def)?;
+        self.line(&format!("{} {{", signature));
+        self.indent += 1;
+
+        self.declared = fn_def.args.iter().map(|a| name_of(&a.ident).to_string()).collect();
+        self.declare_locals(fn_def.body.stmts())?;
+        let is_void = matches!(
+            fn_def.return_type,
+            TypeAnnotation::Unit | TypeAnnotation::Dynamic("void")
+        );
+        let tail = if is_void { Tail::Discard } else { Tail::Return };
+        self.block(&fn_def.body, tail)?;
+
+        self.indent -= 1;
+        self.line("}");
+        Ok(())
+    }
+
+    // Declares every name assigned in `stmts` up front, so a local first
+    // assigned inside a branch is still in scope after it
+    fn declare_locals<'a, 'src: 'a>(&mut self, stmts: impl IntoIterator<Item = &'a AstStmt<'src>>) -> Result<()> {
+        let mut assignments = Vec::new();
+        collect_assignments(stmts, &mut assignments);
+        let mut bound = self.declared.clone();
+        for (target, value) in assignments {
+            let name = name_of(target).to_string();
+            // A `let` declares its name where it stands
+            let Some(value) = value.filter(|_| !bound.contains(&name)) else {
+                bound.insert(name);
+                continue;
+            };
+            let ty = match (target, value) {
+                (AstLiteral::TypedIdent { type_annotation, .. }, _) => c_type(type_annotation)?,
+                // `__typeof__` a string literal is an array, which can't be assigned to
+                (_, AstExpr::LitExpr(AstLiteral::Str(_))) => "const char*".to_string(),
+                _ => format!("__typeof__({})", self.expr(value)?),
+            };
+            self.line(&format!("{} {};", ty, name));
+            self.declared.insert(name.clone());
+            bound.insert(name);
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, block: &AstBlock<'_>, tail: Tail) -> Result<()> {
+        for (i, stmt) in block.stmts.iter().enumerate() {
+            let is_tail = i == block.stmts.len() - 1 && !block.has_semi;
+            self.stmt(stmt, is_tail, tail)?;
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &AstStmt<'_>, is_tail: bool, tail: Tail) -> Result<()> {
+        match stmt {
+            AstStmt::Expr { expr, .. } if is_tail => self.tail_expr(expr, tail),
+            AstStmt::Expr { expr, .. } => self.tail_expr(expr, Tail::Discard),
+            AstStmt::Return(expr) => self.tail_expr(expr, Tail::Return),
+            AstStmt::Assignment { target, assigned } => {
+                let value = self.expr(assigned)?;
+                let target = match target {
+                    // Declared up front by `declare_locals`
+                    AstExpr::LitExpr(AstLiteral::TypedIdent { name, .. }) => name.to_string(),
+                    _ => self.expr(target)?,
+                };
+                self.line(&format!("{} = {};", target, value));
+                Ok(())
+            }
+            AstStmt::Let {
+                name, constant, value, ..
+            } => {
+                let value = self.expr(value)?;
+                let ty = match name {
+                    AstLiteral::TypedIdent { type_annotation, .. } => c_type(type_annotation)?,
+                    _ => "__auto_type".to_string(),
+                };
+                let name = name_of(name).to_string();
+                let qualifier = if *constant { "const " } else { "" };
+                self.line(&format!("{}{} {} = {};", qualifier, ty, name, value));
+                self.declared.insert(name);
+                Ok(())
+            }
+            AstStmt::FnDef(_) => unsupported("nested function definitions"),
+            AstStmt::StructDef { .. } => unsupported("nested struct definitions"),
+            AstStmt::Match { .. } => unsupported("match statements"),
+        }
+    }
+
+    fn tail_expr(&mut self, expr: &AstExpr<'_>, tail: Tail) -> Result<()> {
+        match expr {
+            AstExpr::ConditionalExpr(cond) => self.conditional(cond, tail),
+            AstExpr::BlockExpr(block) => self.block(block, tail),
+            _ => {
+                let expr = self.expr(expr)?;
+                match tail {
+                    Tail::Return => self.line(&format!("return {};", expr)),
+                    Tail::Discard => self.line(&format!("{};", expr)),
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn conditional(&mut self, cond: &AstConditional<'_>, tail: Tail) -> Result<()> {
+        let condition = self.expr(&cond.condition)?;
+        self.line(&format!("if ({}) {{", condition));
+        self.indent += 1;
+        self.block(&cond.if_block, tail)?;
+        self.indent -= 1;
+
+        for (condition, block) in &cond.elif_branches {
+            let condition = self.expr(condition)?;
+            self.line(&format!("}} else if ({}) {{", condition));
+            self.indent += 1;
+            self.block(block, tail)?;
+            self.indent -= 1;
+        }
+
+        if let Some(else_block) = &cond.else_block {
+            self.line("} else {");
+            self.indent += 1;
+            self.tail_expr(else_block, tail)?;
+            self.indent -= 1;
+        }
+        self.line("}");
+        Ok(())
+    }
+
+    // Struct construction becomes a compound literal
+    fn struct_literal(&self, name: &str, fields: &[CallArg<'_>]) -> Result<String> {
+        if fields.iter().any(|a| a.spread.is_some()) {
+            return unsupported("spread arguments");
+        }
+        let fields = fields
+            .iter()
+            .map(|a| match &a.name {
+                Some(name) => Ok(format!(".{} = {}", self.expr(name)?, self.expr(&a.expr)?)),
+                None => self.expr(&a.expr),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!("({}){{{}}}", name, fields.join(", ")))
+    }
+
+    fn expr(&self, expr: &AstExpr<'_>) -> Result<String> {
+        Ok(match expr {
+            AstExpr::BinExpr(bin) => {
+                let op = match bin.op {
+                    Operator::And => "&&",
+                    Operator::Or => "||",
+                    // `/` and `%` truncate in C
+                    Operator::FloorDiv => return unsupported("floor division"),
+                    Operator::Mod => return unsupported("floored remainder"),
+                    ref op => op.symbol(),
+                };
+                format!("({} {} {})", self.expr(&bin.l)?, op, self.expr(&bin.r)?)
+            }
+            AstExpr::UnaryExpr(unary) => format!("({}{})", unary.op, self.expr(&unary.expr)?),
+            AstExpr::NotExpr(expr) => format!("(!{})", self.expr(expr)?),
+            AstExpr::LitExpr(lit) => match lit {
+                // Everything is an int64_t, so the suffix has nothing to add
+                AstLiteral::Int(Token::IntLiteral { value, .. }) => value.to_string(),
+                AstLiteral::Str(Token::StrLiteral { value: s, .. }) => c_string(s),
+                AstLiteral::Ident(Spanned {
+                    node: Token::Ident("true"),
+                    ..
+                }) => "1".to_string(),
+                AstLiteral::Ident(Spanned {
+                    node: Token::Ident("false"),
+                    ..
+                }) => "0".to_string(),
+                AstLiteral::Ident(name) => name.to_string(),
+                _ => return unsupported("type annotations inside expressions"),
+            },
+            // Only value-producing conditionals with single-expression
+            // branches map onto C's ternary
+            AstExpr::ConditionalExpr(cond) => {
+                let then = cond.if_block.single_expr();
+                let otherwise = match cond.else_block.as_deref() {
+                    Some(AstExpr::BlockExpr(block)) => block.single_expr(),
+                    // Chained conditionals and a ternary's fallback
+                    Some(e) => Some(e),
+                    None => None,
+                };
+                let (Some(then), Some(otherwise)) = (then, otherwise) else {
+                    return unsupported("conditionals with statements used as values");
+                };
+                // `elif`s nest into the false side, innermost last
+                let mut otherwise = self.expr(otherwise)?;
+                for (condition, block) in cond.elif_branches.iter().rev() {
+                    let Some(then) = block.single_expr() else {
+                        return unsupported("conditionals with statements used as values");
+                    };
+                    otherwise = format!("({} ? {} : {})", self.expr(condition)?, self.expr(then)?, otherwise);
+                }
+                format!(
+                    "({} ? {} : {})",
+                    self.expr(&cond.condition)?,
+                    self.expr(then)?,
+                    otherwise
+                )
+            }
+            AstExpr::BlockExpr(_) => return unsupported("block expressions"),
+            AstExpr::FnDefExpr(_) => return unsupported("function definitions used as values"),
+            AstExpr::IndexExpr(_) | AstExpr::SliceExpr(_) => return unsupported("indexing"),
+            AstExpr::CallExpr(AstCallExpr { optional: true, .. })
+            | AstExpr::AttrAccessExpr(AttrAccess { optional: true, .. }) => return unsupported("optional chaining"),
+            AstExpr::CallExpr(call) => {
+                let called = self.expr(&call.called_expr)?;
+                if self.structs.contains(&called) {
+                    self.struct_literal(&called, &call.args)?
+                } else {
+                    let args = call
+                        .args
+                        .iter()
+                        .map(|a| match (&a.name, a.spread) {
+                            (Some(_), _) => unsupported("named arguments to functions"),
+                            (None, Some(_)) => unsupported("spread arguments"),
+                            (None, None) => self.expr(&a.expr),
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    format!("{}({})", called, args.join(", "))
+                }
+            }
+            AstExpr::StructInitExpr(si) => self.struct_literal(&si.name.to_string(), &si.fields)?,
+            AstExpr::AttrAccessExpr(aa) => format!("{}.{}", self.expr(&aa.expr)?, aa.attribute),
+        })
+    }
+}
+
+// Every name bound in `stmts`, including in nested branches and blocks, in
+// source order. Names bound by `let` come without a value.
+fn collect_assignments<'a, 'src: 'a>(
+    stmts: impl IntoIterator<Item = &'a AstStmt<'src>>,
+    out: &mut Vec<(&'a AstLiteral<'src>, Option<&'a AstExpr<'src>>)>,
+) {
+    for stmt in stmts {
+        match stmt {
+            AstStmt::Assignment {
+                target: AstExpr::LitExpr(lit @ (AstLiteral::Ident(_) | AstLiteral::TypedIdent { .. })),
+                assigned,
+            } => out.push((lit, Some(assigned))),
+            AstStmt::Let { name, .. } => out.push((name, None)),
+            AstStmt::Expr { expr, .. } | AstStmt::Return(expr) => collect_expr_assignments(expr, out),
+            _ => {}
+        }
+    }
+}
+
+fn collect_expr_assignments<'a, 'src: 'a>(
+    expr: &'a AstExpr<'src>,
+    out: &mut Vec<(&'a AstLiteral<'src>, Option<&'a AstExpr<'src>>)>,
+) {
+    match expr {
+        AstExpr::ConditionalExpr(cond) => {
+            collect_assignments(cond.if_block.stmts(), out);
+            for (_, block) in &cond.elif_branches {
+                collect_assignments(block.stmts(), out);
+            }
+            if let Some(else_block) = &cond.else_block {
+                collect_expr_assignments(else_block, out);
+            }
+        }
+        AstExpr::BlockExpr(block) => collect_assignments(block.stmts(), out),
+        _ => {}
+    }
+}
+
+fn name_of<'a>(lit: &'a AstLiteral<'_>) -> &'a Token<'a> {
+    match lit {
+        AstLiteral::TypedIdent { name, .. } => &name.node,
+        AstLiteral::Int(t) | AstLiteral::Str(t) => t,
+        AstLiteral::Ident(t) => &t.node,
+    }
+}
+
+// Toki strings are taken as written, so only what C would misread is escaped
+fn c_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn c_type(type_annotation: &TypeAnnotation<'_>) -> Result<String> {
+    Ok(match type_annotation {
+        TypeAnnotation::Unit => "void".to_string(),
+        TypeAnnotation::Int | TypeAnnotation::Dynamic("int") => "int64_t".to_string(),
+        TypeAnnotation::Str | TypeAnnotation::Dynamic("str") => "const char*".to_string(),
+        TypeAnnotation::Bool | TypeAnnotation::Dynamic("bool") => "_Bool".to_string(),
+        // C has no immutable-by-default, so `mut` is a no-op
+        TypeAnnotation::Mut(inner) => c_type(inner)?,
+        TypeAnnotation::Dynamic(name) => name.to_string(),
+        TypeAnnotation::Union(..) | TypeAnnotation::Parameterized { .. } => {
+            return unsupported("union and parameterized types")
+        }
+    })
+}
+
+/// Pipes `c_src` through the system C compiler, returning whether it compiled
+pub fn check_with_cc(c_src: &str) -> std::io::Result<bool> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut cc = Command::new("cc")
+        .args(["-o", "/dev/null", "-x", "c", "-"])
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    cc.stdin.take().expect("Piped above").write_all(c_src.as_bytes())?;
+    Ok(cc.wait()?.success())
+}
+
+#[cfg(test)]
+fn generate_src(src: &str) -> Result<String> {
+    let block = crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    CCodegen::generate(&block)
+}
+
+#[cfg(test)]
+fn assert_compiles(c_src: &str) {
+    // Without a `cc` to run there's nothing to check
+    if let Ok(compiled) = check_with_cc(c_src) {
+        assert!(compiled, "Generated C failed to compile:\n{}", c_src);
+    }
+}
+
+#[test]
+fn test_generate_fn_and_main() {
+    let src = "def add(a: int, b: int) -> int:\n    a + b\n\nx: int = add(1, 2);\n";
+    let c_src = generate_src(src).unwrap();
+
+    assert!(c_src.starts_with("#include <stdint.h>\n"));
+    assert!(c_src.contains("int64_t add(int64_t a, int64_t b) {\n    return (a + b);\n}"));
+    assert!(c_src.contains("int main(void) {\n    int64_t x;\n    x = add(1, 2);\n    return 0;\n}"));
+    assert_compiles(&c_src);
+}
+
+#[test]
+fn test_generate_fn_without_return_type() {
+    let src = "def bump(a: int):\n    b = a + 1;\n\nbump(1);\n";
+    let c_src = generate_src(src).unwrap();
+
+    assert!(c_src.contains("void bump(int64_t a) {\n"), "{}", c_src);
+    assert_compiles(&c_src);
+}
+
+#[test]
+fn test_generate_conditional_returns() {
+    let src = "def pick(flag: bool, a: int) -> int:\n    b = a * 2;\n    if flag:\n        a\n    else:\n        b\n\npick(true, 3);\n";
+    let c_src = generate_src(src).unwrap();
+
+    assert!(c_src.contains("    if (flag) {\n        return a;\n    } else {\n        return b;\n    }\n"));
+    assert_compiles(&c_src);
+}
+
+#[test]
+fn test_generate_ternary() {
+    let src = "def pick(flag: bool, a: int) -> int:\n    b = a if flag else 0;\n    b\n\npick(true, 3);\n";
+    let c_src = generate_src(src).unwrap();
+
+    assert!(c_src.contains("b = (flag ? a : 0);"), "{}", c_src);
+    assert_compiles(&c_src);
+}
+
+#[test]
+fn test_generate_local_assigned_in_branches() {
+    let src =
+        "def pick(flag: bool) -> int:\n    if flag:\n        x = 1;\n    else:\n        x = 2;\n    x\n\npick(true);\n";
+    let c_src = generate_src(src).unwrap();
+
+    assert!(
+        c_src.contains("int64_t pick(_Bool flag) {\n    __typeof__(1) x;\n    if (flag) {\n        x = 1;\n"),
+        "{}",
+        c_src
+    );
+    assert_compiles(&c_src);
+}
+
+#[test]
+fn test_generate_elif() {
+    let src = "def sign(a: int) -> int:\n    if a == 0:\n        0\n    elif a == 1:\n        1\n    else:\n        2\n\nsign(1);\n";
+    let c_src = generate_src(src).unwrap();
+
+    assert!(
+        c_src.contains("    } else if ((a == 1)) {\n        return 1;\n    } else {\n"),
+        "{}",
+        c_src
+    );
+    assert_compiles(&c_src);
+}
+
+#[test]
+fn test_generate_struct() {
+    let src = "struct Point:\n    x: int\n    y: int\n\np: mut Point = Point(x=1, y=2);\np.x = p.y;\n";
+    let c_src = generate_src(src).unwrap();
+
+    assert!(c_src.contains("typedef struct {\n    int64_t x;\n    int64_t y;\n} Point;"));
+    assert!(
+        c_src.contains("    Point p;\n    p = (Point){.x = 1, .y = 2};\n"),
+        "{}",
+        c_src
+    );
+    assert_compiles(&c_src);
+}
+
+#[test]
+fn test_generate_unsupported() {
+    let src = "def f[T](x: T) -> T:\n    x\n";
+    assert_eq!(
+        generate_src(src),
+        Err(CodegenErr::Unsupported("generic functions".to_string()))
+    );
+    assert_eq!(
+        generate_src("x = 0 - 7;\nx % 2;\n"),
+        Err(CodegenErr::Unsupported("floored remainder".to_string()))
+    );
+}
+
+#[test]
+fn test_generate_let_and_const() {
+    let c_src = generate_src("const N = 3;\nlet x: int = N;\nx = x + 1;\n").unwrap();
+
+    assert!(
+        c_src.contains("    const __auto_type N = 3;\n    int64_t x = N;\n    x = (x + 1);\n"),
+        "{}",
+        c_src
+    );
+    assert_compiles(&c_src);
+}
+
+#[test]
+fn test_generate_escaped_string() {
+    let src = "s = \"\"\"say \"hi\" to C:\\\nbye\"\"\";\n";
+    let c_src = generate_src(src).unwrap();
+
+    assert!(c_src.contains(r#"s = "say \"hi\" to C:\\\nbye";"#), "{}", c_src);
+    assert_compiles(&c_src);
+}
@@ -0,0 +1,626 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{
+    AstBlock, AstCallExpr, AstConditional, AstExpr, AstLiteral, AstStmt, AstStructField, AttrAccess, CallArg, FnDef,
+    TypeAnnotation,
+};
+use crate::codegen::{CodegenErr, Result};
+use crate::span::Spanned;
+use crate::token::{Operator, Token};
+
+// Where the value of a block's tail expression goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tail {
+    Value,
+    Discard,
+}
+
+// Leave address 0 unused so it can act as a null pointer
+const DATA_START: usize = 8;
+
+/// Emits a WebAssembly text format module for a whole program. Every value is
+/// an `i64`: booleans are 0/1, and strings and structs are pointers into
+/// linear memory. String literals live in data segments and structs are
+/// allocated at runtime by a bump allocator. Top-level statements go into an
+/// exported `main` function.
+#[derive(Default)]
+pub struct WasmCodegen {
+    out: String,
+    indent: usize,
+    // Field names in declaration order, each field is 8 bytes
+    structs: HashMap<String, Vec<String>>,
+    void_fns: HashSet<String>,
+    strings: Vec<(usize, String)>,
+    data_end: usize,
+    params: HashSet<String>,
+    locals: Vec<String>,
+    // Locals annotated with a struct type, so field accesses know their offset
+    local_structs: HashMap<String, String>,
+}
+
+fn unsupported<T>(what: &str) -> Result<T> {
+    Err(CodegenErr::Unsupported(what.to_string()))
+}
+
+impl WasmCodegen {
+    pub fn generate(module: &AstBlock<'_>) -> Result<String> {
+        let mut gen = WasmCodegen {
+            indent: 1,
+            data_end: DATA_START,
+            ..Default::default()
+        };
+
+        for stmt in &module.stmts {
+            match &stmt.node {
+                AstStmt::StructDef {
+                    name, fields, methods, ..
+                } => {
+                    if !methods.is_empty() {
+                        return unsupported("struct methods");
+                    }
+                    gen.struct_def(name, fields)?;
+                }
+                AstStmt::FnDef(fn_def) if is_void(fn_def) => {
+                    gen.void_fns.insert(fn_def.name.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        for stmt in &module.stmts {
+            if let AstStmt::FnDef(fn_def) = &stmt.node {
+                gen.line("");
+                gen.fn_def(fn_def)?;
+            }
+        }
+
+        let top_level = module
+            .stmts
+            .iter()
+            .filter(|s| !matches!(s.node, AstStmt::FnDef(_) | AstStmt::StructDef { .. }))
+            .collect::<Vec<_>>();
+        gen.params.clear();
+        gen.local_structs.clear();
+        let body = top_level
+            .iter()
+            .map(|stmt| gen.stmt(stmt, Tail::Discard))
+            .collect::<Result<Vec<_>>>()?;
+        gen.line("");
+        gen.func("(func $main (export \"main\")", body);
+
+        Ok(gen.module())
+    }
+
+    // The header depends on how much static data the functions used, so it's
+    // assembled last
+    fn module(self) -> String {
+        let heap_start = self.data_end.div_ceil(8) * 8;
+        let mut module = String::from("(module\n");
+        module.push_str("  (memory (export \"memory\") 1)\n");
+        module.push_str(&format!("  (global $heap (mut i32) (i32.const {}))\n", heap_start));
+        for (offset, s) in &self.strings {
+            module.push_str(&format!("  (data (i32.const {}) \"{}\\00\")\n", offset, escape(s)));
+        }
+        module.push('\n');
+        module.push_str("  (func $alloc (param $size i32) (result i32)\n");
+        module.push_str("    (local $ptr i32)\n");
+        module.push_str("    (local.set $ptr (global.get $heap))\n");
+        module.push_str("    (global.set $heap (i32.add (global.get $heap) (local.get $size)))\n");
+        module.push_str("    (local.get $ptr)\n");
+        module.push_str("  )\n");
+        module.push_str(&self.out);
+        module.push_str(")\n");
+        module
+    }
+
+    fn line(&mut self, line: &str) {
+        if !line.is_empty() {
+            self.out.push_str(&"  ".repeat(self.indent));
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    // Locals have to be declared before the body, so the body is generated
+    // first and `self.locals` collected along the way
+    fn func(&mut self, header: &str, body: Vec<String>) {
+        self.line(header);
+        self.indent += 1;
+        for local in std::mem::take(&mut self.locals) {
+            self.line(&format!("(local ${} i64)", local));
+        }
+        for instr in body {
+            self.line(&instr);
+        }
+        self.indent -= 1;
+        self.line(")");
+    }
+
+    // Each struct gets a `$Name.new` constructor taking its fields in order
+    fn struct_def(&mut self, name: &AstLiteral<'_>, fields: &[AstStructField<'_>]) -> Result<()> {
+        if fields.iter().any(|f| f.default.is_some()) {
+            return unsupported("default field values");
+        }
+        let fields = fields.iter().map(|f| f.name.to_string()).collect::<Vec<_>>();
+        let params = fields
+            .iter()
+            .map(|f| format!("(param ${} i64)", f))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        self.line("");
+        self.line(&format!("(func ${}.new {} (result i64)", name, params));
+        self.indent += 1;
+        self.line("(local $ptr i32)");
+        self.line(&format!(
+            "(local.set $ptr (call $alloc (i32.const {})))",
+            fields.len() * 8
+        ));
+        for (i, field) in fields.iter().enumerate() {
+            self.line(&format!(
+                "(i64.store offset={} (local.get $ptr) (local.get ${}))",
+                i * 8,
+                field
+            ));
+        }
+        self.line("(i64.extend_i32_u (local.get $ptr))");
+        self.indent -= 1;
+        self.line(")");
+
+        self.structs.insert(name.to_string(), fields);
+        Ok(())
+    }
+
+    fn fn_def(&mut self, fn_def: &FnDef<'_>) -> Result<()> {
+        self.params.clear();
+        self.local_structs.clear();
+        for arg in &fn_def.args {
+            if arg.default.is_some() {
+                return unsupported("default parameter values");
+            }
+            let name = name_of(&arg.ident).to_string();
+            if let AstLiteral::TypedIdent { type_annotation, .. } = &arg.ident {
+                self.note_struct_type(&name, type_annotation);
+            }
+            self.params.insert(name);
+        }
+
+        let tail = if is_void(fn_def) { Tail::Discard } else { Tail::Value };
+        let body = self.block(&fn_def.body, tail)?;
+
+        let mut header = format!("(func ${} (export \"{}\")", fn_def.name, fn_def.name);
+        for arg in &fn_def.args {
+            header.push_str(&format!(" (param ${} i64)", name_of(&arg.ident)));
+        }
+        if tail == Tail::Value {
+            header.push_str(" (result i64)");
+        }
+        self.func(&header, body);
+        Ok(())
+    }
+
+    fn note_struct_type(&mut self, name: &str, type_annotation: &TypeAnnotation<'_>) {
+        match type_annotation {
+            TypeAnnotation::Mut(inner) => self.note_struct_type(name, inner),
+            TypeAnnotation::Dynamic(ty) if self.structs.contains_key(*ty) => {
+                self.local_structs.insert(name.to_string(), ty.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn declare(&mut self, name: &str) {
+        if !self.params.contains(name) && !self.locals.iter().any(|l| l == name) {
+            self.locals.push(name.to_string());
+        }
+    }
+
+    fn block(&mut self, block: &AstBlock<'_>, tail: Tail) -> Result<Vec<String>> {
+        let has_tail = !block.has_semi && matches!(block.last_stmt(), Some(AstStmt::Expr { .. }));
+        let ends_in_return = matches!(block.last_stmt(), Some(AstStmt::Return(_)));
+        if tail == Tail::Value && !has_tail && !ends_in_return {
+            return unsupported("blocks without a value where one is expected");
+        }
+
+        block
+            .stmts
+            .iter()
+            .enumerate()
+            .map(|(i, stmt)| {
+                let is_tail = i == block.stmts.len() - 1 && has_tail;
+                self.stmt(stmt, if is_tail { tail } else { Tail::Discard })
+            })
+            .collect()
+    }
+
+    fn stmt(&mut self, stmt: &AstStmt<'_>, tail: Tail) -> Result<String> {
+        match stmt {
+            AstStmt::Expr { expr, .. } => self.tail_expr(expr, tail),
+            AstStmt::Return(expr) => Ok(format!("(return {})", self.expr(expr)?)),
+            AstStmt::Assignment { target, assigned } => {
+                let value = self.expr(assigned)?;
+                match target {
+                    AstExpr::LitExpr(AstLiteral::TypedIdent { name, type_annotation }) => {
+                        let name = name.to_string();
+                        self.note_struct_type(&name, type_annotation);
+                        self.declare(&name);
+                        Ok(format!("(local.set ${} {})", name, value))
+                    }
+                    AstExpr::LitExpr(AstLiteral::Ident(name)) => {
+                        let name = name.to_string();
+                        self.declare(&name);
+                        Ok(format!("(local.set ${} {})", name, value))
+                    }
+                    AstExpr::AttrAccessExpr(aa) if !aa.optional => {
+                        let (ptr, offset) = self.field_address(&aa.expr, &aa.attribute.to_string())?;
+                        Ok(format!("(i64.store offset={} {} {})", offset, ptr, value))
+                    }
+                    _ => unsupported("assignments to this kind of target"),
+                }
+            }
+            AstStmt::Let { name, value, .. } => {
+                let value = self.expr(value)?;
+                if let AstLiteral::TypedIdent { name, type_annotation } = name {
+                    self.note_struct_type(&name.to_string(), type_annotation);
+                }
+                let name = name_of(name).to_string();
+                self.declare(&name);
+                Ok(format!("(local.set ${} {})", name, value))
+            }
+            AstStmt::FnDef(_) => unsupported("nested function definitions"),
+            AstStmt::StructDef { .. } => unsupported("nested struct definitions"),
+            AstStmt::Match { .. } => unsupported("match statements"),
+        }
+    }
+
+    fn tail_expr(&mut self, expr: &AstExpr<'_>, tail: Tail) -> Result<String> {
+        match expr {
+            AstExpr::ConditionalExpr(cond) => self.conditional(cond, tail),
+            AstExpr::BlockExpr(block) => Ok(self.block(block, tail)?.join(" ")),
+            _ => {
+                let instr = self.expr(expr)?;
+                Ok(match tail {
+                    Tail::Discard if self.has_value(expr) => format!("(drop {})", instr),
+                    _ => instr,
+                })
+            }
+        }
+    }
+
+    fn has_value(&self, expr: &AstExpr<'_>) -> bool {
+        match expr {
+            AstExpr::CallExpr(call) => !self.void_fns.contains(&call.called_expr.to_string()),
+            _ => true,
+        }
+    }
+
+    fn conditional(&mut self, cond: &AstConditional<'_>, tail: Tail) -> Result<String> {
+        let branches = std::iter::once((cond.condition.as_ref(), &cond.if_block))
+            .chain(cond.elif_branches.iter().map(|(c, b)| (c, b)))
+            .collect::<Vec<_>>();
+        self.branches(&branches, cond.else_block.as_deref(), tail)
+    }
+
+    // Each `elif` becomes an `if` nested in the previous branch's `else`
+    fn branches(
+        &mut self,
+        branches: &[(&AstExpr<'_>, &AstBlock<'_>)],
+        else_block: Option<&AstExpr<'_>>,
+        tail: Tail,
+    ) -> Result<String> {
+        let result = match tail {
+            Tail::Value => " (result i64)",
+            Tail::Discard => "",
+        };
+        let [(condition, if_block), rest @ ..] = branches else {
+            unreachable!("A conditional always has its `if` branch");
+        };
+        let condition = self.expr(condition)?;
+        let then = self.block(if_block, tail)?.join(" ");
+        let otherwise = match else_block {
+            _ if !rest.is_empty() => format!(" (else {})", self.branches(rest, else_block, tail)?),
+            Some(else_block) => format!(" (else {})", self.tail_expr(else_block, tail)?),
+            None if tail == Tail::Value => return unsupported("conditionals without an else used as values"),
+            None => String::new(),
+        };
+
+        Ok(format!(
+            "(if{} (i64.ne {} (i64.const 0)) (then {}){})",
+            result, condition, then, otherwise
+        ))
+    }
+
+    fn field_address(&self, expr: &AstExpr<'_>, field: &str) -> Result<(String, usize)> {
+        let AstExpr::LitExpr(AstLiteral::Ident(name)) = expr else {
+            return unsupported("field access on anything but a local");
+        };
+        let Some(fields) = self.local_structs.get(&name.to_string()).map(|s| &self.structs[s]) else {
+            return unsupported("field access on locals without a struct type annotation");
+        };
+        let Some(index) = fields.iter().position(|f| f == field) else {
+            return unsupported("access to undeclared struct fields");
+        };
+        Ok((format!("(i32.wrap_i64 (local.get ${}))", name), index * 8))
+    }
+
+    fn intern(&mut self, s: &str) -> usize {
+        if let Some((offset, _)) = self.strings.iter().find(|(_, existing)| existing == s) {
+            return *offset;
+        }
+        let offset = self.data_end;
+        // Null terminated, like C
+        self.data_end += s.len() + 1;
+        self.strings.push((offset, s.to_string()));
+        offset
+    }
+
+    // Named arguments are matched to fields, positional ones fill in order
+    fn struct_init(&mut self, name: &str, args: &[CallArg<'_>]) -> Result<String> {
+        let fields = self.structs[name].clone();
+        let mut values = vec![None; fields.len()];
+        for (i, arg) in args.iter().enumerate() {
+            if arg.spread.is_some() {
+                return unsupported("spread arguments");
+            }
+            let index = match &arg.name {
+                Some(field) => fields.iter().position(|f| *f == field.to_string()),
+                None => (i < fields.len()).then_some(i),
+            };
+            let Some(index) = index else {
+                return unsupported("initializing undeclared struct fields");
+            };
+            values[index] = Some(self.expr(&arg.expr)?);
+        }
+
+        let Some(values) = values.into_iter().collect::<Option<Vec<_>>>() else {
+            return unsupported("struct initializations with missing fields");
+        };
+        Ok(format!("(call ${}.new {})", name, values.join(" ")))
+    }
+
+    fn expr(&mut self, expr: &AstExpr<'_>) -> Result<String> {
+        Ok(match expr {
+            AstExpr::BinExpr(bin) => {
+                let (l, r) = (self.expr(&bin.l)?, self.expr(&bin.r)?);
+                match bin.op {
+                    Operator::Add => format!("(i64.add {} {})", l, r),
+                    Operator::Sub => format!("(i64.sub {} {})", l, r),
+                    Operator::Mul => format!("(i64.mul {} {})", l, r),
+                    Operator::Div => format!("(i64.div_s {} {})", l, r),
+                    // `rem_s` truncates like `div_s`
+                    Operator::FloorDiv => return unsupported("floor division"),
+                    Operator::Mod => return unsupported("floored remainder"),
+                    // Short circuits like C's `&&` and `||`, and any non-zero is true
+                    Operator::And => format!(
+                        "(if (result i64) (i64.eqz {}) (then (i64.const 0)) (else {}))",
+                        l,
+                        truthy(&r)
+                    ),
+                    Operator::Or => format!(
+                        "(if (result i64) (i64.eqz {}) (then {}) (else (i64.const 1)))",
+                        l,
+                        truthy(&r)
+                    ),
+                    Operator::Equals => format!("(i64.extend_i32_u (i64.eq {} {}))", l, r),
+                    Operator::NotEquals => format!("(i64.extend_i32_u (i64.ne {} {}))", l, r),
+                }
+            }
+            AstExpr::UnaryExpr(unary) => match unary.op {
+                Operator::Sub => format!("(i64.sub (i64.const 0) {})", self.expr(&unary.expr)?),
+                _ => return unsupported("unary operators other than `-`"),
+            },
+            AstExpr::NotExpr(expr) => format!("(i64.extend_i32_u (i64.eqz {}))", self.expr(expr)?),
+            AstExpr::LitExpr(lit) => match lit {
+                AstLiteral::Int(Token::IntLiteral { value, .. }) => format!("(i64.const {})", value),
+                AstLiteral::Str(Token::StrLiteral { value: s, .. }) => format!("(i64.const {})", self.intern(s)),
+                AstLiteral::Ident(Spanned {
+                    node: Token::Ident("true"),
+                    ..
+                }) => "(i64.const 1)".to_string(),
+                AstLiteral::Ident(Spanned {
+                    node: Token::Ident("false"),
+                    ..
+                }) => "(i64.const 0)".to_string(),
+                AstLiteral::Ident(name) => format!("(local.get ${})", name),
+                _ => return unsupported("type annotations inside expressions"),
+            },
+            AstExpr::ConditionalExpr(cond) => self.conditional(cond, Tail::Value)?,
+            AstExpr::BlockExpr(_) => return unsupported("block expressions"),
+            AstExpr::FnDefExpr(_) => return unsupported("function definitions used as values"),
+            AstExpr::IndexExpr(_) | AstExpr::SliceExpr(_) => return unsupported("indexing"),
+            AstExpr::CallExpr(AstCallExpr { optional: true, .. })
+            | AstExpr::AttrAccessExpr(AttrAccess { optional: true, .. }) => return unsupported("optional chaining"),
+            AstExpr::CallExpr(call) => {
+                let AstExpr::LitExpr(AstLiteral::Ident(called)) = call.called_expr.as_ref() else {
+                    return unsupported("calls to anything but a named function");
+                };
+                let called = called.to_string();
+                if self.structs.contains_key(&called) {
+                    self.struct_init(&called, &call.args)?
+                } else {
+                    let mut instr = format!("(call ${}", called);
+                    for arg in &call.args {
+                        if arg.name.is_some() {
+                            return unsupported("named arguments to functions");
+                        }
+                        if arg.spread.is_some() {
+                            return unsupported("spread arguments");
+                        }
+                        instr.push(' ');
+                        instr.push_str(&self.expr(&arg.expr)?);
+                    }
+                    instr.push(')');
+                    instr
+                }
+            }
+            AstExpr::StructInitExpr(si) => self.struct_init(&si.name.to_string(), &si.fields)?,
+            AstExpr::AttrAccessExpr(aa) => {
+                let (ptr, offset) = self.field_address(&aa.expr, &aa.attribute.to_string())?;
+                format!("(i64.load offset={} {})", offset, ptr)
+            }
+        })
+    }
+}
+
+fn is_void(fn_def: &FnDef<'_>) -> bool {
+    matches!(
+        fn_def.return_type,
+        TypeAnnotation::Unit | TypeAnnotation::Dynamic("void")
+    )
+}
+
+fn name_of<'a>(lit: &'a AstLiteral<'_>) -> &'a Token<'a> {
+    match lit {
+        AstLiteral::TypedIdent { name, .. } => &name.node,
+        AstLiteral::Int(t) | AstLiteral::Str(t) => t,
+        AstLiteral::Ident(t) => &t.node,
+    }
+}
+
+// Anything outside printable ASCII, plus the quote and backslash, is written
+// as a `\hh` byte escape
+fn escape(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'"' | b'\\' => format!("\\{:02x}", b),
+            b' '..=b'~' => (b as char).to_string(),
+            _ => format!("\\{:02x}", b),
+        })
+        .collect()
+}
+
+// An i64 as 0 or 1
+fn truthy(value: &str) -> String {
+    format!("(i64.extend_i32_u (i64.ne {} (i64.const 0)))", value)
+}
+
+/// Runs `wat` through `wat2wasm`, returning whether it validated
+pub fn check_with_wat2wasm(wat: &str) -> std::io::Result<bool> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Callers may run concurrently, e.g. the test harness
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!("toki-{}-{}.wat", std::process::id(), id));
+    std::fs::File::create(&path)?.write_all(wat.as_bytes())?;
+    let status = Command::new("wat2wasm")
+        .arg(&path)
+        .args(["-o", "/dev/null"])
+        .stderr(Stdio::inherit())
+        .status();
+    std::fs::remove_file(&path)?;
+    Ok(status?.success())
+}
+
+#[cfg(test)]
+fn generate_src(src: &str) -> Result<String> {
+    let block = crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    WasmCodegen::generate(&block)
+}
+
+// Without wat2wasm, settle for the parens balancing
+#[cfg(test)]
+fn assert_valid(wat: &str) {
+    match check_with_wat2wasm(wat) {
+        Ok(valid) => assert!(valid, "Generated wat failed to validate:\n{}", wat),
+        Err(_) => {
+            let mut depth = 0i32;
+            for c in wat.chars() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                assert!(depth >= 0, "Unbalanced parens in:\n{}", wat);
+            }
+            assert_eq!(depth, 0, "Unbalanced parens in:\n{}", wat);
+        }
+    }
+}
+
+#[test]
+fn test_generate_fn_and_main() {
+    let src = "def add(a: int, b: int) -> int:\n    a + b\n\nx: int = add(1, 2);\n";
+    let wat = generate_src(src).unwrap();
+
+    assert!(wat.starts_with("(module\n  (memory (export \"memory\") 1)\n"));
+    assert!(wat.contains(
+        "  (func $add (export \"add\") (param $a i64) (param $b i64) (result i64)\n    (i64.add (local.get $a) (local.get $b))\n  )"
+    ));
+    assert!(wat.contains(
+        "  (func $main (export \"main\")\n    (local $x i64)\n    (local.set $x (call $add (i64.const 1) (i64.const 2)))\n  )"
+    ));
+    assert_valid(&wat);
+}
+
+#[test]
+fn test_generate_conditional() {
+    let src = "def pick(flag: bool, a: int) -> int:\n    if flag == true:\n        a\n    else:\n        a * 2\n\npick(true, 3);\n";
+    let wat = generate_src(src).unwrap();
+
+    assert!(wat.contains(
+        "(if (result i64) (i64.ne (i64.extend_i32_u (i64.eq (local.get $flag) (i64.const 1))) (i64.const 0)) (then (local.get $a)) (else (i64.mul (local.get $a) (i64.const 2))))"
+    ));
+    assert!(wat.contains("(drop (call $pick (i64.const 1) (i64.const 3)))"));
+    assert_valid(&wat);
+}
+
+#[test]
+fn test_generate_condition_uses_every_bit() {
+    // Only the high half is set, which `i32.wrap_i64` would have dropped
+    let src = "def big(n: int) -> int:\n    if n:\n        1\n    else:\n        0\n\nbig(4294967296i64);\n";
+    let wat = generate_src(src).unwrap();
+
+    assert!(
+        wat.contains("(if (result i64) (i64.ne (local.get $n) (i64.const 0)) (then (i64.const 1))"),
+        "{}",
+        wat
+    );
+    assert!(wat.contains("(call $big (i64.const 4294967296))"), "{}", wat);
+    assert_valid(&wat);
+}
+
+#[test]
+fn test_generate_logical_ops() {
+    let wat = generate_src("x = 2 and 1;\ny = 0 or 4;\n").unwrap();
+
+    assert!(wat.contains(
+        "(local.set $x (if (result i64) (i64.eqz (i64.const 2)) (then (i64.const 0)) (else (i64.extend_i32_u (i64.ne (i64.const 1) (i64.const 0))))))"
+    ));
+    assert!(wat.contains(
+        "(local.set $y (if (result i64) (i64.eqz (i64.const 0)) (then (i64.extend_i32_u (i64.ne (i64.const 4) (i64.const 0)))) (else (i64.const 1))))"
+    ));
+    assert_valid(&wat);
+}
+
+#[test]
+fn test_generate_strings_and_structs() {
+    let src = "struct Point:\n    x: int\n    y: int\n\np: mut Point = Point(y=2, x=1);\np.x = p.y;\ns = \"hi\";\n";
+    let wat = generate_src(src).unwrap();
+
+    assert!(wat.contains("  (global $heap (mut i32) (i32.const 16))\n  (data (i32.const 8) \"hi\\00\")\n"));
+    assert!(wat.contains("(func $Point.new (param $x i64) (param $y i64) (result i64)"));
+    assert!(wat.contains("(local.set $p (call $Point.new (i64.const 1) (i64.const 2)))"));
+    assert!(wat.contains(
+        "(i64.store offset=0 (i32.wrap_i64 (local.get $p)) (i64.load offset=8 (i32.wrap_i64 (local.get $p))))"
+    ));
+    assert!(wat.contains("(local.set $s (i64.const 8))"));
+    assert_valid(&wat);
+}
+
+#[test]
+fn test_generate_unsupported() {
+    let src = "p = q;\np.x;\n";
+    assert_eq!(
+        generate_src(src),
+        Err(CodegenErr::Unsupported(
+            "field access on locals without a struct type annotation".to_string()
+        ))
+    );
+    assert_eq!(
+        generate_src("x = 0 - 7;\nx % 2;\n"),
+        Err(CodegenErr::Unsupported("floored remainder".to_string()))
+    );
+}
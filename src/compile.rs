@@ -0,0 +1,154 @@
+use crate::codegen::c::CCodegen;
+use crate::codegen::wasm::WasmCodegen;
+use crate::codegen::CodegenErr;
+use crate::diagnostics::{DiagnosticSink, LimitSink};
+use crate::optimize::optimize;
+use crate::parser::parse;
+use crate::resolve::resolve_struct_inits;
+use crate::source::SourceFile;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EmitKind {
+    /// The program printed back as source
+    #[default]
+    Ast,
+    Bytecode,
+    C,
+    WasmText,
+}
+
+/// Settings for the whole parse → resolve → optimize → codegen pipeline, so
+/// new flags don't have to be threaded through each stage's signature. A flag
+/// goes in here along with the stage that reads it.
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    /// How many errors to report before the rest are dropped. The parser
+    /// currently stops at its first error regardless.
+    pub max_errors: usize,
+    /// 0 emits the program as written, anything higher runs `optimize` first
+    pub optimization_level: u8,
+    pub emit: EmitKind,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            max_errors: 20,
+            optimization_level: 0,
+            emit: EmitKind::Ast,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CompileErr {
+    /// The source didn't parse. The errors themselves went to the sink.
+    Parse,
+    Codegen(CodegenErr),
+}
+
+impl std::fmt::Display for CompileErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Parse => write!(f, "Could Not Parse Source"),
+            Self::Codegen(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Compiled {
+    pub output: String,
+    pub warnings: Vec<String>,
+}
+
+pub fn compile(
+    src: &SourceFile,
+    options: &CompileOptions,
+    sink: &mut dyn DiagnosticSink,
+) -> Result<Compiled, CompileErr> {
+    let sink = &mut LimitSink::new(sink, options.max_errors);
+    let parsed = parse(src.lexer(), sink).ok_or(CompileErr::Parse)?;
+    let block = resolve_struct_inits(parsed);
+
+    let (block, dead_code) = optimize(block, options);
+    let warnings = dead_code.iter().map(|w| w.to_string()).collect();
+
+    let output = match options.emit {
+        EmitKind::Ast => block.to_string(),
+        EmitKind::Bytecode => {
+            return Err(CompileErr::Codegen(CodegenErr::Unsupported(
+                "bytecode output".to_string(),
+            )))
+        }
+        EmitKind::C => CCodegen::generate(&block).map_err(CompileErr::Codegen)?,
+        EmitKind::WasmText => WasmCodegen::generate(&block).map_err(CompileErr::Codegen)?,
+    };
+
+    Ok(Compiled { output, warnings })
+}
+
+#[cfg(test)]
+fn compile_src(src: &str, options: CompileOptions) -> Result<Compiled, CompileErr> {
+    let mut sink = crate::diagnostics::VecSink::default();
+    compile(&SourceFile::new("test.toki", src.to_string()), &options, &mut sink)
+}
+
+#[test]
+fn test_compile_emit_kinds() {
+    let src = "def add(a: int, b: int) -> int:\n    a + b\n\nadd(1, 2);\n";
+    let emit = |emit| {
+        compile_src(
+            src,
+            CompileOptions {
+                emit,
+                ..CompileOptions::default()
+            },
+        )
+    };
+
+    assert!(emit(EmitKind::Ast)
+        .unwrap()
+        .output
+        .starts_with("def add(a: int, b: int) -> int:"));
+    assert!(emit(EmitKind::C).unwrap().output.starts_with("#include"));
+    assert!(emit(EmitKind::WasmText).unwrap().output.starts_with("(module"));
+    assert_eq!(
+        emit(EmitKind::Bytecode),
+        Err(CompileErr::Codegen(CodegenErr::Unsupported(
+            "bytecode output".to_string()
+        )))
+    );
+}
+
+#[test]
+fn test_compile_optimization_level() {
+    let src = "x = 2 * 3;\nreturn x;\ny = 1;\n";
+
+    let plain = compile_src(src, CompileOptions::default()).unwrap();
+    assert!(plain.output.contains("(2 * 3)"));
+    assert!(plain.warnings.is_empty());
+
+    let options = CompileOptions {
+        optimization_level: 1,
+        ..CompileOptions::default()
+    };
+    let optimized = compile_src(src, options).unwrap();
+    assert!(optimized.output.starts_with("x = 6;"));
+    assert_eq!(optimized.warnings.len(), 1);
+}
+
+#[test]
+fn test_compile_reports_parse_errors() {
+    let src = SourceFile::new("test.toki", "x = ;\n".to_string());
+    let mut sink = crate::diagnostics::VecSink::default();
+
+    let err = compile(&src, &CompileOptions::default(), &mut sink).unwrap_err();
+    assert_eq!(err, CompileErr::Parse);
+    assert_eq!(
+        sink.diagnostics,
+        vec![crate::diagnostics::Diagnostic::Parse(
+            crate::parser::ParseErr::InvalidExpressionStart(4, 1)
+        )]
+    );
+}
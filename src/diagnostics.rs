@@ -0,0 +1,150 @@
+use crate::ast::AstBlock;
+use crate::linter::{lint, Lint};
+use crate::parser::ParseErr;
+use crate::reporter::{describe, render};
+use crate::source::SourceFile;
+
+// Passes hand what they find to a `DiagnosticSink` rather than formatting it
+// themselves, so the CLI, tests and the language server can each present the
+// same errors their own way.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// An error or warning, kept as the pass that found it reported it
+#[derive(Debug, PartialEq)]
+pub enum Diagnostic {
+    Parse(ParseErr),
+    Lint(Lint),
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Diagnostic::Parse(_) => Severity::Error,
+            Diagnostic::Lint(_) => Severity::Warning,
+        }
+    }
+
+    /// The message and the `(ix, len)` span it points at, if any
+    pub fn describe(&self, src: &SourceFile) -> (String, Option<(usize, usize)>) {
+        match self {
+            Diagnostic::Parse(err) => describe(err, src),
+            Diagnostic::Lint(lint) => (lint.message.clone(), Some((lint.span.start, lint.span.len()))),
+        }
+    }
+}
+
+pub trait DiagnosticSink {
+    fn emit(&mut self, diag: Diagnostic);
+}
+
+/// Writes each diagnostic to stderr as it comes in, pointed out in the source
+pub struct PrintSink<'src> {
+    pub src: &'src SourceFile,
+}
+
+impl DiagnosticSink for PrintSink<'_> {
+    fn emit(&mut self, diag: Diagnostic) {
+        match diag {
+            Diagnostic::Parse(err) => eprintln!("{}", render(&err, self.src)),
+            Diagnostic::Lint(lint) => eprintln!("{}", lint),
+        }
+    }
+}
+
+/// Passes the first `max_errors` errors on to `inner` and drops the rest.
+/// Warnings always go through.
+pub struct LimitSink<'a> {
+    inner: &'a mut dyn DiagnosticSink,
+    errors_left: usize,
+}
+
+impl<'a> LimitSink<'a> {
+    pub fn new(inner: &'a mut dyn DiagnosticSink, max_errors: usize) -> Self {
+        LimitSink {
+            inner,
+            errors_left: max_errors,
+        }
+    }
+}
+
+impl DiagnosticSink for LimitSink<'_> {
+    fn emit(&mut self, diag: Diagnostic) {
+        if diag.severity() == Severity::Error {
+            if self.errors_left == 0 {
+                return;
+            }
+            self.errors_left -= 1;
+        }
+        self.inner.emit(diag);
+    }
+}
+
+/// Keeps every diagnostic, in the order they were emitted
+#[derive(Debug, Default)]
+pub struct VecSink {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl DiagnosticSink for VecSink {
+    fn emit(&mut self, diag: Diagnostic) {
+        self.diagnostics.push(diag);
+    }
+}
+
+pub fn lint_with_sink(block: &AstBlock<'_>, sink: &mut dyn DiagnosticSink) {
+    for lint in lint(block) {
+        sink.emit(Diagnostic::Lint(lint));
+    }
+}
+
+#[test]
+fn test_limit_sink_drops_extra_errors() {
+    let mut sink = VecSink::default();
+    let mut limited = LimitSink::new(&mut sink, 1);
+
+    let src = SourceFile::new("test.toki", "def f() -> int:\n    x = 1;\n    3\n".to_string());
+    let block = crate::parser::try_parse(src.lexer()).unwrap();
+    limited.emit(Diagnostic::Parse(ParseErr::UnexpectedEnd));
+    limited.emit(Diagnostic::Parse(ParseErr::UnexpectedEnd));
+    lint_with_sink(&block, &mut limited);
+
+    let severities = sink.diagnostics.iter().map(Diagnostic::severity).collect::<Vec<_>>();
+    assert_eq!(severities, vec![Severity::Error, Severity::Warning, Severity::Warning]);
+}
+
+#[test]
+fn test_vec_sink_keeps_every_diagnostic() {
+    let mut sink = VecSink::default();
+
+    let src = SourceFile::new(
+        "test.toki",
+        "def f() -> int:\n    x = 1;\n    y = 2;\n    3\n".to_string(),
+    );
+    let block = crate::parser::parse(src.lexer(), &mut sink).unwrap();
+    assert!(sink.diagnostics.is_empty());
+    lint_with_sink(&block, &mut sink);
+
+    let broken = SourceFile::new("test.toki", "a = 1 +;\n".to_string());
+    assert!(crate::parser::parse(broken.lexer(), &mut sink).is_none());
+
+    let severities = sink.diagnostics.iter().map(Diagnostic::severity).collect::<Vec<_>>();
+    assert_eq!(
+        severities,
+        vec![Severity::Warning, Severity::Warning, Severity::Warning, Severity::Error]
+    );
+    let messages = sink
+        .diagnostics
+        .iter()
+        .map(|d| d.describe(&broken).0)
+        .collect::<Vec<_>>();
+    assert!(messages[0].contains('x') && messages[1].contains('y'), "{:?}", messages);
+    assert_eq!(
+        sink.diagnostics[3],
+        Diagnostic::Parse(ParseErr::InvalidExpressionStart(7, 1))
+    );
+}
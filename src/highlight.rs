@@ -0,0 +1,142 @@
+use serde_json::{json, Value};
+
+use crate::token::{Token, INT_SUFFIXES};
+
+// Tokens the grammar highlights as operators, spelled the way the lexer reads them
+const OPERATORS: &[Token<'static>] = &[
+    Token::Walrus,
+    Token::Arrow,
+    Token::AddEq,
+    Token::SubEq,
+    Token::MulEq,
+    Token::DivEq,
+    Token::DoubleEq,
+    Token::BangEq,
+    Token::FloorDiv,
+    Token::Add,
+    Token::Sub,
+    Token::Mul,
+    Token::Div,
+    Token::Percent,
+    Token::Eq,
+    Token::Bang,
+    Token::Question,
+    Token::Bar,
+    Token::Dot,
+];
+
+/// A TextMate grammar for the language, as the JSON a VS Code extension
+/// ships in its `tmLanguage.json`
+pub fn generate_tmlanguage() -> String {
+    serde_json::to_string_pretty(&grammar()).expect("Grammar is plain JSON")
+}
+
+fn grammar() -> Value {
+    let keywords = Token::keywords()
+        .filter_map(Token::keyword)
+        .collect::<Vec<_>>()
+        .join("|");
+
+    // Longest first, so `//` isn't read as two `/`s
+    let mut operators: Vec<_> = OPERATORS.iter().map(Token::to_string).collect();
+    operators.sort_by_key(|op| std::cmp::Reverse(op.len()));
+    let operators = operators.iter().map(|op| escape(op)).collect::<Vec<_>>().join("|");
+
+    let suffixes = INT_SUFFIXES.iter().map(|(s, ..)| *s).collect::<Vec<_>>().join("|");
+
+    json!({
+        "$schema": "https://raw.githubusercontent.com/martinring/tmlanguage/master/tmlanguage.json",
+        "name": "Toki",
+        "scopeName": "source.toki",
+        "fileTypes": ["toki"],
+        "patterns": [
+            { "include": "#comments" },
+            { "include": "#strings" },
+            { "include": "#keywords" },
+            { "include": "#numbers" },
+            { "include": "#operators" },
+            { "include": "#identifiers" },
+        ],
+        "repository": {
+            "comments": {
+                "patterns": [
+                    { "name": "meta.preprocessor.pragma.toki", "match": "^#!.*$" },
+                    { "name": "comment.line.number-sign.toki", "match": "#(?![\\[!]).*$" },
+                ],
+            },
+            "strings": {
+                "patterns": [
+                    {
+                        "name": "string.quoted.triple.toki",
+                        "begin": "\"\"\"",
+                        "end": "\"\"\"",
+                    },
+                    {
+                        "name": "string.quoted.double.toki",
+                        "begin": "\"",
+                        "end": "\"",
+                        // A `"` always ends the string, so escapes can't contain one
+                        "patterns": [{ "name": "constant.character.escape.toki", "match": "\\\\[nrt0\\\\]" }],
+                    },
+                ],
+            },
+            "keywords": {
+                "patterns": [{ "name": "keyword.control.toki", "match": format!("\\b({})\\b", keywords) }],
+            },
+            "numbers": {
+                "patterns": [
+                    { "name": "constant.numeric.float.toki", "match": "\\b[0-9][0-9_]*\\.[0-9][0-9_]*\\b" },
+                    {
+                        "name": "constant.numeric.integer.toki",
+                        "match": format!("\\b[0-9][0-9_]*({})?\\b", suffixes),
+                    },
+                ],
+            },
+            "operators": {
+                "patterns": [{ "name": "keyword.operator.toki", "match": operators }],
+            },
+            "identifiers": {
+                "patterns": [{ "name": "variable.other.toki", "match": "\\b[A-Za-z_][A-Za-z0-9_]*\\b" }],
+            },
+        },
+    })
+}
+
+// Escapes regex metacharacters in an operator's spelling
+fn escape(op: &str) -> String {
+    op.chars()
+        .flat_map(|c| match c {
+            '+' | '*' | '|' | '.' | '?' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect()
+}
+
+#[test]
+fn test_generate_tmlanguage() {
+    let grammar: Value = serde_json::from_str(&generate_tmlanguage()).unwrap();
+
+    assert_eq!(grammar["scopeName"], "source.toki");
+    assert!(grammar["patterns"].is_array());
+    let repository = grammar["repository"].as_object().unwrap();
+    for key in ["comments", "strings", "keywords", "numbers", "operators", "identifiers"] {
+        assert!(repository.contains_key(key), "{}", key);
+    }
+
+    let keywords = grammar["repository"]["keywords"]["patterns"][0]["match"]
+        .as_str()
+        .unwrap();
+    assert!(
+        keywords.contains("|def|") && keywords.contains("|struct|"),
+        "{}",
+        keywords
+    );
+    let operators = grammar["repository"]["operators"]["patterns"][0]["match"]
+        .as_str()
+        .unwrap();
+    assert!(
+        operators.find("//").unwrap() < operators.find("|/|").unwrap(),
+        "{}",
+        operators
+    );
+}
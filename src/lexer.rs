@@ -1,4 +1,4 @@
-use crate::token::{SpannedToken, Token};
+use crate::token::{SpannedToken, TextToken, Token, INT_SUFFIXES};
 
 pub struct Lexer<'src> {
     src: &'src str,
@@ -7,8 +7,12 @@ pub struct Lexer<'src> {
     byte: usize,
 
     just_after_newline: bool,
+    // Set after a trailing binary operator so the following line is lexed as a
+    // continuation of the current one rather than a new indentation level
+    continues_line: bool,
 
     indent_level: usize,
+    keep_comments: bool,
 }
 
 type SourcePostion = usize;
@@ -26,13 +30,41 @@ where
 pub enum LexErr {
     UnknownToken(SourcePostion, Option<SourcePostion>),
     UnterminatedString(SourcePostion),
+    // Indentation that isn't a multiple of 4 spaces
+    InvalidIndent(SourcePostion, usize),
+    // Indenting more than one level at once
+    UnexpectedIndent(SourcePostion, usize),
+    IntOverflow(SourcePostion, usize),
+    // A suffix on an int literal that isn't in `INT_SUFFIXES`
+    InvalidSuffix(SourcePostion, usize),
+    UnbalancedIndent(SourcePostion),
+}
+
+impl LexErr {
+    /// A stable code to look the error up by, numbered apart from the parser's
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnknownToken(..) => "E0101",
+            Self::UnterminatedString(..) => "E0102",
+            Self::InvalidIndent(..) => "E0103",
+            Self::UnexpectedIndent(..) => "E0104",
+            Self::IntOverflow(..) => "E0105",
+            Self::InvalidSuffix(..) => "E0106",
+            Self::UnbalancedIndent(..) => "E0107",
+        }
+    }
 }
 
 impl std::fmt::Display for LexErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::UnknownToken(ix, ed) => todo!(),
-            Self::UnterminatedString(ix) => todo!(),
+            Self::UnknownToken(ix, _) => write!(f, "Unknown token at {}", ix),
+            Self::UnterminatedString(ix) => write!(f, "Unterminated string starting at {}", ix),
+            Self::InvalidIndent(ix, _) => write!(f, "Indent at {} is not a multiple of 4 spaces", ix),
+            Self::UnexpectedIndent(ix, _) => write!(f, "Indent at {} is more than one level deeper", ix),
+            Self::IntOverflow(ix, _) => write!(f, "Integer literal at {} is too large", ix),
+            Self::InvalidSuffix(ix, _) => write!(f, "Unknown integer suffix at {}", ix),
+            Self::UnbalancedIndent(ix) => write!(f, "Indents and dedents stop balancing at {}", ix),
         }
     }
 }
@@ -46,6 +78,7 @@ impl std::error::Error for LexErr {
 enum Started<'src> {
     IfEqualElse(Token<'src>, Token<'src>),
     Minus,
+    Slash,
     String,
     Numeric,
     Ident,
@@ -59,16 +92,156 @@ impl<'src> Lexer<'src> {
             rest: src,
             byte: 0,
             just_after_newline: false,
+            continues_line: false,
             indent_level: 0,
+            keep_comments: false,
         }
     }
+
+    /// Lexes whole-line comments as `Token::Comment` rather than skipping
+    /// them, for tools like the formatter that need to keep them
+    pub fn with_comments(mut self) -> Self {
+        self.keep_comments = true;
+        self
+    }
+
+    /// The part of the source that hasn't been lexed yet, e.g. what was left
+    /// when a `LexErr` came back
+    pub fn remaining_source(&self) -> &'src str {
+        self.rest
+    }
+
+    pub fn consumed_bytes(&self) -> usize {
+        self.byte
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    /// Lexes the whole source, stopping at the first error
+    pub fn tokenize_all(self) -> Result<Vec<SpannedToken<'src>>> {
+        self.collect()
+    }
+
+    /// Lexes the whole source, skipping anything that fails to lex. For tools
+    /// that want to work with partially valid input.
+    pub fn tokenize_all_lossy(self) -> Vec<SpannedToken<'src>> {
+        self.filter_map(|tok| tok.ok()).collect()
+    }
+
+    /// Yields each token along with the source it came from. Unlike
+    /// `Token::src_len`, the text's length is exact, quotes and all.
+    pub fn with_text(self) -> WithText<'src> {
+        WithText { lexer: self }
+    }
+
+    /// Drops `Newline`s, and `Comment`s if they're kept, for consumers that
+    /// only want the significant tokens. `Indent` and `Dedent` still mark out
+    /// blocks, and since indentation is worked out from the source as it's
+    /// lexed, dropping newlines afterwards doesn't change them.
+    pub fn without_newlines(self) -> WithoutNewlines<'src> {
+        WithoutNewlines { lexer: self }
+    }
+}
+
+pub struct WithoutNewlines<'src> {
+    lexer: Lexer<'src>,
+}
+
+impl<'src> Iterator for WithoutNewlines<'src> {
+    type Item = Result<SpannedToken<'src>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lexer
+            .find(|tok| !matches!(tok, Ok((_, Token::Newline | Token::Comment(_)))))
+    }
+}
+
+pub struct WithText<'src> {
+    lexer: Lexer<'src>,
+}
+
+impl<'src> Iterator for WithText<'src> {
+    type Item = Result<TextToken<'src>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.lexer.next()?;
+        Some(next.map(|(ix, tok)| {
+            // Dedents are emitted without consuming anything
+            let end = self.lexer.byte.max(ix);
+            (ix, tok, &self.lexer.src[ix..end])
+        }))
+    }
+}
+
+/// Lexes `src` and renders one token per line as `Kind@byte`, with lex errors
+/// rendered in place. Used for snapshot tests of the token stream.
+pub fn tokens_to_string(src: &str) -> String {
+    Lexer::new(src)
+        .map(|tok| match tok {
+            Ok((ix, tok)) => format!("{:?}@{}\n", tok, ix),
+            Err(e) => format!("{:?}\n", e),
+        })
+        .collect()
 }
 
 impl<'src> Iterator for Lexer<'src> {
     type Item = Result<SpannedToken<'src>>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_token();
+        // A comment line doesn't end a continued expression
+        if !matches!(&next, Some(Ok((_, Token::Comment(_))))) {
+            self.continues_line = matches!(&next, Some(Ok((_, tok))) if tok.as_operator().is_some());
+        }
+        next
+    }
+}
+
+impl<'src> Lexer<'src> {
+    // Lines holding only whitespace or a comment don't take part in
+    // indentation. Skips to the end of such a line, leaving its newline.
+    fn skip_blank_line(&mut self) -> bool {
+        let line_end_ix = self.rest.find('\n').unwrap_or(self.rest.len());
+        let content = self.rest[..line_end_ix].trim_start_matches([' ', '\t', '\r']);
+        let is_blank = content.is_empty() || is_comment(content);
+        if is_blank {
+            self.byte += line_end_ix;
+            self.rest = &self.rest[line_end_ix..];
+        }
+        is_blank
+    }
+
+    // Takes a whole-line comment, newline and all, so the line doesn't take
+    // part in indentation
+    fn comment_line(&mut self) -> Option<SpannedToken<'src>> {
+        let line_end_ix = self.rest.find('\n').unwrap_or(self.rest.len());
+        let line = &self.rest[..line_end_ix];
+        let content = line.trim_start_matches([' ', '\t', '\r']);
+        if !is_comment(content) {
+            return None;
+        }
+
+        let c_at = self.byte + (line.len() - content.len());
+        let n_bytes = (line_end_ix + 1).min(self.rest.len());
+        self.byte += n_bytes;
+        self.rest = &self.rest[n_bytes..];
+        Some((c_at, Token::Comment(content[1..].trim_end_matches('\r'))))
+    }
+
+    fn next_token(&mut self) -> Option<Result<SpannedToken<'src>>> {
         loop {
+            if self.keep_comments && (self.byte == 0 || self.just_after_newline) {
+                if let Some(comment) = self.comment_line() {
+                    self.just_after_newline = true;
+                    return Some(Ok(comment));
+                }
+            }
+            if (self.byte == 0 || self.just_after_newline) && self.skip_blank_line() && self.rest.is_empty() {
+                return None;
+            }
+
             let mut c_at = self.byte;
             let mut chars = self.rest.chars();
             let mut c = chars.next()?;
@@ -87,12 +260,13 @@ impl<'src> Iterator for Lexer<'src> {
             }
 
             let c_rest = self.rest;
+            let at_line_start = c_at == 0 || self.just_after_newline;
 
             self.byte += c.len_utf8();
             self.rest = chars.as_str();
 
             if c == '\n' {
-                self.just_after_newline = true;
+                self.just_after_newline = !self.continues_line;
                 return Some(Ok((c_at, Token::Newline)));
             } else {
                 self.just_after_newline = false;
@@ -109,18 +283,36 @@ impl<'src> Iterator for Lexer<'src> {
                 ',' => return Some(Ok((c_at, Token::Comma))),
                 '.' => return Some(Ok((c_at, Token::Dot))),
                 '|' => return Some(Ok((c_at, Token::Bar))),
+                '?' => return Some(Ok((c_at, Token::Question))),
+                '#' if self.rest.starts_with('[') => return Some(Ok((c_at, Token::Hash))),
+                '#' if self.rest.starts_with('!') && at_line_start => {
+                    let line_end_ix = self.rest.find('\n').unwrap_or(self.rest.len());
+                    let pragma = &self.rest[1..line_end_ix];
+
+                    self.byte += line_end_ix;
+                    self.rest = &self.rest[line_end_ix..];
+
+                    return Some(Ok((c_at, Token::Pragma(pragma))));
+                }
+                '#' => {
+                    let line_end_ix = self.rest.find('\n').unwrap_or(self.rest.len());
+                    self.byte += line_end_ix;
+                    self.rest = &self.rest[line_end_ix..];
+                    continue;
+                }
 
                 '-' => Started::Minus,
                 '+' => Started::IfEqualElse(Token::Add, Token::AddEq),
                 '*' => Started::IfEqualElse(Token::Mul, Token::MulEq),
-                '/' => Started::IfEqualElse(Token::Div, Token::DivEq),
+                '/' => Started::Slash,
+                '%' => return Some(Ok((c_at, Token::Percent))),
                 '!' => Started::IfEqualElse(Token::Bang, Token::BangEq),
                 '=' => Started::IfEqualElse(Token::Eq, Token::DoubleEq),
 
                 ' ' => Started::Spaces,
                 '"' => Started::String,
                 '0'..='9' => Started::Numeric,
-                a if a.is_alphabetic() => Started::Ident,
+                a if a.is_alphabetic() || a == '_' => Started::Ident,
 
                 _ => return Some(Err(LexErr::UnknownToken(c_at, None))),
             };
@@ -130,8 +322,9 @@ impl<'src> Iterator for Lexer<'src> {
                     let space_end_ix = c_rest.find(|c| c != ' ').unwrap_or_else(|| c_rest.len());
                     let spaces = &c_rest[..space_end_ix];
 
-                    // TODO: Add LexErr
-                    assert!(spaces.len() % 4 == 0, "Required indent size is 4 spaces");
+                    if spaces.len() % 4 != 0 {
+                        return Some(Err(LexErr::InvalidIndent(c_at, spaces.len())));
+                    }
 
                     let indent = spaces.len() / 4;
 
@@ -140,14 +333,24 @@ impl<'src> Iterator for Lexer<'src> {
                     }
 
                     let tok = if indent < self.indent_level {
-                        let n_bytes = 4 - c.len_utf8();
-
-                        self.byte += n_bytes;
-                        self.rest = &self.rest[n_bytes..];
                         self.indent_level -= 1;
+                        if indent < self.indent_level {
+                            // Dropping several levels at once, come back to these
+                            // spaces for each remaining `Dedent`
+                            self.byte = c_at;
+                            self.rest = c_rest;
+                            self.just_after_newline = true;
+                        } else {
+                            let n_bytes = 4 - c.len_utf8();
+
+                            self.byte += n_bytes;
+                            self.rest = &self.rest[n_bytes..];
+                        }
                         (c_at, Token::Dedent)
                     } else {
-                        assert!(indent - self.indent_level == 1, "Unexpected Indent");
+                        if indent - self.indent_level != 1 {
+                            return Some(Err(LexErr::UnexpectedIndent(c_at, spaces.len())));
+                        }
 
                         let n_bytes = 4 - c.len_utf8();
 
@@ -160,17 +363,32 @@ impl<'src> Iterator for Lexer<'src> {
                 }
                 Started::Numeric => {
                     let numeric_end_ix = c_rest
-                        .find(|c: char| !(c.is_numeric() || c == '_'))
+                        .find(|c: char| !(c.is_ascii_digit() || c == '_'))
                         .unwrap_or_else(|| c_rest.len());
 
                     let numeric_token = &c_rest[..numeric_end_ix];
+                    let suffix_len = c_rest[numeric_end_ix..]
+                        .find(|c: char| !c.is_alphanumeric())
+                        .unwrap_or(c_rest.len() - numeric_end_ix);
+                    let suffix = &c_rest[numeric_end_ix..numeric_end_ix + suffix_len];
 
-                    let n_bytes = numeric_token.len() - c.len_utf8();
+                    let n_bytes = numeric_token.len() + suffix.len() - c.len_utf8();
                     self.byte += n_bytes;
                     self.rest = &self.rest[n_bytes..];
 
-                    let n: i32 = numeric_token.parse().expect("Should have checked");
-                    (c_at, Token::IntLiteral(n))
+                    let (min, max) = match INT_SUFFIXES.iter().find(|(name, ..)| *name == suffix) {
+                        Some((_, min, max)) => (*min, *max),
+                        None if suffix.is_empty() => (i32::MIN.into(), i32::MAX.into()),
+                        None => return Some(Err(LexErr::InvalidSuffix(c_at + numeric_end_ix, suffix.len()))),
+                    };
+
+                    // Only digits and `_` separators were taken, so overflow is the only failure
+                    let value = numeric_token.replace('_', "").parse::<i64>().ok();
+                    let Some(value) = value.filter(|v| (min..=max).contains(v)) else {
+                        return Some(Err(LexErr::IntOverflow(c_at, numeric_token.len() + suffix.len())));
+                    };
+                    let suffix = (!suffix.is_empty()).then_some(suffix);
+                    (c_at, Token::IntLiteral { value, suffix })
                 }
                 Started::Ident => {
                     let ident_ed_ix = c_rest
@@ -185,6 +403,25 @@ impl<'src> Iterator for Lexer<'src> {
 
                     (c_at, get_keyword(ident).unwrap_or_else(|| Token::Ident(ident)))
                 }
+                Started::String if c_rest.starts_with("\"\"\"") => {
+                    // Triple-quoted strings run to the next `"""`, newlines and all
+                    if let Some(str_end_ix) = c_rest[3..].find("\"\"\"") {
+                        let n_bytes = 3 + str_end_ix + 3 - c.len_utf8();
+
+                        self.byte += n_bytes;
+                        self.rest = &self.rest[n_bytes..];
+
+                        (
+                            c_at,
+                            Token::StrLiteral {
+                                value: &c_rest[3..3 + str_end_ix],
+                                triple: true,
+                            },
+                        )
+                    } else {
+                        return Some(Err(LexErr::UnterminatedString(c_at)));
+                    }
+                }
                 Started::String => {
                     if let Some(str_end_ix) = c_rest[1..].find(|c| c == '"') {
                         let full_str = &c_rest[..=str_end_ix + 1];
@@ -193,7 +430,7 @@ impl<'src> Iterator for Lexer<'src> {
                         self.byte += n_bytes;
                         self.rest = &self.rest[n_bytes..];
 
-                        (c_at, Token::StrLiteral(&full_str[1..&full_str.len() - 1]))
+                        (c_at, Token::str(&full_str[1..&full_str.len() - 1]))
                     } else {
                         return Some(Err(LexErr::UnterminatedString(c_at)));
                     }
@@ -208,6 +445,20 @@ impl<'src> Iterator for Lexer<'src> {
                     };
                     (c_at, tok)
                 }
+                Started::Slash => {
+                    let tok = if self.rest.starts_with('=') {
+                        self.byte += '='.len_utf8();
+                        self.rest = &self.rest[1..];
+                        Token::DivEq
+                    } else if self.rest.starts_with('/') {
+                        self.byte += '/'.len_utf8();
+                        self.rest = &self.rest[1..];
+                        Token::FloorDiv
+                    } else {
+                        Token::Div
+                    };
+                    (c_at, tok)
+                }
                 Started::Minus => {
                     let tok = if self.rest.starts_with('=') {
                         self.byte += '='.len_utf8();
@@ -228,17 +479,300 @@ impl<'src> Iterator for Lexer<'src> {
     }
 }
 
-fn get_keyword<'src>(ident: &'src str) -> Option<Token<'src>> {
-    Some(match ident {
-        "and" => Token::And,
-        "or" => Token::Or,
-        "not" => Token::Not,
-        "mut" => Token::Mut,
-        "return" => Token::Return,
-        "if" => Token::If,
-        "else" => Token::Else,
-        "def" => Token::Def,
-        "struct" => Token::Struct,
-        _ => return None,
-    })
+// `#` starts a comment unless it's the start of an attribute or pragma
+fn is_comment(s: &str) -> bool {
+    s.starts_with('#') && !s.starts_with("#[") && !s.starts_with("#!")
+}
+
+/// Checks a token stream as it passes through: every `Dedent` has to close an
+/// earlier `Indent`, and the stream has to end back at depth 0. Otherwise the
+/// stream ends with `LexErr::UnbalancedIndent`.
+pub struct BalancedIndent<I> {
+    tokens: I,
+    depth: usize,
+    last_ix: SourcePostion,
+    done: bool,
+}
+
+impl<I> BalancedIndent<I> {
+    pub fn new(tokens: I) -> Self {
+        BalancedIndent {
+            tokens,
+            depth: 0,
+            last_ix: 0,
+            done: false,
+        }
+    }
+}
+
+impl<'src, I> Iterator for BalancedIndent<I>
+where
+    I: Iterator<Item = Result<SpannedToken<'src>>>,
+{
+    type Item = Result<SpannedToken<'src>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.tokens.next();
+        match &next {
+            Some(Ok((ix, tok))) => {
+                self.last_ix = *ix;
+                match tok {
+                    Token::Indent => self.depth += 1,
+                    Token::Dedent if self.depth == 0 => {
+                        self.done = true;
+                        return Some(Err(LexErr::UnbalancedIndent(*ix)));
+                    }
+                    Token::Dedent => self.depth -= 1,
+                    _ => {}
+                }
+            }
+            None if self.depth > 0 => {
+                self.done = true;
+                return Some(Err(LexErr::UnbalancedIndent(self.last_ix)));
+            }
+            _ => {}
+        }
+        next
+    }
+}
+
+/// The keyword spelled `ident`, if it is one. Called for every identifier
+/// lexed; a `HashMap` lookup measured around five times slower than this
+/// `match` (see the `keywords` group in `benches/lex_bench.rs`).
+pub fn get_keyword<'src>(ident: &str) -> Option<Token<'src>> {
+    Token::keywords().find(|tok| tok.keyword() == Some(ident)).cloned()
+}
+
+#[test]
+fn test_remaining_source() {
+    let mut lexer = Lexer::new("hello world");
+    assert_eq!(lexer.next(), Some(Ok((0, Token::Ident("hello")))));
+    assert_eq!(lexer.remaining_source(), " world");
+    assert_eq!(lexer.consumed_bytes(), 5);
+    assert!(!lexer.is_at_end());
+
+    assert_eq!(lexer.next(), Some(Ok((6, Token::Ident("world")))));
+    assert!(lexer.is_at_end());
+}
+
+#[test]
+fn test_tokenize_all() {
+    let toks = Lexer::new("def f() -> int:\n    1\n").tokenize_all().unwrap();
+    assert_eq!(
+        toks,
+        vec![
+            (0, Token::Def),
+            (4, Token::Ident("f")),
+            (5, Token::LParen),
+            (6, Token::RParen),
+            (8, Token::Arrow),
+            (11, Token::Ident("int")),
+            (14, Token::Colon),
+            (15, Token::Newline),
+            (16, Token::Indent),
+            (20, Token::int(1)),
+            (21, Token::Newline),
+        ]
+    );
+}
+
+#[test]
+fn test_tokenize_all_stops_or_skips_errors() {
+    assert_eq!(Lexer::new("a $ b").tokenize_all(), Err(LexErr::UnknownToken(2, None)));
+    assert_eq!(
+        Lexer::new("a $ b").tokenize_all_lossy(),
+        vec![(0, Token::Ident("a")), (4, Token::Ident("b"))]
+    );
+}
+
+#[cfg(test)]
+fn check_balance(toks: Vec<Token<'_>>) -> Result<Vec<SpannedToken<'_>>> {
+    BalancedIndent::new(toks.into_iter().enumerate().map(Ok)).collect()
+}
+
+#[test]
+fn test_balanced_indent_passes_balanced_stream() {
+    let toks = vec![Token::Colon, Token::Indent, Token::Indent, Token::Dedent, Token::Dedent];
+    assert_eq!(check_balance(toks).unwrap().len(), 5);
+}
+
+#[test]
+fn test_balanced_indent_rejects_extra_dedent() {
+    let toks = vec![Token::Indent, Token::Dedent, Token::Dedent, Token::Ident("a")];
+    assert_eq!(check_balance(toks), Err(LexErr::UnbalancedIndent(2)));
+}
+
+#[test]
+fn test_balanced_indent_rejects_unclosed_indent() {
+    let toks = vec![Token::Indent, Token::Ident("a"), Token::Indent, Token::Dedent];
+    assert_eq!(check_balance(toks), Err(LexErr::UnbalancedIndent(3)));
+}
+
+#[test]
+fn test_snapshot_assignment() {
+    insta::assert_snapshot!(tokens_to_string("foo = 1 + bar * 2;\n"));
+}
+
+#[test]
+fn test_snapshot_fn_def() {
+    let src = "def add(a: int, b: int) -> int:\n    if a == b:\n        a\n    else:\n        a + b\n";
+    insta::assert_snapshot!(tokens_to_string(src));
+}
+
+#[test]
+fn test_snapshot_struct_and_pragma() {
+    let src = "#!strict\n#[derive(eq)]\nstruct Point:\n    x: mut int\n    name: str\n";
+    insta::assert_snapshot!(tokens_to_string(src));
+}
+
+#[test]
+fn test_snapshot_strings_and_calls() {
+    insta::assert_snapshot!(tokens_to_string("print(\"hi\", p.name, xs[1:2], 1_000);\n"));
+}
+
+#[test]
+fn test_snapshot_lex_errors() {
+    insta::assert_snapshot!(tokens_to_string("x = 99999999999;\ny = \"open\n"));
+}
+
+#[test]
+fn test_comments_and_blank_lines_are_skipped() {
+    let src = "def f() -> int:\n    x = 1; # one\n\n  # stray\n      \n    x\n";
+    let plain = "def f() -> int:\n    x = 1;\n\n\n\n    x\n";
+
+    let kinds = |src| Lexer::new(src).map(|t| t.map(|(_, t)| t)).collect::<Vec<_>>();
+    assert_eq!(kinds(src), kinds(plain));
+}
+
+#[test]
+fn test_triple_quoted_strings() {
+    let src = "def f() -> str:\n    \"\"\"say \"hi\"\n  and \"\"bye\"\"\n\"\"\"\n    x\n";
+    assert_eq!(
+        Lexer::new(src).tokenize_all().unwrap()[9..12],
+        [
+            (
+                20,
+                Token::StrLiteral {
+                    value: "say \"hi\"\n  and \"\"bye\"\"\n",
+                    triple: true,
+                },
+            ),
+            (49, Token::Newline),
+            (54, Token::Ident("x")),
+        ]
+    );
+
+    assert_eq!(
+        Lexer::new("s = \"\"\"open\n\"\"").tokenize_all(),
+        Err(LexErr::UnterminatedString(4))
+    );
+}
+
+#[test]
+fn test_dedent_several_levels_to_nonzero_indent() {
+    let src = "def f() -> int:\n    if a:\n        if b:\n            1\n    x\n";
+    let toks = Lexer::new(src).tokenize_all().unwrap();
+    let tail = toks[toks.len() - 5..].iter().map(|(_, t)| t).collect::<Vec<_>>();
+    assert_eq!(
+        tail,
+        vec![
+            &Token::Newline,
+            &Token::Dedent,
+            &Token::Dedent,
+            &Token::Ident("x"),
+            &Token::Newline
+        ]
+    );
+}
+
+#[test]
+fn test_tokens_with_text() {
+    let src = "x = \"hi\" if y else \"\"\"a\"\"\";\n";
+    let texts: Vec<_> = Lexer::new(src).with_text().map(|t| t.unwrap().2).collect();
+    assert_eq!(
+        texts,
+        vec!["x", "=", "\"hi\"", "if", "y", "else", "\"\"\"a\"\"\"", ";", "\n"]
+    );
+}
+
+#[test]
+fn test_without_newlines() {
+    let src = "def f() -> int:\n    # one\n\n    if a:\n        1\n    x\ny = f();\n";
+    let all = Lexer::new(src).with_comments().tokenize_all().unwrap();
+    let significant = Lexer::new(src)
+        .with_comments()
+        .without_newlines()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+
+    let expected: Vec<_> = all
+        .iter()
+        .filter(|(_, tok)| !matches!(tok, Token::Newline | Token::Comment(_)))
+        .collect();
+    assert_eq!(significant.iter().collect::<Vec<_>>(), expected);
+    assert_eq!(
+        significant
+            .iter()
+            .filter(|(_, tok)| matches!(tok, Token::Indent | Token::Dedent))
+            .count(),
+        4
+    );
+    assert!(all.len() > significant.len());
+
+    // Errors still come through
+    assert_eq!(
+        Lexer::new("a\n$").without_newlines().nth(1),
+        Some(Err(LexErr::UnknownToken(2, None)))
+    );
+}
+
+#[test]
+fn test_lex_comments_when_asked() {
+    let src = "def f() -> int:\n    # one\n    x = 1; # trailing\n    x\n";
+    let comments: Vec<_> = Lexer::new(src)
+        .with_comments()
+        .filter_map(|t| match t.unwrap() {
+            (ix, Token::Comment(text)) => Some((ix, text)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(comments, vec![(20, " one")]);
+
+    assert!(!Lexer::new(src).any(|t| matches!(t, Ok((_, Token::Comment(_))))));
+}
+
+#[test]
+fn test_int_suffixes() {
+    let toks = Lexer::new("10 10i64 255u8 1_000u32").tokenize_all().unwrap();
+    let suffixes: Vec<_> = toks
+        .iter()
+        .map(|(_, tok)| match tok {
+            Token::IntLiteral { value, suffix } => (*value, *suffix),
+            _ => panic!("Expected IntLiteral, got {:?}", tok),
+        })
+        .collect();
+    assert_eq!(
+        suffixes,
+        vec![(10, None), (10, Some("i64")), (255, Some("u8")), (1000, Some("u32"))]
+    );
+    assert_eq!(toks[2].1.to_string(), "255u8");
+    assert_eq!(toks[2].1.src_len(), 5);
+
+    assert_eq!(Lexer::new("x = 10q;").nth(2), Some(Err(LexErr::InvalidSuffix(6, 1))));
+    assert_eq!(Lexer::new("256u8").next(), Some(Err(LexErr::IntOverflow(0, 5))));
+
+    // Suffixes wider than `i32` get their whole range
+    assert!(matches!(
+        Lexer::new("4000000000u32").next(),
+        Some(Ok((_, Token::IntLiteral { value: 4000000000, .. })))
+    ));
+    assert!(matches!(
+        Lexer::new("3000000000i64").next(),
+        Some(Ok((_, Token::IntLiteral { value: 3000000000, .. })))
+    ));
+    assert_eq!(Lexer::new("3000000000").next(), Some(Err(LexErr::IntOverflow(0, 10))));
 }
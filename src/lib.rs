@@ -0,0 +1,18 @@
+pub mod ast;
+pub mod cfg;
+pub mod codegen;
+pub mod compile;
+pub mod diagnostics;
+pub mod highlight;
+pub mod lexer;
+pub mod linter;
+pub mod log;
+pub mod lsp;
+pub mod optimize;
+pub mod parser;
+pub mod pretty;
+pub mod reporter;
+pub mod resolve;
+pub mod source;
+pub mod span;
+pub mod token;
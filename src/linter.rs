@@ -0,0 +1,348 @@
+use crate::ast::{AstBlock, AstExpr, AstLiteral, AstStmt, FnDef, FnParam, Pattern};
+use crate::span::{Span, Spanned};
+use crate::token::Token;
+
+// There's no resolver yet, so the linter does its own (Python-style) scoping:
+// functions and struct bodies open a scope, conditionals don't, and the first
+// assignment to a name in a scope defines it. `let` and `const` always define.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    UnusedVariable,
+    ShadowedVariable,
+    UnusedFunction,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Lint {
+    pub kind: LintKind,
+    /// The binding the lint is about
+    pub span: Span,
+    pub message: String,
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "warning: {}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BindingKind {
+    Variable,
+    Param,
+    Function,
+    Struct,
+}
+
+struct Binding<'src> {
+    name: &'src str,
+    span: Span,
+    kind: BindingKind,
+    uses: usize,
+}
+
+#[derive(Default)]
+pub struct Linter<'src> {
+    // Kept in definition order so lints come out in source order
+    scopes: Vec<Vec<Binding<'src>>>,
+    lints: Vec<Lint>,
+}
+
+pub fn lint(block: &AstBlock<'_>) -> Vec<Lint> {
+    let mut linter = Linter::default();
+    linter.lint_scope(block, &[]);
+    linter.lints
+}
+
+fn ident_name<'src>(lit: &AstLiteral<'src>) -> Option<(&'src str, Span)> {
+    match lit {
+        AstLiteral::Ident(ident) | AstLiteral::TypedIdent { name: ident, .. } => binding_name(ident),
+        _ => None,
+    }
+}
+
+fn binding_name<'src>(ident: &Spanned<Token<'src>>) -> Option<(&'src str, Span)> {
+    match ident.node {
+        Token::Ident(name) => Some((name, ident.span)),
+        _ => None,
+    }
+}
+
+impl<'src> Linter<'src> {
+    fn lint_scope(&mut self, block: &AstBlock<'src>, params: &[FnParam<'src>]) {
+        self.scopes.push(Vec::new());
+
+        for (param, span) in params.iter().filter_map(|p| ident_name(&p.ident)) {
+            self.define(param, span, BindingKind::Param);
+        }
+        self.hoist(block);
+        self.walk_block(block);
+
+        let scope = self.scopes.pop().expect("Pushed above");
+        for binding in scope {
+            self.check_used(&binding);
+        }
+    }
+
+    // Functions and structs can be referenced before their definition
+    fn hoist(&mut self, block: &AstBlock<'src>) {
+        for stmt in &block.stmts {
+            match &stmt.node {
+                AstStmt::FnDef(fn_def) => {
+                    if let Some((name, span)) = ident_name(&fn_def.name) {
+                        self.define(name, span, BindingKind::Function);
+                    }
+                }
+                AstStmt::StructDef { name, .. } => {
+                    if let Some((name, span)) = ident_name(name) {
+                        self.define(name, span, BindingKind::Struct);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn walk_block(&mut self, block: &AstBlock<'src>) {
+        for stmt in &block.stmts {
+            self.walk_stmt(stmt);
+        }
+    }
+
+    fn walk_stmt(&mut self, stmt: &AstStmt<'src>) {
+        match stmt {
+            AstStmt::Expr { expr, .. } | AstStmt::Return(expr) => self.walk_expr(expr),
+            AstStmt::Assignment { target, assigned } => {
+                self.walk_expr(assigned);
+                match target {
+                    AstExpr::LitExpr(lit) => {
+                        if let Some((name, span)) = ident_name(lit) {
+                            self.assign(name, span);
+                        }
+                    }
+                    _ => self.walk_expr(target),
+                }
+            }
+            AstStmt::Let { name, value, .. } => {
+                self.walk_expr(value);
+                if let Some((name, span)) = ident_name(name) {
+                    self.define(name, span, BindingKind::Variable);
+                }
+            }
+            AstStmt::FnDef(fn_def) => self.walk_fn_def(fn_def),
+            // Like conditionals, arms don't open a scope of their own
+            AstStmt::Match { scrutinee, arms } => {
+                self.walk_expr(scrutinee);
+                for arm in arms {
+                    if let Some((name, span)) = match &arm.pattern {
+                        Pattern::Binding(ident) => binding_name(ident),
+                        _ => None,
+                    } {
+                        self.assign(name, span);
+                    }
+                    self.walk_block(&arm.body);
+                }
+            }
+            AstStmt::StructDef { fields, methods, .. } => {
+                for default in fields.iter().filter_map(|f| f.default.as_ref()) {
+                    self.walk_expr(default);
+                }
+                for method in methods {
+                    self.walk_fn_def(method);
+                }
+            }
+        }
+    }
+
+    fn walk_fn_def(&mut self, fn_def: &FnDef<'src>) {
+        // Defaults are evaluated where the function is defined
+        for default in fn_def.args.iter().filter_map(|p| p.default.as_ref()) {
+            self.walk_expr(default);
+        }
+        self.lint_scope(&fn_def.body, &fn_def.args);
+    }
+
+    fn walk_expr(&mut self, expr: &AstExpr<'src>) {
+        match expr {
+            AstExpr::LitExpr(lit) => {
+                if let Some((name, _)) = ident_name(lit) {
+                    self.mark_used(name);
+                }
+            }
+            AstExpr::BinExpr(bin) => {
+                self.walk_expr(&bin.l);
+                self.walk_expr(&bin.r);
+            }
+            AstExpr::UnaryExpr(unary) => self.walk_expr(&unary.expr),
+            AstExpr::NotExpr(expr) => self.walk_expr(expr),
+            AstExpr::ConditionalExpr(cond) => {
+                self.walk_expr(&cond.condition);
+                self.walk_block(&cond.if_block);
+                for (condition, block) in &cond.elif_branches {
+                    self.walk_expr(condition);
+                    self.walk_block(block);
+                }
+                if let Some(else_block) = &cond.else_block {
+                    self.walk_expr(else_block);
+                }
+            }
+            AstExpr::BlockExpr(block) => self.walk_block(block),
+            AstExpr::CallExpr(call) => {
+                self.walk_expr(&call.called_expr);
+                // Argument names are keywords, not uses
+                for arg in &call.args {
+                    self.walk_expr(&arg.expr);
+                }
+            }
+            AstExpr::AttrAccessExpr(aa) => self.walk_expr(&aa.expr),
+            AstExpr::IndexExpr(index) => {
+                self.walk_expr(&index.target);
+                self.walk_expr(&index.index);
+            }
+            AstExpr::SliceExpr(slice) => {
+                self.walk_expr(&slice.target);
+                for bound in slice.start.iter().chain(&slice.end) {
+                    self.walk_expr(bound);
+                }
+            }
+            AstExpr::FnDefExpr(fn_def) => self.walk_fn_def(fn_def),
+            AstExpr::StructInitExpr(si) => {
+                if let Some((name, _)) = ident_name(&si.name) {
+                    self.mark_used(name);
+                }
+                for field in &si.fields {
+                    self.walk_expr(&field.expr);
+                }
+            }
+        }
+    }
+
+    fn define(&mut self, name: &'src str, span: Span, kind: BindingKind) {
+        let is_shadowing = self.scopes[..self.scopes.len() - 1]
+            .iter()
+            .any(|scope| scope.iter().any(|b| b.name == name));
+        if is_shadowing {
+            self.lints.push(Lint {
+                kind: LintKind::ShadowedVariable,
+                span,
+                message: format!("`{}` shadows a name from an outer scope", name),
+            });
+        }
+
+        let scope = self.scopes.last_mut().expect("Always inside a scope");
+        scope.push(Binding {
+            name,
+            span,
+            kind,
+            uses: 0,
+        });
+    }
+
+    fn assign(&mut self, name: &'src str, span: Span) {
+        let scope = self.scopes.last().expect("Always inside a scope");
+        if !scope.iter().any(|b| b.name == name) {
+            self.define(name, span, BindingKind::Variable);
+        }
+    }
+
+    fn mark_used(&mut self, name: &str) {
+        let binding = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.iter_mut().find(|b| b.name == name));
+        if let Some(binding) = binding {
+            binding.uses += 1;
+        }
+    }
+
+    fn check_used(&mut self, binding: &Binding<'src>) {
+        if binding.uses > 0 || binding.name.starts_with('_') {
+            return;
+        }
+
+        let is_top_level = self.scopes.is_empty();
+        let (kind, what) = match binding.kind {
+            // Module-level variables may be read by importers
+            BindingKind::Variable if is_top_level => return,
+            BindingKind::Param if binding.name == "self" => return,
+            BindingKind::Variable => (LintKind::UnusedVariable, "variable"),
+            BindingKind::Param => (LintKind::UnusedVariable, "parameter"),
+            BindingKind::Function => (LintKind::UnusedFunction, "function"),
+            BindingKind::Struct => return,
+        };
+
+        self.lints.push(Lint {
+            kind,
+            span: binding.span,
+            message: format!("{} `{}` is never used", what, binding.name),
+        });
+    }
+}
+
+#[cfg(test)]
+fn lint_src(src: &str) -> Vec<(LintKind, String)> {
+    let block = crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    lint(&block).into_iter().map(|l| (l.kind, l.message)).collect()
+}
+
+#[test]
+fn test_lint_unused_variable_and_param() {
+    let src = "def f(a: int, _b: int) -> int:\n    x = 1;\n    _y = 2;\n    a\n\nf(1, 2);\n";
+    assert_eq!(
+        lint_src(src),
+        vec![(LintKind::UnusedVariable, "variable `x` is never used".to_string())]
+    );
+}
+
+#[test]
+fn test_lint_shadowed_variable() {
+    let src = "def f(x: int) -> int:\n    def g(x: int) -> int:\n        x\n    g(x)\n\nf(1);\n";
+    assert_eq!(
+        lint_src(src),
+        vec![(
+            LintKind::ShadowedVariable,
+            "`x` shadows a name from an outer scope".to_string()
+        )]
+    );
+}
+
+#[test]
+fn test_lint_unused_function() {
+    let src = "def used() -> int:\n    1\n\ndef unused() -> int:\n    used()\n\n";
+    assert_eq!(
+        lint_src(src),
+        vec![(LintKind::UnusedFunction, "function `unused` is never used".to_string())]
+    );
+}
+
+#[test]
+fn test_lint_let_declares_a_new_binding() {
+    // A bare assignment updates `x`, but `let` declares another one
+    let src = "def f() -> int:\n    x = 1;\n    x = 2;\n    x\n\nf();\n";
+    assert_eq!(lint_src(src), vec![]);
+
+    let src = "def f() -> int:\n    x = 1;\n    let x = 2;\n    x\n\nf();\n";
+    assert_eq!(
+        lint_src(src),
+        vec![(LintKind::UnusedVariable, "variable `x` is never used".to_string())]
+    );
+}
+
+#[test]
+fn test_lint_points_at_the_binding() {
+    let src = "def f(x: int) -> int:\n    def g(x: int) -> int:\n        1\n    y = g(x);\n    0\n\nf(1);\n";
+    let block = crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    let inner_x = src.find("g(x").unwrap() + 2;
+    let y = src.find("y =").unwrap();
+    let spans: Vec<_> = lint(&block).into_iter().map(|l| (l.kind, l.span)).collect();
+    assert_eq!(
+        spans,
+        vec![
+            (LintKind::ShadowedVariable, crate::span::Span::new(inner_x, inner_x + 1)),
+            (LintKind::UnusedVariable, crate::span::Span::new(inner_x, inner_x + 1)),
+            (LintKind::UnusedVariable, crate::span::Span::new(y, y + 1)),
+        ]
+    );
+}
@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Verbosity of the `TOKI_LOG` debugging output on stderr. Each level includes
+/// everything below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Info,
+    Debug,
+    Trace,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Off as u8);
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "off" => Level::Off,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            "trace" => Level::Trace,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Level::Off => "off",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Reads `TOKI_LOG`, leaving logging off if it's unset or unrecognised
+pub fn init_from_env() {
+    if let Some(level) = std::env::var("TOKI_LOG").ok().and_then(|l| Level::from_name(&l)) {
+        set_level(level);
+    }
+}
+
+// Callers check this before building their message so that disabled logging
+// costs a single atomic load
+pub fn enabled(level: Level) -> bool {
+    level != Level::Off && LEVEL.load(Ordering::Relaxed) >= level as u8
+}
+
+pub fn write(level: Level, msg: std::fmt::Arguments<'_>) {
+    eprintln!("[{}] {}", level.name(), msg);
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, format_args!($($arg)*));
+        }
+    };
+}
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+use crate::ast::{AstBlock, AstExpr, AstLiteral, AstStmt};
+use crate::diagnostics::{Diagnostic, DiagnosticSink, Severity};
+use crate::lexer::Lexer;
+use crate::parser::{parse, try_parse};
+use crate::source::SourceFile;
+use crate::span::Spanned;
+use crate::token::Token;
+
+// Only what an editor needs for squiggles and hovers: whole-document syncs,
+// diagnostics for the first parse error and type annotations on hover.
+// Positions are LSP's line and UTF-16 column pairs.
+
+/// Serves requests from `input` until the client sends `exit`, following
+/// version 3.17 of the language server protocol
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server::default();
+    while let Some(msg) = read_message(&mut input)? {
+        if msg["method"] == "exit" {
+            break;
+        }
+        for reply in server.handle(&msg) {
+            write_message(&mut output, &reply)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads one `Content-Length` framed message, or `None` once the input ends
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            len = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let len = len.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length Header"))?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(io::Error::from)
+}
+
+pub fn write_message(output: &mut impl Write, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[derive(Default)]
+pub struct Server {
+    // Full text of each open document, by URI
+    documents: HashMap<String, String>,
+}
+
+impl Server {
+    /// Everything to send back for `msg`, responses and notifications alike
+    pub fn handle(&mut self, msg: &Value) -> Vec<Value> {
+        let params = &msg["params"];
+        match msg["method"].as_str() {
+            Some("initialize") => vec![response(
+                msg,
+                json!({
+                    "capabilities": {
+                        // Every change sends the whole document
+                        "textDocumentSync": 1,
+                        "hoverProvider": true,
+                    },
+                    "serverInfo": { "name": "toki" },
+                }),
+            )],
+            Some("shutdown") => vec![response(msg, Value::Null)],
+            Some("textDocument/didOpen") => {
+                let doc = &params["textDocument"];
+                self.update(&doc["uri"], doc["text"].as_str())
+            }
+            Some("textDocument/didChange") => {
+                // Full syncs only have the one change, but the last one wins either way
+                let text = params["contentChanges"].as_array().and_then(|c| c.last());
+                self.update(&params["textDocument"]["uri"], text.and_then(|c| c["text"].as_str()))
+            }
+            Some("textDocument/didClose") => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    self.documents.remove(uri);
+                }
+                Vec::new()
+            }
+            Some("textDocument/hover") => {
+                let text = params["textDocument"]["uri"]
+                    .as_str()
+                    .and_then(|uri| self.documents.get(uri));
+                let hover = text.and_then(|text| hover(text, &params["position"]));
+                vec![response(msg, hover.unwrap_or(Value::Null))]
+            }
+            // Requests need an answer even when they aren't supported
+            Some(method) if msg.get("id").is_some() => vec![json!({
+                "jsonrpc": "2.0",
+                "id": msg["id"],
+                "error": { "code": -32601, "message": format!("Unknown Method {}", method) },
+            })],
+            // Unknown notifications are safe to ignore
+            _ => Vec::new(),
+        }
+    }
+
+    fn update(&mut self, uri: &Value, text: Option<&str>) -> Vec<Value> {
+        let (Some(uri), Some(text)) = (uri.as_str(), text) else {
+            return Vec::new();
+        };
+        let src = SourceFile::new(uri, text.to_string());
+        let mut sink = LspSink::new(&src);
+        parse(src.lexer(), &mut sink);
+        let published = sink.publish(uri);
+        self.documents.insert(uri.to_string(), src.content);
+
+        vec![published]
+    }
+}
+
+/// Turns diagnostics into LSP ones as they come in, to go out together in a
+/// `publishDiagnostics` notification
+pub struct LspSink<'src> {
+    src: &'src SourceFile,
+    diagnostics: Vec<Value>,
+}
+
+impl<'src> LspSink<'src> {
+    pub fn new(src: &'src SourceFile) -> Self {
+        LspSink {
+            src,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// The notification replacing `uri`'s diagnostics with everything emitted
+    pub fn publish(self, uri: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": self.diagnostics },
+        })
+    }
+}
+
+impl DiagnosticSink for LspSink<'_> {
+    fn emit(&mut self, diag: Diagnostic) {
+        let text = &self.src.content;
+        let (message, span) = diag.describe(self.src);
+        // Errors without a span ran out of input, so point at the end
+        let (ix, len) = span.unwrap_or((text.len(), 0));
+        let end = (ix + len).min(text.len());
+        let severity = match diag.severity() {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        };
+        self.diagnostics.push(json!({
+            "range": { "start": position(self.src, ix), "end": position(self.src, end) },
+            "severity": severity,
+            "source": "toki",
+            "message": message,
+        }));
+    }
+}
+
+fn response(request: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": request["id"], "result": result })
+}
+
+fn position(src: &SourceFile, ix: usize) -> Value {
+    let line = src.line_index(ix);
+    let start = src.line_start(line).expect("Index is within the source");
+    let character: usize = src.content[start..ix].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+// The byte offset of an LSP position, if it's within the text
+fn offset(text: &str, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+
+    let start = match line {
+        0 => 0,
+        _ => text.match_indices('\n').nth(line - 1)?.0 + 1,
+    };
+    let mut units = 0;
+    for (ix, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Some(start + ix);
+        }
+        units += c.len_utf16();
+    }
+    Some(text.len())
+}
+
+fn hover(text: &str, at: &Value) -> Option<Value> {
+    let offset = offset(text, at)?;
+    let (ix, tok) = Lexer::new(text)
+        .tokenize_all_lossy()
+        .into_iter()
+        .find(|(ix, tok)| (*ix..ix + tok.src_len()).contains(&offset))?;
+    let Token::Ident(name) = tok else {
+        return None;
+    };
+
+    let src = SourceFile::new("", text.to_string());
+    let block = try_parse(src.lexer()).ok()?;
+    let mut declared = Vec::new();
+    annotations(&block, 0, &mut declared);
+    // The closest declaration above the hover, ignoring scopes
+    let (_, _, type_annotation) = declared
+        .into_iter()
+        .filter(|(at, declared, _)| *declared == name && *at <= ix)
+        .max_by_key(|(at, _, _)| *at)?;
+
+    Some(json!({
+        "contents": { "kind": "markdown", "value": format!("```toki\n{}: {}\n```", name, type_annotation) },
+        "range": { "start": position(&src, ix), "end": position(&src, ix + tok.src_len()) },
+    }))
+}
+
+// Every annotated name in `block` as `(where it's declared, name, type)`.
+// Parameters count as declared at the start of their function.
+fn annotations<'src>(block: &AstBlock<'src>, at: usize, declared: &mut Vec<(usize, &'src str, String)>) {
+    for stmt in &block.stmts {
+        let at = stmt.span.start.max(at);
+        match &stmt.node {
+            AstStmt::Assignment {
+                target: AstExpr::LitExpr(lit),
+                ..
+            }
+            | AstStmt::Let { name: lit, .. } => annotation(lit, at, declared),
+            AstStmt::FnDef(fn_def) => {
+                for param in &fn_def.args {
+                    annotation(&param.ident, at, declared);
+                }
+                annotations(&fn_def.body, at, declared);
+            }
+            AstStmt::StructDef { methods, .. } => {
+                for method in methods {
+                    for param in &method.args {
+                        annotation(&param.ident, at, declared);
+                    }
+                    annotations(&method.body, at, declared);
+                }
+            }
+            AstStmt::Match { arms, .. } => {
+                for arm in arms {
+                    annotations(&arm.body, at, declared);
+                }
+            }
+            AstStmt::Expr {
+                expr: AstExpr::ConditionalExpr(cond),
+                ..
+            } => {
+                annotations(&cond.if_block, at, declared);
+                for (_, block) in &cond.elif_branches {
+                    annotations(block, at, declared);
+                }
+                if let Some(AstExpr::BlockExpr(block)) = cond.else_block.as_deref() {
+                    annotations(block, at, declared);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn annotation<'src>(lit: &AstLiteral<'src>, at: usize, declared: &mut Vec<(usize, &'src str, String)>) {
+    if let AstLiteral::TypedIdent {
+        name: Spanned {
+            node: Token::Ident(name),
+            ..
+        },
+        type_annotation,
+    } = lit
+    {
+        declared.push((at, name, type_annotation.to_string()));
+    }
+}
+
+#[cfg(test)]
+fn frame(msg: Value) -> String {
+    let body = msg.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+#[test]
+fn test_lsp_session() {
+    let uri = "file:///test.toki";
+    let input = [
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "capabilities": {} } }),
+        json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "languageId": "toki", "version": 1, "text": "x: int = 1;\ny = x +;\n" } },
+        }),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "x: int = 1;\ny = x + 1;\n" }],
+            },
+        }),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/hover",
+            "params": { "textDocument": { "uri": uri }, "position": { "line": 1, "character": 4 } },
+        }),
+        json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }),
+        json!({ "jsonrpc": "2.0", "method": "exit" }),
+    ]
+    .map(frame)
+    .concat();
+
+    let mut output = Vec::new();
+    run(input.as_bytes(), &mut output).unwrap();
+
+    let mut output = output.as_slice();
+    let mut replies = Vec::new();
+    while let Some(msg) = read_message(&mut output).unwrap() {
+        replies.push(msg);
+    }
+    assert_eq!(replies.len(), 5);
+
+    assert_eq!(replies[0]["id"], 1);
+    assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+
+    // The broken version gets a diagnostic on the `;` after `+`
+    assert_eq!(replies[1]["method"], "textDocument/publishDiagnostics");
+    let diagnostics = replies[1]["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0]["range"],
+        json!({ "start": { "line": 1, "character": 7 }, "end": { "line": 1, "character": 8 } })
+    );
+    assert_eq!(diagnostics[0]["severity"], 1);
+
+    // And fixing it clears them
+    assert_eq!(replies[2]["params"]["diagnostics"], json!([]));
+
+    assert_eq!(replies[3]["id"], 2);
+    assert_eq!(replies[3]["result"]["contents"]["value"], "```toki\nx: int\n```");
+    assert_eq!(
+        replies[3]["result"]["range"]["start"],
+        json!({ "line": 1, "character": 4 })
+    );
+
+    assert_eq!(replies[4], json!({ "jsonrpc": "2.0", "id": 3, "result": null }));
+}
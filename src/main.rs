@@ -1,44 +1,170 @@
-pub mod ast;
-pub mod lexer;
-pub mod parser;
-pub mod reporter;
-pub mod token;
-
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Result, Write};
+use std::path::Path;
+
+use interp_rs::codegen::c::check_with_cc;
+use interp_rs::compile::{compile, CompileOptions, EmitKind};
+use interp_rs::diagnostics::{lint_with_sink, Diagnostic, DiagnosticSink, PrintSink};
+use interp_rs::highlight::generate_tmlanguage;
+use interp_rs::log::{self, Level};
+use interp_rs::lsp;
+use interp_rs::parser::{parse, ParseErr};
+use interp_rs::source::SourceFile;
+
+#[derive(Debug, Default)]
+struct CliArgs {
+    file: Option<String>,
+    // Read the source from stdin instead of `file`
+    stdin: bool,
+    lint: bool,
+    optimize: bool,
+    tokens: bool,
+    lsp: bool,
+    generate_grammar: bool,
+    // `-` writes to stdout
+    output_c: Option<String>,
+    output_c_check: bool,
+    // `-` writes to stdout
+    output_wasm_text: Option<String>,
+    force: bool,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> CliArgs {
+    let mut cli = CliArgs::default();
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lint" => cli.lint = true,
+            "--optimize" => cli.optimize = true,
+            "--tokens" => cli.tokens = true,
+            "--lsp" => cli.lsp = true,
+            "--generate-grammar" => cli.generate_grammar = true,
+            "--output-c" => cli.output_c = Some(args.next().expect("--output-c Requires A File Name")),
+            "--output-c-check" => cli.output_c_check = true,
+            "--output-wasm-text" => {
+                cli.output_wasm_text = Some(args.next().expect("--output-wasm-text Requires A File Name"))
+            }
+            "--force" => cli.force = true,
+            "--stdin" | "-" => cli.stdin = true,
+            _ => cli.file = Some(arg),
+        }
+    }
+    cli
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(1);
+}
+
+impl CliArgs {
+    fn compile_options(&self) -> CompileOptions {
+        let emit = if self.output_c.is_some() || self.output_c_check {
+            EmitKind::C
+        } else if self.output_wasm_text.is_some() {
+            EmitKind::WasmText
+        } else {
+            EmitKind::Ast
+        };
 
-use crate::lexer::Lexer;
-use crate::parser::parse;
-use crate::reporter::report;
+        CompileOptions {
+            optimization_level: self.optimize as u8,
+            emit,
+            ..CompileOptions::default()
+        }
+    }
+}
+
+fn emit(output: &str, options: &CompileOptions, cli: &CliArgs) {
+    match options.emit {
+        EmitKind::C => {
+            if cli.output_c_check {
+                match check_with_cc(output) {
+                    Ok(true) => eprintln!("Generated C Compiles"),
+                    Ok(false) => fail("Generated C Failed To Compile"),
+                    Err(e) => fail(&format!("Could Not Run C Compiler: {}", e)),
+                }
+            }
+            if let Some(path) = cli.output_c.as_deref() {
+                write_output(path, output, cli.force);
+            }
+        }
+        EmitKind::WasmText => {
+            let path = cli.output_wasm_text.as_deref().expect("Only chosen with a path");
+            write_output(path, output, cli.force);
+        }
+        EmitKind::Ast | EmitKind::Bytecode => println!("{}", output),
+    }
+}
+
+fn write_output(path: &str, contents: &str, force: bool) {
+    if path == "-" {
+        print!("{}", contents);
+        return;
+    }
+    if Path::new(path).exists() && !force {
+        fail(&format!("{} Already Exists (Pass --force To Overwrite)", path));
+    }
+    std::fs::write(path, contents).unwrap_or_else(|e| fail(&format!("Could Not Write {}: {}", path, e)));
+}
 
 fn main() {
-    let mut args = std::env::args();
-    args.next();
-
-    let file = args.next().expect("Must Provide A File Name");
-    let mut f = std::fs::File::open(&file).expect("Could Not Open File");
-    let mut src = String::new();
-    f.read_to_string(&mut src).expect("Couldn't Read String");
-
-    // for tok in Lexer::new(&src) {
-    //     if tok.is_ok() {
-    //         let (_, t) = tok.expect("");
-    //         print!("[{}]", t);
-    //     }
-    // }
-    // println!();
-
-    match report(parse(Lexer::new(&src)), &src) {
-        Ok(parsed) => {
-            println!("{}", parsed);
-            // let mut write_file = OpenOptions::new()
-            //     .write(true)
-            //     .truncate(true)
-            //     .open(&file)
-            //     .expect("Failed To Save");
-
-            // write_file.write_all(format!("{}", parsed).as_bytes());
+    log::init_from_env();
+    let cli = parse_args(std::env::args().skip(1));
+
+    if cli.generate_grammar {
+        println!("{}", generate_tmlanguage());
+        return;
+    }
+
+    if cli.lsp {
+        let stdin = std::io::stdin();
+        lsp::run(stdin.lock(), std::io::stdout()).unwrap_or_else(|e| fail(&format!("Language Server Failed: {}", e)));
+        return;
+    }
+
+    let src = if cli.stdin {
+        let mut content = String::new();
+        std::io::stdin()
+            .read_to_string(&mut content)
+            .unwrap_or_else(|e| fail(&format!("Could Not Read Stdin: {}", e)));
+        SourceFile::new("<stdin>", content)
+    } else {
+        let file = cli.file.as_deref().expect("Must Provide A File Name");
+        SourceFile::open(Path::new(file)).expect("Could Not Open File")
+    };
+    let file = src.path.display();
+
+    if cli.tokens {
+        match src.lexer().tokenize_all() {
+            Ok(toks) => {
+                for (_, t) in toks {
+                    print!("[{}]", t);
+                }
+                println!();
+            }
+            Err(e) => PrintSink { src: &src }.emit(Diagnostic::Parse(ParseErr::LexErr(e))),
+        }
+        return;
+    }
+
+    interp_rs::log!(Level::Info, "parsing {} ({} bytes)", file, src.content.len());
+    if cli.lint {
+        let mut sink = PrintSink { src: &src };
+        if let Some(parsed) = parse(src.lexer(), &mut sink) {
+            lint_with_sink(&parsed, &mut sink);
+        }
+        return;
+    }
+
+    let options = cli.compile_options();
+    match compile(&src, &options, &mut PrintSink { src: &src }) {
+        Ok(compiled) => {
+            for w in &compiled.warnings {
+                eprintln!("{}", w);
+            }
+            emit(&compiled.output, &options, &cli);
         }
-        Err(e) => println!("{}", e),
+        Err(e) => fail(&e.to_string()),
     }
 }
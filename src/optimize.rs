@@ -0,0 +1,660 @@
+use crate::ast::{
+    AstBinExpr, AstBlock, AstCallExpr, AstConditional, AstExpr, AstIndex, AstLiteral, AstSlice, AstStmt, AstStructInit,
+    AstUnaryExpr, AttrAccess, CallArg,
+};
+use crate::compile::CompileOptions;
+use crate::span::{Span, Spanned};
+use crate::token::{Operator, Token};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FoldReport {
+    /// Number of binary and unary expressions that were simplified
+    pub count: usize,
+    /// Number of AST nodes removed by those simplifications
+    pub saved_nodes: usize,
+}
+
+impl<'src> AstExpr<'src> {
+    /// Simplifies constant arithmetic and comparisons bottom-up, e.g. `2 + 3`
+    /// becomes `5` and `x * 1` becomes `x`.
+    pub fn fold_constants(expr: AstExpr<'src>) -> (AstExpr<'src>, FoldReport) {
+        let mut report = FoldReport::default();
+        let folded = fold_expr(expr, &mut report);
+        (folded, report)
+    }
+}
+
+pub fn fold_block<'src>(block: AstBlock<'src>, report: &mut FoldReport) -> AstBlock<'src> {
+    AstBlock {
+        stmts: block
+            .stmts
+            .into_iter()
+            .map(|s| s.map(|s| fold_stmt(s, report)))
+            .collect(),
+        ..block
+    }
+}
+
+fn fold_stmt<'src>(stmt: AstStmt<'src>, report: &mut FoldReport) -> AstStmt<'src> {
+    match stmt {
+        AstStmt::Expr { expr, has_semi } => AstStmt::Expr {
+            expr: fold_expr(expr, report),
+            has_semi,
+        },
+        AstStmt::Return(expr) => AstStmt::Return(fold_expr(expr, report)),
+        AstStmt::Assignment { target, assigned } => AstStmt::Assignment {
+            target,
+            assigned: fold_expr(assigned, report),
+        },
+        AstStmt::Let {
+            name,
+            mutable,
+            constant,
+            value,
+        } => AstStmt::Let {
+            name,
+            mutable,
+            constant,
+            value: fold_expr(value, report),
+        },
+        AstStmt::FnDef(mut fn_def) => {
+            fn_def.body = fold_block(fn_def.body, report);
+            AstStmt::FnDef(fn_def)
+        }
+        AstStmt::StructDef {
+            attributes,
+            name,
+            fields,
+            methods,
+        } => AstStmt::StructDef {
+            attributes,
+            name,
+            fields,
+            methods: methods
+                .into_iter()
+                .map(|mut m| {
+                    m.body = fold_block(m.body, report);
+                    m
+                })
+                .collect(),
+        },
+        AstStmt::Match { scrutinee, arms } => AstStmt::Match {
+            scrutinee: fold_expr(scrutinee, report),
+            arms: arms
+                .into_iter()
+                .map(|mut arm| {
+                    arm.body = fold_block(arm.body, report);
+                    arm
+                })
+                .collect(),
+        },
+    }
+}
+
+fn fold_expr<'src>(expr: AstExpr<'src>, report: &mut FoldReport) -> AstExpr<'src> {
+    match expr {
+        AstExpr::BinExpr(AstBinExpr { op, l, r }) => {
+            let l = fold_expr(*l, report);
+            let r = fold_expr(*r, report);
+
+            let before = 1 + node_count(&l) + node_count(&r);
+            match fold_bin_expr(&op, l, r) {
+                Ok(folded) => {
+                    report.count += 1;
+                    report.saved_nodes += before - node_count(&folded);
+                    folded
+                }
+                Err(operands) => {
+                    let (l, r) = *operands;
+                    (l, op, r).into()
+                }
+            }
+        }
+        AstExpr::UnaryExpr(AstUnaryExpr { op, expr }) => {
+            let expr = fold_expr(*expr, report);
+            match int_value(&expr).and_then(i32::checked_neg) {
+                Some(negated) if op == Operator::Sub => {
+                    report.count += 1;
+                    report.saved_nodes += 1;
+                    int_expr(negated)
+                }
+                _ => AstExpr::UnaryExpr(AstUnaryExpr {
+                    op,
+                    expr: Box::new(expr),
+                }),
+            }
+        }
+        AstExpr::NotExpr(expr) => {
+            let expr = fold_expr(*expr, report);
+            match literal_bool(&expr) {
+                Some(b) => {
+                    report.count += 1;
+                    report.saved_nodes += 1;
+                    bool_expr(!b)
+                }
+                None => AstExpr::NotExpr(Box::new(expr)),
+            }
+        }
+        AstExpr::ConditionalExpr(AstConditional {
+            condition,
+            if_block,
+            elif_branches,
+            else_block,
+            inline,
+        }) => AstExpr::ConditionalExpr(AstConditional {
+            condition: Box::new(fold_expr(*condition, report)),
+            if_block: fold_block(if_block, report),
+            elif_branches: elif_branches
+                .into_iter()
+                .map(|(c, b)| (fold_expr(c, report), fold_block(b, report)))
+                .collect(),
+            else_block: else_block.map(|e| Box::new(fold_expr(*e, report))),
+            inline,
+        }),
+        AstExpr::BlockExpr(block) => AstExpr::BlockExpr(fold_block(block, report)),
+        AstExpr::CallExpr(AstCallExpr {
+            called_expr,
+            args,
+            optional,
+        }) => AstExpr::CallExpr(AstCallExpr {
+            called_expr: Box::new(fold_expr(*called_expr, report)),
+            args: fold_args(args, report),
+            optional,
+        }),
+        AstExpr::AttrAccessExpr(AttrAccess {
+            attribute,
+            expr,
+            optional,
+        }) => AstExpr::AttrAccessExpr(AttrAccess {
+            attribute,
+            expr: Box::new(fold_expr(*expr, report)),
+            optional,
+        }),
+        AstExpr::IndexExpr(AstIndex { target, index }) => AstExpr::IndexExpr(AstIndex {
+            target: Box::new(fold_expr(*target, report)),
+            index: Box::new(fold_expr(*index, report)),
+        }),
+        AstExpr::SliceExpr(AstSlice { target, start, end }) => AstExpr::SliceExpr(AstSlice {
+            target: Box::new(fold_expr(*target, report)),
+            start: start.map(|e| Box::new(fold_expr(*e, report))),
+            end: end.map(|e| Box::new(fold_expr(*e, report))),
+        }),
+        AstExpr::StructInitExpr(AstStructInit { name, fields }) => AstExpr::StructInitExpr(AstStructInit {
+            name,
+            fields: fold_args(fields, report),
+        }),
+        AstExpr::FnDefExpr(mut fn_def) => {
+            fn_def.body = fold_block(fn_def.body, report);
+            AstExpr::FnDefExpr(fn_def)
+        }
+        lit @ AstExpr::LitExpr(_) => lit,
+    }
+}
+
+fn fold_args<'src>(args: Vec<CallArg<'src>>, report: &mut FoldReport) -> Vec<CallArg<'src>> {
+    args.into_iter()
+        .map(|a| CallArg {
+            expr: fold_expr(a.expr, report),
+            ..a
+        })
+        .collect()
+}
+
+type Operands<'src> = (AstExpr<'src>, AstExpr<'src>);
+
+fn fold_bin_expr<'src>(
+    op: &Operator,
+    l: AstExpr<'src>,
+    r: AstExpr<'src>,
+) -> std::result::Result<AstExpr<'src>, Box<Operands<'src>>> {
+    match (int_value(&l), int_value(&r)) {
+        (Some(a), Some(b)) => {
+            let folded = match op {
+                Operator::Add => a.checked_add(b).map(int_expr),
+                Operator::Sub => a.checked_sub(b).map(int_expr),
+                Operator::Mul => a.checked_mul(b).map(int_expr),
+                // Only fold exact division so we don't bake in truncation
+                Operator::Div => a
+                    .checked_rem(b)
+                    .filter(|&rem| rem == 0)
+                    .and(a.checked_div(b))
+                    .map(int_expr),
+                Operator::FloorDiv if b != 0 => a.checked_div(b).map(|q| {
+                    let rounds_up = a % b != 0 && (a < 0) != (b < 0);
+                    int_expr(if rounds_up { q - 1 } else { q })
+                }),
+                // Truncating and flooring remainders only agree on non-negatives
+                Operator::Mod if a >= 0 && b > 0 => Some(int_expr(a % b)),
+                Operator::FloorDiv | Operator::Mod => None,
+                Operator::Equals => Some(bool_expr(a == b)),
+                Operator::NotEquals => Some(bool_expr(a != b)),
+                Operator::And | Operator::Or => None,
+            };
+            folded.ok_or_else(|| Box::new((l, r)))
+        }
+        // Identities. An operand is only dropped when evaluating it can't
+        // have side effects.
+        (Some(0), _) if *op == Operator::Mul && is_pure(&r) => Ok(int_expr(0)),
+        (_, Some(0)) if *op == Operator::Mul && is_pure(&l) => Ok(int_expr(0)),
+        (Some(1), _) if *op == Operator::Mul => Ok(r),
+        (_, Some(1)) if *op == Operator::Mul => Ok(l),
+        (Some(0), _) if *op == Operator::Add => Ok(r),
+        (_, Some(0)) if *op == Operator::Add => Ok(l),
+        _ => Err(Box::new((l, r))),
+    }
+}
+
+fn int_value(expr: &AstExpr<'_>) -> Option<i32> {
+    match expr {
+        // Folding a suffixed literal would drop its type
+        AstExpr::LitExpr(AstLiteral::Int(Token::IntLiteral { value, suffix: None })) => i32::try_from(*value).ok(),
+        _ => None,
+    }
+}
+
+fn int_expr<'src>(i: i32) -> AstExpr<'src> {
+    AstLiteral::Int(Token::int(i)).into()
+}
+
+// There are no boolean literals, `true`/`false` are plain identifiers
+fn bool_expr<'src>(b: bool) -> AstExpr<'src> {
+    AstLiteral::from(Token::Ident(if b { "true" } else { "false" })).into()
+}
+
+fn is_pure(expr: &AstExpr<'_>) -> bool {
+    match expr {
+        // Defining a function doesn't run it
+        AstExpr::LitExpr(_) | AstExpr::FnDefExpr(_) => true,
+        AstExpr::BinExpr(bin) => is_pure(&bin.l) && is_pure(&bin.r),
+        AstExpr::UnaryExpr(unary) => is_pure(&unary.expr),
+        AstExpr::NotExpr(expr) => is_pure(expr),
+        AstExpr::AttrAccessExpr(aa) => is_pure(&aa.expr),
+        _ => false,
+    }
+}
+
+fn node_count(expr: &AstExpr<'_>) -> usize {
+    match expr {
+        AstExpr::BinExpr(bin) => 1 + node_count(&bin.l) + node_count(&bin.r),
+        AstExpr::UnaryExpr(unary) => 1 + node_count(&unary.expr),
+        AstExpr::NotExpr(expr) => 1 + node_count(expr),
+        AstExpr::AttrAccessExpr(aa) => 1 + node_count(&aa.expr),
+        AstExpr::IndexExpr(index) => 1 + node_count(&index.target) + node_count(&index.index),
+        AstExpr::SliceExpr(slice) => {
+            1 + node_count(&slice.target)
+                + slice
+                    .start
+                    .iter()
+                    .chain(&slice.end)
+                    .map(|e| node_count(e))
+                    .sum::<usize>()
+        }
+        AstExpr::CallExpr(call) => {
+            1 + node_count(&call.called_expr) + call.args.iter().map(|a| node_count(&a.expr)).sum::<usize>()
+        }
+        AstExpr::StructInitExpr(si) => 1 + si.fields.iter().map(|a| node_count(&a.expr)).sum::<usize>(),
+        _ => 1,
+    }
+}
+
+/// Runs every optimization pass over a parsed program, unless
+/// `optimization_level` is 0. Folding goes first so that conditions like
+/// `1 == 1` are literal by the time dead code is pruned.
+pub fn optimize<'src>(block: AstBlock<'src>, options: &CompileOptions) -> (AstBlock<'src>, Vec<DeadCodeWarning>) {
+    if options.optimization_level == 0 {
+        return (block, Vec::new());
+    }
+
+    let block = fold_block(block, &mut FoldReport::default());
+
+    let mut eliminator = DeadCodeEliminator::default();
+    let block = eliminator.eliminate(block);
+    (block, eliminator.warnings)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeadCodeWarning {
+    /// The code that was removed
+    pub span: Span,
+    pub reason: String,
+}
+
+impl std::fmt::Display for DeadCodeWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "warning: {}", self.reason)
+    }
+}
+
+#[derive(Default)]
+pub struct DeadCodeEliminator {
+    pub warnings: Vec<DeadCodeWarning>,
+}
+
+fn literal_bool(expr: &AstExpr<'_>) -> Option<bool> {
+    match expr {
+        AstExpr::LitExpr(AstLiteral::Ident(Spanned {
+            node: Token::Ident("true"),
+            ..
+        })) => Some(true),
+        AstExpr::LitExpr(AstLiteral::Ident(Spanned {
+            node: Token::Ident("false"),
+            ..
+        })) => Some(false),
+        _ => None,
+    }
+}
+
+fn branch_span(branch: &AstExpr<'_>, stmt_span: Span) -> Span {
+    match branch {
+        AstExpr::BlockExpr(block) => block.span,
+        _ => stmt_span,
+    }
+}
+
+impl DeadCodeEliminator {
+    pub fn eliminate<'src>(&mut self, block: AstBlock<'src>) -> AstBlock<'src> {
+        let mut stmts = Vec::new();
+        let mut rest = block.stmts.into_iter();
+
+        while let Some(stmt) = rest.next() {
+            let is_return = matches!(stmt.node, AstStmt::Return(_));
+
+            // `if false:` with nothing to fall back to can go entirely
+            if let AstStmt::Expr {
+                expr: AstExpr::ConditionalExpr(cond),
+                ..
+            } = &stmt.node
+            {
+                let is_last_branch = cond.elif_branches.is_empty() && cond.else_block.is_none();
+                if literal_bool(&cond.condition) == Some(false) && is_last_branch {
+                    self.warn(stmt.span, "`if false` branch can never execute".to_string());
+                    continue;
+                }
+            }
+
+            let span = stmt.span;
+            stmts.push(stmt.map(|s| self.eliminate_stmt(s, span)));
+
+            if is_return {
+                let unreachable: Vec<_> = rest.by_ref().map(|s| s.span).collect();
+                if let (Some(first), Some(last)) = (unreachable.first(), unreachable.last()) {
+                    self.warn(
+                        Span::new(first.start, last.end),
+                        format!("{} statement(s) after `return` can never execute", unreachable.len()),
+                    );
+                }
+                break;
+            }
+        }
+
+        AstBlock { stmts, ..block }
+    }
+
+    fn warn(&mut self, span: Span, reason: String) {
+        self.warnings.push(DeadCodeWarning { span, reason });
+    }
+
+    // `span` is the enclosing statement's, for branches that don't carry one
+    fn eliminate_stmt<'src>(&mut self, stmt: AstStmt<'src>, span: Span) -> AstStmt<'src> {
+        match stmt {
+            AstStmt::Expr { expr, has_semi } => AstStmt::Expr {
+                expr: self.eliminate_expr(expr, span),
+                has_semi,
+            },
+            AstStmt::Return(expr) => AstStmt::Return(self.eliminate_expr(expr, span)),
+            AstStmt::Assignment { target, assigned } => AstStmt::Assignment {
+                target,
+                assigned: self.eliminate_expr(assigned, span),
+            },
+            AstStmt::Let {
+                name,
+                mutable,
+                constant,
+                value,
+            } => AstStmt::Let {
+                name,
+                mutable,
+                constant,
+                value: self.eliminate_expr(value, span),
+            },
+            AstStmt::FnDef(mut fn_def) => {
+                fn_def.body = self.eliminate(fn_def.body);
+                AstStmt::FnDef(fn_def)
+            }
+            AstStmt::StructDef {
+                attributes,
+                name,
+                fields,
+                methods,
+            } => AstStmt::StructDef {
+                attributes,
+                name,
+                fields,
+                methods: methods
+                    .into_iter()
+                    .map(|mut m| {
+                        m.body = self.eliminate(m.body);
+                        m
+                    })
+                    .collect(),
+            },
+            AstStmt::Match { scrutinee, arms } => AstStmt::Match {
+                scrutinee: self.eliminate_expr(scrutinee, span),
+                arms: arms
+                    .into_iter()
+                    .map(|mut arm| {
+                        arm.body = self.eliminate(arm.body);
+                        arm
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    fn eliminate_expr<'src>(&mut self, expr: AstExpr<'src>, span: Span) -> AstExpr<'src> {
+        let cond = match expr {
+            AstExpr::ConditionalExpr(cond) => cond,
+            AstExpr::FnDefExpr(mut fn_def) => {
+                fn_def.body = self.eliminate(fn_def.body);
+                return AstExpr::FnDefExpr(fn_def);
+            }
+            expr => return expr,
+        };
+
+        // Branches after an `elif` aren't pruned yet
+        let condition = literal_bool(&cond.condition).filter(|_| cond.elif_branches.is_empty());
+        match (condition, cond.else_block) {
+            (Some(true), Some(else_block)) => {
+                self.warn(
+                    branch_span(&else_block, span),
+                    "`else` branch of `if true` can never execute".to_string(),
+                );
+                AstExpr::ConditionalExpr(AstConditional {
+                    if_block: self.eliminate(cond.if_block),
+                    else_block: None,
+                    ..cond
+                })
+            }
+            (Some(false), Some(else_block)) => {
+                self.warn(cond.if_block.span, "`if false` branch can never execute".to_string());
+                self.eliminate_expr(*else_block, span)
+            }
+            (_, else_block) => AstExpr::ConditionalExpr(AstConditional {
+                if_block: self.eliminate(cond.if_block),
+                elif_branches: cond
+                    .elif_branches
+                    .into_iter()
+                    .map(|(c, b)| (c, self.eliminate(b)))
+                    .collect(),
+                else_block: else_block.map(|e| Box::new(self.eliminate_expr(*e, span))),
+                ..cond
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+fn fold_src(src: &str) -> (AstExpr<'_>, FoldReport) {
+    let expr = crate::parser::parse_expression(crate::lexer::Lexer::new(src)).unwrap();
+    AstExpr::fold_constants(expr)
+}
+
+#[test]
+fn test_fold_arithmetic() {
+    let (folded, report) = fold_src("2 + 3 * 4");
+    assert_eq!(folded, int_expr(14));
+    assert_eq!(
+        report,
+        FoldReport {
+            count: 2,
+            saved_nodes: 4
+        }
+    );
+}
+
+#[test]
+fn test_fold_equality() {
+    let (folded, report) = fold_src("2 == 2");
+    assert_eq!(folded, bool_expr(true));
+    assert_eq!(
+        report,
+        FoldReport {
+            count: 1,
+            saved_nodes: 2
+        }
+    );
+}
+
+#[test]
+fn test_fold_identities() {
+    let x = || AstExpr::from(AstLiteral::from(Token::Ident("x")));
+
+    let (folded, report) = fold_src("0 + x * 1");
+    assert_eq!(folded, x());
+    assert_eq!(
+        report,
+        FoldReport {
+            count: 2,
+            saved_nodes: 4
+        }
+    );
+
+    let (folded, report) = fold_src("0 * x");
+    assert_eq!(folded, int_expr(0));
+    assert_eq!(
+        report,
+        FoldReport {
+            count: 1,
+            saved_nodes: 2
+        }
+    );
+}
+
+#[test]
+fn test_fold_keeps_side_effects_and_inexact_division() {
+    let (folded, report) = fold_src("0 * f()");
+    assert_eq!(folded.to_string(), "(0 * f())");
+    assert_eq!(report, FoldReport::default());
+
+    let (folded, report) = fold_src("7 / 2");
+    assert_eq!(folded.to_string(), "(7 / 2)");
+    assert_eq!(report.count, 0);
+
+    // i32::MIN / -1 overflows, so it's left for run time
+    let (folded, report) = fold_src("((0 - 2147483647) - 1) / (0 - 1)");
+    assert_eq!(folded.to_string(), "(-2147483648 / -1)");
+    assert_eq!(report.count, 3);
+}
+
+#[test]
+fn test_fold_negation() {
+    let (folded, report) = fold_src("x * -(2 + 3)");
+    assert_eq!(folded.to_string(), "(x * -5)");
+    assert_eq!(report.count, 2);
+
+    let (folded, _) = fold_src("-x");
+    assert_eq!(folded.to_string(), "-x");
+}
+
+#[test]
+fn test_fold_not() {
+    let (folded, report) = fold_src("not 1 == 2");
+    assert_eq!(folded, bool_expr(true));
+    assert_eq!(report.count, 2);
+
+    let (folded, _) = fold_src("not x");
+    assert_eq!(folded.to_string(), "not x");
+}
+
+#[test]
+fn test_fold_floor_div_and_mod() {
+    assert_eq!(fold_src("7 // 2").0, int_expr(3));
+    assert_eq!(fold_src("-7 // 2").0, int_expr(-4));
+    assert_eq!(fold_src("7 % 3").0, int_expr(1));
+
+    // C and wasm truncate, so negative remainders are left to run time
+    assert_eq!(fold_src("-7 % 2").0.to_string(), "(-7 % 2)");
+    assert_eq!(fold_src("1 // 0").0.to_string(), "(1 // 0)");
+}
+
+#[cfg(test)]
+fn eliminate_src(src: &str) -> (AstBlock<'_>, Vec<DeadCodeWarning>) {
+    let block = crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    let mut eliminator = DeadCodeEliminator::default();
+    let block = eliminator.eliminate(block);
+    (block, eliminator.warnings)
+}
+
+#[test]
+fn test_eliminate_after_return() {
+    let src = "return 1;\nx = 2;\ny = 3;\n";
+    let (block, warnings) = eliminate_src(src);
+    assert_eq!(block.to_string(), "return 1;\n");
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(&src[warnings[0].span.start..warnings[0].span.end], "x = 2;\ny = 3;");
+}
+
+#[test]
+fn test_eliminate_else_of_if_true() {
+    let src = "if true:\n    a;\nelse:\n    b;\n";
+    let (block, warnings) = eliminate_src(src);
+    let AstStmt::Expr {
+        expr: AstExpr::ConditionalExpr(cond),
+        ..
+    } = &block.stmts[0].node
+    else {
+        panic!("Expected conditional, got {:?}", block.stmts[0].node);
+    };
+    assert_eq!(cond.else_block, None);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(&src[warnings[0].span.start..warnings[0].span.end], "b;");
+}
+
+#[test]
+fn test_eliminate_if_false() {
+    let src = "if false:\n    a;\nb;\n";
+    let (block, warnings) = eliminate_src(src);
+    assert_eq!(block.to_string(), "b;\n");
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(
+        &src[warnings[0].span.start..warnings[0].span.end],
+        "if false:\n    a;\n"
+    );
+}
+
+#[test]
+fn test_optimize_folds_before_eliminating() {
+    let src = "if 1 == 1:\n    a;\nelse:\n    b;\n";
+    let parse = || crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    let options = CompileOptions {
+        optimization_level: 1,
+        ..CompileOptions::default()
+    };
+    let (block, warnings) = optimize(parse(), &options);
+    assert_eq!(block.to_string(), "if true:\n    a;\n\n");
+    assert_eq!(warnings.len(), 1);
+
+    let (block, warnings) = optimize(parse(), &CompileOptions::default());
+    assert_eq!(block, parse());
+    assert!(warnings.is_empty());
+}
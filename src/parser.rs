@@ -1,17 +1,25 @@
 use crate::ast::{
-    AstBinExpr, AstBlock, AstCallExpr, AstConditional, AstExpr, AstLiteral, AstNode, AstStmt, AttrAccess, CallArg,
-    FnDef, TypeAnnotation,
+    spanned_token, AstAttribute, AstBinExpr, AstBlock, AstCallExpr, AstConditional, AstExpr, AstIndex, AstLiteral,
+    AstNode, AstSlice, AstStmt, AstStructField, AstUnaryExpr, AttrAccess, CallArg, FnDef, FnParam, MatchArm, Pattern,
+    Pragma, Spread, TypeAnnotation,
 };
-use crate::lexer::{LexErr, Result as LexResult};
+use crate::diagnostics::{Diagnostic, DiagnosticSink};
+use crate::lexer::{LexErr, Lexer, Result as LexResult};
+use crate::log::{self, Level};
+use crate::span::{Span, Spanned};
 use crate::token::{Operator, SpannedToken, Token};
 use core::iter::Peekable;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Precedence {
     Lowest,
+    Or,
+    And,
     AddSub,
     MulDiv,
     Equality,
+    /// Prefix `-`, above every binary operator
+    Unary,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -21,41 +29,91 @@ pub enum ParseErr {
 
     UnexpectedEnd,
     UnexpectedIndent(usize, usize, usize),
-    UnexpectedStmt(usize, usize),
+    // Carries the span of the expression before it, which is missing its `;`
+    UnexpectedStmt(usize, usize, Span),
+    TrailingInput(usize, usize),
 
     ExpectedTypeAnnotation(usize, usize),
     UnexpectedMut(usize, usize),
 
     ExpectedNewline(usize, usize),
     ExpectedSemi(usize, usize),
-    ExpectedColon(usize, usize),
+    // Carries what the colon should have followed, e.g. "Function Signature"
+    ExpectedColon(usize, usize, &'static str),
     ExpectedFnName(usize, usize),
+    ExpectedDeclaration(usize, usize),
+    ExpectedPattern(usize, usize),
+
+    UnknownAttribute(usize, usize),
+    // A keyword used where a name was expected
+    ReservedKeyword(usize, usize, String),
+    // Blocks or expressions nested deeper than the stream's max depth
+    RecursionLimitExceeded(usize),
+    // The left side of `=` isn't a name, attribute or index
+    InvalidAssignmentTarget(usize, usize),
+    // A `*spread` after a keyword argument
+    SpreadAfterKeywordArg(usize, usize),
+    // Any argument after a `**spread`
+    SpreadAfterKeywordSpread(usize, usize),
+    // A `not` or `!` with nothing it could apply to
+    ExpectedExpressionAfterUnary(usize, usize),
 
     // TODO: Make this an &str once or &Token once lifetime is removed
     ExpectedToken(usize, usize, String),
+    // Where the `(` was opened, for a call whose line or input ended first
+    UnclosedParen(usize),
+    // The `:` of a block header that the input ended after
+    UnclosedBlock(Span),
+    // A `?.` or `?(` on an expression that can't be `nil`. Nothing raises this
+    // until there's a type checker to know which expressions can be.
+    OptionalChainOnNonNullable(Span),
+}
+
+impl ParseErr {
+    /// A stable code to look the error up by. Codes are never reused, so a
+    /// retired variant's code stays retired.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseErr::LexErr(err) => err.code(),
+            ParseErr::InvalidExpressionStart(..) => "E0001",
+            ParseErr::UnexpectedEnd => "E0002",
+            ParseErr::UnexpectedIndent(..) => "E0003",
+            ParseErr::UnexpectedStmt(..) => "E0004",
+            ParseErr::TrailingInput(..) => "E0005",
+            ParseErr::ExpectedTypeAnnotation(..) => "E0006",
+            ParseErr::UnexpectedMut(..) => "E0007",
+            ParseErr::ExpectedNewline(..) => "E0008",
+            ParseErr::ExpectedSemi(..) => "E0009",
+            ParseErr::ExpectedColon(..) => "E0010",
+            ParseErr::ExpectedFnName(..) => "E0011",
+            ParseErr::ExpectedDeclaration(..) => "E0012",
+            ParseErr::ExpectedPattern(..) => "E0013",
+            ParseErr::UnknownAttribute(..) => "E0014",
+            ParseErr::ReservedKeyword(..) => "E0015",
+            ParseErr::RecursionLimitExceeded(..) => "E0016",
+            ParseErr::InvalidAssignmentTarget(..) => "E0017",
+            ParseErr::SpreadAfterKeywordArg(..) => "E0018",
+            ParseErr::SpreadAfterKeywordSpread(..) => "E0019",
+            ParseErr::ExpectedToken(..) => "E0020",
+            ParseErr::UnclosedParen(..) => "E0021",
+            ParseErr::UnclosedBlock(..) => "E0022",
+            ParseErr::ExpectedExpressionAfterUnary(..) => "E0023",
+            ParseErr::OptionalChainOnNonNullable(..) => "E0024",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 struct ParseContext {
     can_parse_annotation: bool,
-    is_in_paren_block: bool,
 }
 
 impl ParseContext {
     fn new() -> Self {
         ParseContext {
             can_parse_annotation: true,
-            is_in_paren_block: false,
         }
     }
-    fn entering_parens(mut self: Self) -> Self {
-        self.is_in_paren_block = true;
-        self
-    }
-    fn exiting_parens(mut self: Self) -> Self {
-        self.is_in_paren_block = false;
-        self
-    }
     fn with_annotation_parsing(mut self: Self) -> Self {
         self.can_parse_annotation = true;
         self
@@ -70,7 +128,153 @@ type TokenIter<'src> = LexResult<SpannedToken<'src>>;
 
 type Result<T> = std::result::Result<T, ParseErr>;
 
-pub fn get_next_token<'src, I>(tokens: &mut Peekable<I>) -> Result<SpannedToken<'src>>
+/// How deeply blocks and expressions may nest before parsing gives up,
+/// rather than overflowing the stack on hostile input. Each `if` level counts
+/// twice (its condition expression and its block); 100 keeps an unoptimized
+/// build within a 2 MiB thread stack.
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
+/// The token stream every parse function reads from
+pub struct ParseStream<'src, I>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    tokens: Peekable<I>,
+    prev_end: usize,
+    depth: usize,
+    max_depth: usize,
+    // Comments passed over since the last `take_comments`
+    comments: Vec<&'src str>,
+}
+
+impl<'src, I> ParseStream<'src, I>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    pub fn new(tokens: I) -> Self {
+        ParseStream {
+            tokens: tokens.peekable(),
+            prev_end: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            comments: Vec::new(),
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Runs `f` one nesting level deeper, failing once past `max_depth`
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.deepen()?;
+        let res = f(self);
+        self.depth -= 1;
+        res
+    }
+
+    // Goes one nesting level deeper without coming back up. The caller
+    // restores `depth` itself, for nesting built in a loop rather than by
+    // recursion.
+    fn deepen(&mut self) -> Result<()> {
+        if self.depth >= self.max_depth {
+            let ix = match self.peek() {
+                Some(Ok((ix, _))) => *ix,
+                _ => 0,
+            };
+            return Err(ParseErr::RecursionLimitExceeded(ix));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub fn peek(&mut self) -> Option<&TokenIter<'src>> {
+        self.skip_comments();
+        self.tokens.peek()
+    }
+
+    // Comments are only kept where something asks for them, so everywhere
+    // else parses as if they were never lexed
+    fn skip_comments(&mut self) {
+        while let Some(Ok((_, Token::Comment(text)))) = self.tokens.peek() {
+            self.comments.push(text);
+            self.tokens.next();
+        }
+    }
+
+    /// The comments passed over since the last call
+    pub fn take_comments(&mut self) -> Vec<&'src str> {
+        self.skip_comments();
+        std::mem::take(&mut self.comments)
+    }
+
+    pub fn skip_newlines(&mut self) {
+        while matches!(self.peek(), Some(Ok((_, Token::Newline)))) {
+            self.next();
+        }
+    }
+
+    pub fn peek_skip_newlines(&mut self) -> Option<&TokenIter<'src>> {
+        self.skip_newlines();
+        self.peek()
+    }
+
+    /// Byte offset just past the last token taken from the stream
+    pub fn prev_end(&self) -> usize {
+        self.prev_end
+    }
+
+    pub fn eat(&mut self, expected: Token) -> Result<()> {
+        let (ix, tok) = get_next_token(self)?;
+        if tok == expected {
+            return Ok(());
+        }
+        Err(ParseErr::ExpectedToken(ix, tok.src_len(), expected.to_string()))
+    }
+
+    /// Eats the newline and indent starting the block whose header's `:` was
+    /// just eaten
+    pub fn open_block(&mut self) -> Result<()> {
+        let colon = Span::new(self.prev_end() - 1, self.prev_end());
+        let opened = self.eat(Token::Newline).and_then(|_| {
+            // Blank lines may come before the body
+            self.skip_newlines();
+            self.eat(Token::Indent)
+        });
+        match opened {
+            Err(ParseErr::UnexpectedEnd) => Err(ParseErr::UnclosedBlock(colon)),
+            opened => opened,
+        }
+    }
+
+    /// Eats the `:` ending a block's header, which comes `after` the rest of it
+    pub fn eat_colon(&mut self, after: &'static str) -> Result<()> {
+        let (ix, tok) = get_next_token(self)?;
+        if tok == Token::Colon {
+            return Ok(());
+        }
+        Err(ParseErr::ExpectedColon(ix, tok.src_len(), after))
+    }
+}
+
+impl<'src, I> Iterator for ParseStream<'src, I>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    type Item = TokenIter<'src>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.skip_comments();
+        let next = self.tokens.next();
+        if let Some(Ok((ix, tok))) = &next {
+            self.prev_end = ix + tok.src_len();
+        }
+        next
+    }
+}
+
+pub fn get_next_token<'src, I>(tokens: &mut ParseStream<'src, I>) -> Result<SpannedToken<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
@@ -81,21 +285,285 @@ where
     }
 }
 
-pub fn parse<'src, I>(tokens: I) -> Result<AstBlock<'src>>
+/// Entry point of the parser. Errors go to `sink`, and `None` means the parse
+/// couldn't go on; the parser stops at its first error, so that's any error
+/// for now.
+pub fn parse<'src, I>(tokens: I, sink: &mut dyn DiagnosticSink) -> Option<AstBlock<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    try_parse(tokens).map_err(|err| sink.emit(Diagnostic::Parse(err))).ok()
+}
+
+/// Like `parse`, but hands back the error instead of emitting it
+pub fn try_parse<'src, I>(tokens: I) -> Result<AstBlock<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    parse_with_max_depth(tokens, DEFAULT_MAX_DEPTH)
+}
+
+pub fn parse_with_max_depth<'src, I>(tokens: I, max_depth: usize) -> Result<AstBlock<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    let tokens = &mut ParseStream::new(tokens).with_max_depth(max_depth);
+    let pragmas = parse_pragmas(tokens);
+
+    let mut block = parse_block(tokens, 0)?;
+    block.pragmas = pragmas;
+    return Ok(block);
+}
+
+/// Parses tokens that were built up front rather than lexed, e.g. by a tool
+/// generating code or a test
+pub fn parse_tokens(tokens: Vec<SpannedToken<'_>>) -> Result<AstBlock<'_>> {
+    try_parse(tokens.into_iter().map(Ok))
+}
+
+/// Parses exactly one expression, e.g. for a calculator mode or a debugger's
+/// watch expressions. Anything but trailing newlines after it is an error.
+pub fn parse_expression<'src, I>(tokens: I) -> Result<AstExpr<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    let tokens = &mut ParseStream::new(tokens);
+    let expr = parse_expr(tokens, Precedence::Lowest, 0, ParseContext::new())?;
+
+    tokens.skip_newlines();
+    match tokens.next() {
+        None => Ok(expr),
+        Some(Ok((ix, tok))) => Err(ParseErr::TrailingInput(ix, tok.src_len())),
+        Some(Err(e)) => Err(ParseErr::LexErr(e)),
+    }
+}
+
+/// A change to source text: `delete_len` bytes at `offset` replaced by `insert`
+#[derive(Debug, Clone, Copy)]
+pub struct TextEdit<'a> {
+    pub offset: usize,
+    pub delete_len: usize,
+    pub insert: &'a str,
+}
+
+/// Parses `new_src`, which is `old_src` with `edit` applied, reusing as much
+/// of `old_ast` as it can. An edit inside one top-level `def` or `struct`
+/// only re-parses that definition; anything else falls back to a full parse.
+///
+/// The reused nodes still borrow from `old_src`, so both sources share `'src`.
+/// Nested blocks can't be re-parsed on their own, since the lexer only starts
+/// at the outermost indent level.
+pub fn reparse_incremental<'src>(
+    old_ast: AstBlock<'src>,
+    old_src: &'src str,
+    new_src: &'src str,
+    edit: TextEdit<'_>,
+) -> Result<AstBlock<'src>> {
+    debug_assert_eq!(old_src.len() - edit.delete_len + edit.insert.len(), new_src.len());
+    let full_parse = || try_parse(Lexer::new(new_src));
+
+    let edit_end = edit.offset + edit.delete_len;
+    let Some(ix) = old_ast
+        .stmts
+        .iter()
+        .position(|s| s.span.start < edit.offset && edit_end < s.span.end && is_definition(s))
+    else {
+        return full_parse();
+    };
+
+    let shift = edit.insert.len() as isize - edit.delete_len as isize;
+    let old_span = old_ast.stmts[ix].span;
+    let span = Span::new(old_span.start, old_span.end.saturating_add_signed(shift));
+    // The edit might have split the definition in two, or turned it into
+    // something that needs its neighbours to parse
+    let mut reparsed = match try_parse(Lexer::new(&new_src[span.start..span.end])) {
+        Ok(block) if block.stmts.len() == 1 && is_definition(&block.stmts[0]) => block,
+        _ => return full_parse(),
+    };
+    shift_block(&mut reparsed, span.start as isize);
+
+    let mut block = old_ast;
+    block.stmts[ix] = reparsed.stmts.remove(0);
+    for stmt in &mut block.stmts[ix + 1..] {
+        stmt.span = stmt.span.shifted(shift);
+        shift_stmt(&mut stmt.node, shift);
+    }
+    block.span.end = block.span.end.saturating_add_signed(shift);
+    Ok(block)
+}
+
+fn is_definition(stmt: &Spanned<AstStmt<'_>>) -> bool {
+    matches!(stmt.node, AstStmt::FnDef(_) | AstStmt::StructDef { .. })
+}
+
+// Moves every span in `block` by `by` bytes
+fn shift_block(block: &mut AstBlock<'_>, by: isize) {
+    block.span = block.span.shifted(by);
+    for stmt in &mut block.stmts {
+        stmt.span = stmt.span.shifted(by);
+        shift_stmt(&mut stmt.node, by);
+    }
+}
+
+fn shift_stmt(stmt: &mut AstStmt<'_>, by: isize) {
+    match stmt {
+        AstStmt::Expr { expr, .. } | AstStmt::Return(expr) => shift_expr(expr, by),
+        AstStmt::Let { name, value, .. } => {
+            shift_lit(name, by);
+            shift_expr(value, by);
+        }
+        AstStmt::Assignment { target, assigned } => {
+            shift_expr(target, by);
+            shift_expr(assigned, by);
+        }
+        AstStmt::FnDef(fn_def) => shift_fn_def(fn_def, by),
+        AstStmt::StructDef {
+            name, fields, methods, ..
+        } => {
+            shift_lit(name, by);
+            for default in fields.iter_mut().filter_map(|f| f.default.as_mut()) {
+                shift_expr(default, by);
+            }
+            for method in methods {
+                shift_fn_def(method, by);
+            }
+        }
+        AstStmt::Match { scrutinee, arms } => {
+            shift_expr(scrutinee, by);
+            for arm in arms {
+                shift_block(&mut arm.body, by);
+            }
+        }
+    }
+}
+
+fn shift_fn_def(fn_def: &mut FnDef<'_>, by: isize) {
+    shift_lit(&mut fn_def.name, by);
+    for default in fn_def.args.iter_mut().filter_map(|a| a.default.as_mut()) {
+        shift_expr(default, by);
+    }
+    shift_block(&mut fn_def.body, by);
+}
+
+// Only blocks and names carry spans, so this just finds those inside `expr`
+fn shift_expr(expr: &mut AstExpr<'_>, by: isize) {
+    match expr {
+        AstExpr::LitExpr(lit) => shift_lit(lit, by),
+        AstExpr::BinExpr(bin) => {
+            shift_expr(&mut bin.l, by);
+            shift_expr(&mut bin.r, by);
+        }
+        AstExpr::UnaryExpr(AstUnaryExpr { expr, .. }) | AstExpr::NotExpr(expr) => shift_expr(expr, by),
+        AstExpr::AttrAccessExpr(aa) => {
+            shift_lit(&mut aa.attribute, by);
+            shift_expr(&mut aa.expr, by);
+        }
+        AstExpr::ConditionalExpr(cond) => {
+            shift_expr(&mut cond.condition, by);
+            shift_block(&mut cond.if_block, by);
+            for (condition, block) in &mut cond.elif_branches {
+                shift_expr(condition, by);
+                shift_block(block, by);
+            }
+            if let Some(else_block) = &mut cond.else_block {
+                shift_expr(else_block, by);
+            }
+        }
+        AstExpr::BlockExpr(block) => shift_block(block, by),
+        AstExpr::CallExpr(call) => {
+            shift_expr(&mut call.called_expr, by);
+            for arg in &mut call.args {
+                shift_expr(&mut arg.expr, by);
+            }
+        }
+        AstExpr::StructInitExpr(si) => {
+            shift_lit(&mut si.name, by);
+            for field in &mut si.fields {
+                shift_expr(&mut field.expr, by);
+            }
+        }
+        AstExpr::IndexExpr(index) => {
+            shift_expr(&mut index.target, by);
+            shift_expr(&mut index.index, by);
+        }
+        AstExpr::SliceExpr(slice) => {
+            shift_expr(&mut slice.target, by);
+            for bound in slice.start.iter_mut().chain(&mut slice.end) {
+                shift_expr(bound, by);
+            }
+        }
+        AstExpr::FnDefExpr(fn_def) => shift_fn_def(fn_def, by),
+    }
+}
+
+fn shift_lit(lit: &mut AstLiteral<'_>, by: isize) {
+    if let AstLiteral::Ident(name) | AstLiteral::TypedIdent { name, .. } = lit {
+        name.span = name.span.shifted(by);
+    }
+}
+
+fn parse_pragmas<'src, I>(tokens: &mut ParseStream<'src, I>) -> Vec<Pragma<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    // Pragmas are only recognized before any code; anywhere else they're
+    // rejected as an invalid statement start
+    let mut pragmas = Vec::new();
+    while let Some(Ok((_, Token::Pragma(text)))) = tokens.peek_skip_newlines() {
+        pragmas.push(Pragma::new(text));
+        tokens.next();
+    }
+    pragmas
+}
+
+// Logs entry into `rule` along with the token it starts on
+fn log_rule<'src, I>(level: Level, rule: &str, tokens: &mut ParseStream<'src, I>, depth: usize)
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
-    // Entry point of the parser
-    let peekable_tokens = &mut tokens.peekable();
-    return Ok(parse_block(peekable_tokens, 0)?);
+    if !log::enabled(level) {
+        return;
+    }
+    match tokens.peek() {
+        Some(Ok((ix, tok))) => log::write(
+            level,
+            format_args!(
+                "{} depth={} token={:?} span={}..{}",
+                rule,
+                depth,
+                tok,
+                ix,
+                ix + tok.src_len()
+            ),
+        ),
+        Some(Err(e)) => log::write(level, format_args!("{} depth={} token=<{:?}>", rule, depth, e)),
+        None => log::write(level, format_args!("{} depth={} token=<eof>", rule, depth)),
+    }
+}
+
+pub fn parse_block<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<AstBlock<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    log_rule(Level::Debug, "parse_block", tokens, indent);
+    tokens.nested(|tokens| parse_block_from(tokens, indent, Vec::new()))
 }
 
-pub fn parse_block<'src, I>(tokens: &mut Peekable<I>, indent: usize) -> Result<AstBlock<'src>>
+// Continues a block whose leading statements have already been parsed
+fn parse_block_from<'src, I>(
+    tokens: &mut ParseStream<'src, I>,
+    indent: usize,
+    stmts: Vec<Spanned<AstStmt<'src>>>,
+) -> Result<AstBlock<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
-    let mut stmts = Vec::new();
-    let mut has_no_semi_expr = false;
+    let entry = tokens.prev_end();
+    let mut stmts = stmts;
+    let mut has_no_semi_expr = matches!(
+        stmts.last().map(|s| &s.node),
+        Some(AstStmt::Expr { has_semi: false, .. })
+    );
 
     loop {
         match tokens.peek() {
@@ -115,30 +583,41 @@ where
             }
             Some(Ok((ix, tok))) => {
                 if has_no_semi_expr {
-                    return Err(ParseErr::UnexpectedStmt(*ix, tok.src_len()));
+                    let prev = stmts.last().map(|s| s.span).unwrap_or_default();
+                    return Err(ParseErr::UnexpectedStmt(*ix, tok.src_len(), prev));
                 }
 
+                let start = *ix;
                 let stmt = parse_stmt(tokens, indent)?;
 
                 if matches!(stmt, AstStmt::Expr { has_semi: false, .. }) {
                     has_no_semi_expr = true;
                 }
 
-                stmts.push(stmt);
+                stmts.push(Spanned::new(stmt, Span::new(start, tokens.prev_end())));
             }
 
-            Some(Err(_)) => todo!(),
+            // Surfaces the lex error
+            Some(Err(_)) => {
+                get_next_token(tokens)?;
+            }
         };
     }
 
+    let span = match (stmts.first(), stmts.last()) {
+        (Some(first), Some(last)) => Span::new(first.span.start, last.span.end),
+        _ => Span::new(entry, entry),
+    };
     let block = AstBlock {
+        pragmas: Vec::new(),
         indent,
         stmts,
         has_semi: !has_no_semi_expr,
+        span,
     };
     Ok(block)
 }
-pub fn parse_stmt<'src, I>(tokens: &mut Peekable<I>, indent: usize) -> Result<AstStmt<'src>>
+pub fn parse_stmt<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<AstStmt<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
@@ -149,17 +628,22 @@ where
     // 4. A call statement some_fn();
     // 5. A call expression some_fn()
 
+    log_rule(Level::Trace, "parse_stmt", tokens, indent);
     let context = ParseContext::new();
     if matches!(tokens.peek(), Some(Ok((_, Token::Return)))) {
         tokens.next();
 
         let expr = parse_expr(tokens, Precedence::Lowest, indent, context)?;
 
-        eat(tokens, Token::Semicolon)?;
+        tokens.eat(Token::Semicolon)?;
 
         return Ok(AstStmt::Return(expr));
     }
 
+    if matches!(tokens.peek(), Some(Ok((_, Token::Hash)))) {
+        return parse_attributed_decl(tokens, indent);
+    }
+
     if matches!(tokens.peek(), Some(Ok((_, Token::Def)))) {
         return parse_fn_def(tokens, indent).map(|r| AstStmt::FnDef(r));
     }
@@ -168,152 +652,457 @@ where
         return Ok(parse_struct_def(tokens, indent)?);
     }
 
+    if matches!(tokens.peek(), Some(Ok((_, Token::Match)))) {
+        return parse_match(tokens, indent);
+    }
+
+    if matches!(tokens.peek(), Some(Ok((_, Token::Let | Token::Const)))) {
+        return parse_let(tokens, indent);
+    }
+
+    let start = match tokens.peek() {
+        Some(Ok((ix, _))) => *ix,
+        _ => tokens.prev_end(),
+    };
     let primary_expr = parse_primary_expr(tokens, indent, context)?;
+    parse_stmt_with(primary_expr, start, tokens, indent)
+}
+
+// Continues a statement whose first expression, starting at byte `start`, has
+// already been parsed
+fn parse_stmt_with<'src, I>(
+    primary_expr: AstExpr<'src>,
+    start: usize,
+    tokens: &mut ParseStream<'src, I>,
+    indent: usize,
+) -> Result<AstStmt<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    let context = ParseContext::new();
+    let expr = parse_expr_with(primary_expr, start, tokens, Precedence::Lowest, indent, context)?;
 
     if let Some(Ok((_, Token::Eq))) = tokens.peek() {
+        if !expr.is_lvalue() {
+            return Err(ParseErr::InvalidAssignmentTarget(start, tokens.prev_end() - start));
+        }
         tokens.next();
         let to_assign = parse_expr(tokens, Precedence::Lowest, indent, context)?;
 
-        eat(tokens, Token::Semicolon)?;
+        // A value ending in a block may leave out the `;`, which would
+        // otherwise sit alone on the line after the block
+        let has_semi_next = matches!(tokens.peek(), Some(Ok((_, Token::Semicolon))));
+        if has_semi_next || !to_assign.ends_in_block() {
+            tokens.eat(Token::Semicolon)?;
+        }
 
         return Ok(AstStmt::Assignment {
-            target: primary_expr,
+            target: expr,
             assigned: to_assign,
         });
     }
 
-    let expr = parse_expr_with(primary_expr, tokens, Precedence::Lowest, indent, context)?;
-
     let has_semi_next = matches!(tokens.peek(), Some(Ok((_, Token::Semicolon))));
     if has_semi_next {
-        eat(tokens, Token::Semicolon)?;
+        tokens.eat(Token::Semicolon)?;
     }
 
     let has_semi = expr_has_semi(&expr, has_semi_next);
     Ok(AstStmt::Expr { expr, has_semi })
 }
 
-fn parse_fn_args<'src, I>(tokens: &mut Peekable<I>) -> Result<Vec<AstLiteral<'src>>>
+// `let [mut] name[: type] = value;` or `const name[: type] = value;`
+fn parse_let<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<AstStmt<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    let (_, keyword) = get_next_token(tokens)?;
+    let constant = keyword == Token::Const;
+    let mutable = !constant && matches!(tokens.peek(), Some(Ok((_, Token::Mut))));
+    if mutable {
+        tokens.next();
+    }
+
+    let (ix, tok) = get_next_token(tokens)?;
+    if !matches!(tok, Token::Ident(_)) {
+        return Err(reserved_keyword(ix, &tok).unwrap_or(ParseErr::InvalidAssignmentTarget(ix, tok.src_len())));
+    }
+    let name = if matches!(tokens.peek(), Some(Ok((_, Token::Colon)))) {
+        AstLiteral::typed_ident(tok, ix, parse_annotation(tokens)?)
+    } else {
+        AstLiteral::ident(tok, ix)
+    };
+
+    tokens.eat(Token::Eq)?;
+    let value = parse_expr(tokens, Precedence::Lowest, indent, ParseContext::new())?;
+    // Same as for assignments, a value ending in a block may leave out the `;`
+    let has_semi_next = matches!(tokens.peek(), Some(Ok((_, Token::Semicolon))));
+    if has_semi_next || !value.ends_in_block() {
+        tokens.eat(Token::Semicolon)?;
+    }
+
+    Ok(AstStmt::Let {
+        name,
+        mutable,
+        constant,
+        value,
+    })
+}
+
+const BUILTIN_ATTRIBUTES: &[&str] = &["inline", "deprecated", "test"];
+
+fn parse_attributed_decl<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<AstStmt<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    let attributes = parse_attributes(tokens)?;
+
+    let (ix, tok) = match tokens.peek() {
+        Some(Ok((ix, tok))) => (*ix, tok),
+        Some(Err(_)) => return Err(get_next_token(tokens).unwrap_err()),
+        None => return Err(ParseErr::UnexpectedEnd),
+    };
+
+    match tok {
+        Token::Def => {
+            let mut fn_def = parse_fn_def(tokens, indent)?;
+            fn_def.attributes = attributes;
+            Ok(AstStmt::FnDef(fn_def))
+        }
+        Token::Struct => {
+            let mut struct_def = parse_struct_def(tokens, indent)?;
+            if let AstStmt::StructDef { attributes: attrs, .. } = &mut struct_def {
+                *attrs = attributes;
+            }
+            Ok(struct_def)
+        }
+        _ => Err(ParseErr::ExpectedDeclaration(ix, tok.src_len())),
+    }
+}
+
+fn parse_attributes<'src, I>(tokens: &mut ParseStream<'src, I>) -> Result<Vec<AstAttribute<'src>>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    let mut attributes = Vec::new();
+
+    while matches!(tokens.peek(), Some(Ok((_, Token::Hash)))) {
+        tokens.eat(Token::Hash)?;
+        tokens.eat(Token::LSquareBrace)?;
+
+        let (ix, tok) = get_next_token(tokens)?;
+        let name = match tok {
+            Token::Ident(name) if BUILTIN_ATTRIBUTES.contains(&name) => name,
+            _ => return Err(reserved_keyword(ix, &tok).unwrap_or(ParseErr::UnknownAttribute(ix, tok.src_len()))),
+        };
+
+        let mut args = Vec::new();
+        if matches!(tokens.peek(), Some(Ok((_, Token::LParen)))) {
+            tokens.eat(Token::LParen)?;
+            let ctx = ParseContext::new();
+            while !matches!(tokens.peek(), Some(Ok((_, Token::RParen)))) {
+                args.push(parse_expr(tokens, Precedence::Lowest, 0, ctx)?);
+                if !matches!(tokens.peek(), Some(Ok((_, Token::Comma)))) {
+                    break;
+                }
+                tokens.eat(Token::Comma)?;
+            }
+            tokens.eat(Token::RParen)?;
+        }
+
+        tokens.eat(Token::RSquareBrace)?;
+        tokens.eat(Token::Newline)?;
+
+        attributes.push(AstAttribute { name, args });
+    }
+
+    Ok(attributes)
+}
+
+fn parse_fn_args<'src, I>(tokens: &mut ParseStream<'src, I>) -> Result<Vec<FnParam<'src>>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
     let mut args = Vec::new();
 
     loop {
+        if let Some(Ok((ix, tok))) = tokens.peek() {
+            if let Some(err) = reserved_keyword(*ix, tok) {
+                return Err(err);
+            }
+        }
         if !matches!(tokens.peek(), Some(Ok((_, Token::Ident(_))))) {
             break;
         }
 
-        let (_, tok) = get_next_token(tokens)?;
+        let (ix, tok) = get_next_token(tokens)?;
+        let ident = AstLiteral::typed_ident(tok, ix, parse_annotation(tokens)?);
 
-        args.push(AstLiteral::TypedIdent {
-            name: tok,
-            type_annotation: parse_annotation(tokens)?,
-        });
+        let default = if matches!(tokens.peek(), Some(Ok((_, Token::Eq)))) {
+            tokens.next();
+            let ctx = ParseContext::new().without_annotation_parsing();
+            Some(parse_expr(tokens, Precedence::Lowest, 0, ctx)?)
+        } else {
+            None
+        };
+
+        args.push(FnParam { ident, default });
 
         if matches!(tokens.peek(), Some(Ok((_, Token::Comma)))) {
             tokens.next();
         }
     }
-    eat(tokens, Token::RParen)?;
+    tokens.eat(Token::RParen)?;
     Ok(args)
 }
 
-pub fn parse_struct_def<'src, I>(tokens: &mut Peekable<I>, indent: usize) -> Result<AstStmt<'src>>
+pub fn parse_struct_def<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<AstStmt<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
-    eat(tokens, Token::Struct)?;
+    // Anything from before the struct isn't about its first field
+    tokens.take_comments();
+    tokens.eat(Token::Struct)?;
 
     let (ix, struct_name) = get_next_token(tokens)?;
     if !matches!(struct_name, Token::Ident(_)) {
-        return Err(ParseErr::ExpectedFnName(ix, struct_name.src_len()));
+        return Err(reserved_keyword(ix, &struct_name).unwrap_or(ParseErr::ExpectedFnName(ix, struct_name.src_len())));
     }
-    let name = AstLiteral::Ident(struct_name);
+    let name = AstLiteral::ident(struct_name, ix);
 
-    eat(tokens, Token::Colon)?;
-    eat(tokens, Token::Newline)?;
-    eat(tokens, Token::Indent)?;
+    tokens.eat_colon("Struct Name")?;
+    tokens.open_block()?;
 
     let fields = parse_struct_fields(tokens)?;
-    skip_newlines(tokens);
+    tokens.skip_newlines();
 
-    let methods = parse_struct_methods(tokens)?;
+    let methods = parse_struct_methods(tokens, indent + 1)?;
 
-    skip_newlines(tokens);
-    eat(tokens, Token::Dedent)?;
+    tokens.skip_newlines();
+    tokens.eat(Token::Dedent)?;
 
-    Ok(AstStmt::StructDef { name, fields, methods })
+    Ok(AstStmt::StructDef {
+        attributes: Vec::new(),
+        name,
+        fields,
+        methods,
+    })
 }
 
-pub fn skip_newlines<'src, I>(tokens: &mut Peekable<I>)
-where
-    I: Iterator<Item = TokenIter<'src>>,
-{
-    while matches!(tokens.peek(), Some(Ok((_, Token::Newline)))) {
-        // Skip any newlines after the struct
-        tokens.next();
-    }
-}
-pub fn parse_struct_fields<'src, I>(tokens: &mut Peekable<I>) -> Result<Vec<AstLiteral<'src>>>
+fn parse_match<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<AstStmt<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
-    let mut fields = Vec::new();
-    while matches!(tokens.peek(), Some(Ok((_, Token::Ident(_))))) {
-        let (_, name) = get_next_token(tokens)?;
-        let type_annotation = parse_annotation(tokens)?;
-        fields.push(AstLiteral::TypedIdent { name, type_annotation });
-        eat(tokens, Token::Newline)?;
+    tokens.eat(Token::Match)?;
+
+    let ctx = ParseContext::new().without_annotation_parsing();
+    let scrutinee = parse_expr(tokens, Precedence::Lowest, indent, ctx)?;
+
+    tokens.eat_colon("Match Subject")?;
+    tokens.open_block()?;
+
+    let mut arms = Vec::new();
+    loop {
+        tokens.skip_newlines();
+        match tokens.peek() {
+            Some(Ok((_, Token::Dedent))) | None if !arms.is_empty() => break,
+            _ => {}
+        }
+
+        tokens.eat(Token::Case)?;
+        let pattern = parse_pattern(tokens)?;
+
+        tokens.eat_colon("Case Pattern")?;
+        tokens.open_block()?;
+        let body = parse_block(tokens, indent + 2)?;
+
+        arms.push(MatchArm { pattern, body });
     }
-    Ok(fields)
+    tokens.next();
+
+    Ok(AstStmt::Match { scrutinee, arms })
 }
 
-pub fn parse_struct_methods<'src, I>(tokens: &mut Peekable<I>) -> Result<Vec<FnDef<'src>>>
+fn parse_pattern<'src, I>(tokens: &mut ParseStream<'src, I>) -> Result<Pattern<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
-    let mut methods = Vec::new();
-    while matches!(tokens.peek(), Some(Ok((_, Token::Def)))) {
-        methods.push(parse_fn_def(tokens, 0)?);
-    }
-    Ok(methods)
+    let (ix, tok) = get_next_token(tokens)?;
+    Ok(match tok {
+        Token::Ident("_") => Pattern::Wildcard,
+        Token::Ident(_) => Pattern::Binding(spanned_token(tok, ix)),
+        Token::IntLiteral { .. } => Pattern::Literal(AstLiteral::Int(tok)),
+        Token::StrLiteral { .. } => Pattern::Literal(AstLiteral::Str(tok)),
+        _ => return Err(reserved_keyword(ix, &tok).unwrap_or(ParseErr::ExpectedPattern(ix, tok.src_len()))),
+    })
 }
 
-pub fn parse_fn_def<'src, I>(tokens: &mut Peekable<I>, indent: usize) -> Result<FnDef<'src>>
+pub fn parse_struct_fields<'src, I>(tokens: &mut ParseStream<'src, I>) -> Result<Vec<AstStructField<'src>>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    let mut fields = Vec::new();
+    loop {
+        // `def` starts the methods
+        match tokens.peek() {
+            Some(Ok((_, Token::Ident(_)))) => {}
+            Some(Ok((ix, tok))) if *tok != Token::Def => match reserved_keyword(*ix, tok) {
+                Some(err) => return Err(err),
+                None => break,
+            },
+            _ => break,
+        }
+
+        let comments = tokens.take_comments();
+        let (_, name) = get_next_token(tokens)?;
+        let type_annotation = parse_annotation(tokens)?;
+
+        let default = if matches!(tokens.peek(), Some(Ok((_, Token::Eq)))) {
+            tokens.next();
+            let ctx = ParseContext::new().without_annotation_parsing();
+            Some(parse_expr(tokens, Precedence::Lowest, 0, ctx)?)
+        } else {
+            None
+        };
+
+        fields.push(AstStructField {
+            comments,
+            name,
+            type_annotation,
+            default,
+        });
+        tokens.eat(Token::Newline)?;
+    }
+    Ok(fields)
+}
+
+pub fn parse_struct_methods<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<Vec<FnDef<'src>>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    let mut methods = Vec::new();
+    loop {
+        let attributes = parse_attributes(tokens)?;
+        if !matches!(tokens.peek(), Some(Ok((_, Token::Def)))) {
+            if let Some(Ok((ix, tok))) = tokens.peek().filter(|_| !attributes.is_empty()) {
+                return Err(ParseErr::ExpectedDeclaration(*ix, tok.src_len()));
+            }
+            break;
+        }
+
+        let mut method = parse_fn_def(tokens, indent)?;
+        method.attributes = attributes;
+        methods.push(method);
+    }
+    Ok(methods)
+}
+
+pub fn parse_fn_def<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<FnDef<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    log_rule(Level::Debug, "parse_fn_def", tokens, indent);
+    tokens.eat(Token::Def)?;
+    parse_fn_def_after_def(tokens, indent)
+}
+
+// Everything in a definition after the `def` keyword
+fn parse_fn_def_after_def<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<FnDef<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
-    eat(tokens, Token::Def)?;
     let (ix, fn_name) = get_next_token(tokens)?;
 
     if !matches!(fn_name, Token::Ident(_)) {
-        return Err(ParseErr::ExpectedFnName(ix, fn_name.src_len()));
+        return Err(reserved_keyword(ix, &fn_name).unwrap_or(ParseErr::ExpectedFnName(ix, fn_name.src_len())));
     }
-    let name = AstLiteral::Ident(fn_name);
+    let name = AstLiteral::ident(fn_name, ix);
 
-    eat(tokens, Token::LParen)?;
+    let type_params = if matches!(tokens.peek(), Some(Ok((_, Token::LSquareBrace)))) {
+        parse_fn_type_params(tokens)?
+    } else {
+        Vec::new()
+    };
+
+    tokens.eat(Token::LParen)?;
 
     let args = parse_fn_args(tokens)?;
 
-    eat(tokens, Token::Arrow)?;
-    let return_type = parse_type_decl(tokens, true)?;
+    let return_type = if matches!(tokens.peek(), Some(Ok((_, Token::Arrow)))) {
+        tokens.next();
+        parse_type_decl(tokens, true)?
+    } else {
+        TypeAnnotation::Unit
+    };
 
-    eat(tokens, Token::Colon)?;
-    eat(tokens, Token::Newline)?;
-    eat(tokens, Token::Indent)?;
+    tokens.eat_colon("Function Signature")?;
+    tokens.open_block()?;
 
-    let body = parse_block(tokens, indent + 1)?;
+    let (docstring, body) = parse_fn_body(tokens, indent + 1)?;
 
     return Ok(FnDef {
+        attributes: Vec::new(),
+        docstring,
         name,
+        type_params,
         args,
         body,
         return_type,
     });
 }
 
-fn parse_type_decl<'src, I>(tokens: &mut Peekable<I>, can_be_mut: bool) -> Result<TypeAnnotation<'src>>
+fn parse_fn_body<'src, I>(
+    tokens: &mut ParseStream<'src, I>,
+    indent: usize,
+) -> Result<(Option<Token<'src>>, AstBlock<'src>)>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    if !matches!(tokens.peek(), Some(Ok((_, Token::StrLiteral { .. })))) {
+        return Ok((None, parse_block(tokens, indent)?));
+    }
+
+    // A string literal alone on the body's first line is a docstring. Otherwise
+    // it's just the start of the first statement.
+    let (ix, tok) = get_next_token(tokens)?;
+    if matches!(tok, Token::StrLiteral { .. })
+        && matches!(tokens.peek(), None | Some(Ok((_, Token::Newline | Token::Dedent))))
+    {
+        return Ok((Some(tok), parse_block(tokens, indent)?));
+    }
+
+    let first_stmt = parse_stmt_with(AstLiteral::Str(tok).into(), ix, tokens, indent)?;
+    let first_stmt = Spanned::new(first_stmt, Span::new(ix, tokens.prev_end()));
+    Ok((None, parse_block_from(tokens, indent, vec![first_stmt])?))
+}
+
+fn parse_fn_type_params<'src, I>(tokens: &mut ParseStream<'src, I>) -> Result<Vec<&'src str>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    // Type params are plain names for now. Annotations referring to them
+    // (`x: T`) already parse as `TypeAnnotation::Dynamic`.
+    tokens.eat(Token::LSquareBrace)?;
+
+    let mut type_params = Vec::new();
+    loop {
+        let (ix, tok) = get_next_token(tokens)?;
+        match tok {
+            Token::Ident(name) => type_params.push(name),
+            _ => return Err(ParseErr::ExpectedTypeAnnotation(ix, tok.src_len())),
+        }
+
+        if !matches!(tokens.peek(), Some(Ok((_, Token::Comma)))) {
+            break;
+        }
+        tokens.eat(Token::Comma)?;
+    }
+
+    tokens.eat(Token::RSquareBrace)?;
+    Ok(type_params)
+}
+
+fn parse_type_decl<'src, I>(tokens: &mut ParseStream<'src, I>, can_be_mut: bool) -> Result<TypeAnnotation<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
@@ -327,6 +1116,11 @@ where
 
         tokens.next();
         is_mut = true;
+
+        // `mut mut T` would otherwise fail below as a missing type name
+        if let Some(Ok((ix, tok @ Token::Mut))) = tokens.peek() {
+            return Err(ParseErr::UnexpectedMut(*ix, tok.src_len()));
+        }
     }
 
     let mut lhs = parse_type_name(tokens)?;
@@ -336,7 +1130,7 @@ where
             break;
         }
 
-        eat(tokens, Token::LSquareBrace)?;
+        tokens.eat(Token::LSquareBrace)?;
 
         let can_inner_be_mut = can_be_mut && !is_mut;
         let params = parse_type_params(tokens, can_inner_be_mut)?;
@@ -354,7 +1148,7 @@ where
     Ok(type_annotation)
 }
 
-fn parse_type_params<'src, I>(tokens: &mut Peekable<I>, can_be_mut: bool) -> Result<Vec<TypeAnnotation<'src>>>
+fn parse_type_params<'src, I>(tokens: &mut ParseStream<'src, I>, can_be_mut: bool) -> Result<Vec<TypeAnnotation<'src>>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
@@ -362,16 +1156,16 @@ where
     params.push(parse_type_decl(tokens, can_be_mut)?);
 
     while let Some(Ok((_, Token::Comma))) = tokens.peek() {
-        eat(tokens, Token::Comma)?;
+        tokens.eat(Token::Comma)?;
         params.push(parse_type_decl(tokens, can_be_mut)?);
     }
 
-    eat(tokens, Token::RSquareBrace)?;
+    tokens.eat(Token::RSquareBrace)?;
 
     Ok(params)
 }
 
-fn parse_type_name<'src, I>(tokens: &mut Peekable<I>) -> Result<TypeAnnotation<'src>>
+fn parse_type_name<'src, I>(tokens: &mut ParseStream<'src, I>) -> Result<TypeAnnotation<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
@@ -395,11 +1189,18 @@ pub fn expr_has_semi(expr: &AstExpr<'_>, has_semi_next: bool) -> bool {
                 if_block.has_semi
             }
         }
+        // Calls and attribute accesses end on the line they're written on, so
+        // like any other expression they only have a semi if one follows
+        AstExpr::CallExpr(_) | AstExpr::AttrAccessExpr(_) => has_semi_next,
         _ => has_semi_next,
     }
 }
 
-fn parse_primary_expr<'src, I>(tokens: &mut Peekable<I>, indent: usize, context: ParseContext) -> Result<AstExpr<'src>>
+fn parse_primary_expr<'src, I>(
+    tokens: &mut ParseStream<'src, I>,
+    indent: usize,
+    context: ParseContext,
+) -> Result<AstExpr<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
@@ -407,27 +1208,57 @@ where
 
     let expr = match tok {
         Token::LParen => {
-            if matches!(tokens.peek(), Some(Ok((_, Token::Newline)))) {
-                return parse_expr(tokens, Precedence::Lowest, indent, context);
-            }
-            return parse_expr(tokens, Precedence::Lowest, indent, context);
+            let inner = parse_expr(tokens, Precedence::Lowest, indent, context)?;
+            tokens.eat(Token::RParen)?;
+            return Ok(inner);
         }
         Token::If => return Ok(parse_conditional(tokens, indent)?.into()),
+        Token::Do => {
+            tokens.eat_colon("`do`")?;
+            tokens.open_block()?;
+            return Ok(AstExpr::BlockExpr(parse_block(tokens, indent + 1)?));
+        }
+        Token::Def => return Ok(AstExpr::FnDefExpr(Box::new(parse_fn_def_after_def(tokens, indent)?))),
+        // Binds tighter than any binary operator, so `-a.b * c` is `(-(a.b)) * c`
+        Token::Sub => {
+            let ctx = context.without_annotation_parsing();
+            let expr = parse_expr(tokens, Precedence::Unary, indent, ctx)?;
+            return Ok(AstUnaryExpr {
+                op: Operator::Sub,
+                expr: Box::new(expr),
+            }
+            .into());
+        }
+        // Binds looser than comparisons but tighter than `and`/`or`, so
+        // `not a == b and c` is `(not (a == b)) and c`
+        Token::Not | Token::Bang => {
+            // An operand that doesn't start an expression is blamed on the operator
+            let operand_ix = match tokens.peek() {
+                Some(Ok((operand_ix, _))) => Some(*operand_ix),
+                _ => None,
+            };
+            let missing_operand = ParseErr::ExpectedExpressionAfterUnary(ix, tok.src_len());
+            let ctx = context.without_annotation_parsing();
+            let expr = parse_expr(tokens, Precedence::AddSub, indent, ctx).map_err(|err| match err {
+                ParseErr::InvalidExpressionStart(at, _) if Some(at) == operand_ix => missing_operand,
+                ParseErr::UnexpectedEnd if operand_ix.is_none() => missing_operand,
+                err => err,
+            })?;
+            return Ok(AstExpr::NotExpr(Box::new(expr)));
+        }
         id @ Token::Ident(_) => {
             if matches!(tokens.peek(), Some(Ok((_, Token::Colon)))) && context.can_parse_annotation {
-                AstLiteral::TypedIdent {
-                    name: id,
-                    type_annotation: parse_annotation(tokens)?,
-                }
+                AstLiteral::typed_ident(id, ix, parse_annotation(tokens)?)
             } else {
-                AstLiteral::Ident(id)
+                AstLiteral::ident(id, ix)
             }
         }
-        il @ Token::IntLiteral(_) => AstLiteral::Int(il),
-        sl @ Token::StrLiteral(_) => AstLiteral::Str(sl),
-        // fl @ Token::FloatLiteral(_) => AstLiteral::Str(sl).into(),
+        il @ Token::IntLiteral { .. } => AstLiteral::Int(il),
+        sl @ Token::StrLiteral { .. } => AstLiteral::Str(sl),
+        // TODO: Floats need their own `AstLiteral` once the lexer produces `Token::FloatLiteral`
         ref x => {
-            println!("Encountered Invalid Expression Start: {}", x);
+            // Not stdout, which is the protocol channel under `--lsp`
+            crate::log!(Level::Debug, "invalid expression start {}", x);
             return Err(ParseErr::InvalidExpressionStart(ix, tok.src_len()));
         }
     };
@@ -435,7 +1266,7 @@ where
     Ok(parse_postfix_expr(expr.into(), tokens)?)
 }
 
-fn parse_conditional<'src, I>(tokens: &mut Peekable<I>, indent: usize) -> Result<AstConditional<'src>>
+fn parse_conditional<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<AstConditional<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
@@ -444,31 +1275,37 @@ where
     let cond_expr = parse_expr(tokens, Precedence::Lowest, indent, ctx)?;
     let condition = Box::new(cond_expr);
 
-    let (ix, tok) = get_next_token(tokens)?;
-
-    if !matches!(tok, Token::Colon) {
-        return Err(ParseErr::ExpectedColon(ix, tok.src_len()));
+    tokens.eat_colon("Condition")?;
+
+    let inline = !matches!(tokens.peek(), Some(Ok((_, Token::Newline))) | None);
+    if inline {
+        // `if x: stmt` has a single statement for a body, and no `else`
+        return Ok(AstConditional {
+            condition,
+            if_block: parse_inline_block(tokens, indent + 1)?,
+            elif_branches: Vec::new(),
+            else_block: None,
+            inline,
+        });
     }
 
-    if let Some(Ok((_, Token::Newline))) = tokens.peek() {
-        tokens.next();
-        eat(tokens, Token::Indent)?;
-    }
+    tokens.open_block()?;
     let if_block = parse_block(tokens, indent + 1)?;
 
+    let elif_branches = parse_elif_branches(tokens, indent)?;
+
     let else_block = if matches!(tokens.peek(), Some(Ok((_, Token::Else)))) {
         // Consume 'else'
         tokens.next();
 
         let (ix, tok) = get_next_token(tokens)?;
         let expr = match tok {
-            Token::If => AstExpr::ConditionalExpr(parse_conditional(tokens, indent)?),
+            Token::If => AstExpr::ConditionalExpr(tokens.nested(|tokens| parse_conditional(tokens, indent))?),
             Token::Colon => {
-                eat(tokens, Token::Newline)?;
-                eat(tokens, Token::Indent)?;
+                tokens.open_block()?;
                 AstExpr::BlockExpr(parse_block(tokens, indent + 1)?)
             }
-            _ => return Err(ParseErr::ExpectedColon(ix, tok.src_len())),
+            _ => return Err(ParseErr::ExpectedColon(ix, tok.src_len(), "`else`")),
         };
 
         Some(Box::new(expr))
@@ -479,12 +1316,93 @@ where
     Ok(AstConditional {
         condition,
         if_block,
+        elif_branches,
         else_block,
+        inline,
+    })
+}
+
+fn parse_elif_branches<'src, I>(
+    tokens: &mut ParseStream<'src, I>,
+    indent: usize,
+) -> Result<Vec<(AstExpr<'src>, AstBlock<'src>)>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    let ctx = ParseContext::new().without_annotation_parsing();
+    let mut branches = Vec::new();
+    while matches!(tokens.peek(), Some(Ok((_, Token::Elif)))) {
+        tokens.next();
+        let condition = parse_expr(tokens, Precedence::Lowest, indent, ctx)?;
+        tokens.eat_colon("Condition")?;
+        tokens.open_block()?;
+        branches.push((condition, parse_block(tokens, indent + 1)?));
+    }
+    Ok(branches)
+}
+
+// A block of one statement on the current line
+fn parse_inline_block<'src, I>(tokens: &mut ParseStream<'src, I>, indent: usize) -> Result<AstBlock<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    let start = match tokens.peek() {
+        Some(Ok((ix, _))) => *ix,
+        _ => tokens.prev_end(),
+    };
+    let stmt = parse_stmt(tokens, indent)?;
+    let has_semi = !matches!(stmt, AstStmt::Expr { has_semi: false, .. });
+    let span = Span::new(start, tokens.prev_end());
+    Ok(AstBlock {
+        pragmas: Vec::new(),
+        indent,
+        stmts: vec![Spanned::new(stmt, span)],
+        has_semi,
+        span,
+    })
+}
+
+// `value if condition else fallback`, with `value` already parsed
+fn parse_ternary<'src, I>(
+    value: AstExpr<'src>,
+    start: usize,
+    tokens: &mut ParseStream<'src, I>,
+    indent: usize,
+) -> Result<AstConditional<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    tokens.eat(Token::If)?;
+    let ctx = ParseContext::new().without_annotation_parsing();
+    let condition = parse_expr(tokens, Precedence::Lowest, indent, ctx)?;
+    tokens.eat(Token::Else)?;
+    let fallback = parse_expr(tokens, Precedence::Lowest, indent, ctx)?;
+
+    let span = Span::new(start, tokens.prev_end());
+    let if_block = AstBlock {
+        pragmas: Vec::new(),
+        indent: indent + 1,
+        stmts: vec![Spanned::new(
+            AstStmt::Expr {
+                expr: value,
+                has_semi: false,
+            },
+            span,
+        )],
+        has_semi: false,
+        span,
+    };
+    Ok(AstConditional {
+        condition: Box::new(condition),
+        if_block,
+        elif_branches: Vec::new(),
+        else_block: Some(Box::new(fallback)),
+        inline: true,
     })
 }
 
 fn parse_expr<'src, I>(
-    tokens: &mut Peekable<I>,
+    tokens: &mut ParseStream<'src, I>,
     precedence: Precedence,
     indent: usize,
     context: ParseContext,
@@ -492,13 +1410,21 @@ fn parse_expr<'src, I>(
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
-    let lhs = parse_primary_expr(tokens, indent, context)?;
-    Ok(parse_expr_with(lhs, tokens, precedence, indent, context)?)
+    log_rule(Level::Trace, "parse_expr", tokens, indent);
+    tokens.nested(|tokens| {
+        let start = match tokens.peek() {
+            Some(Ok((ix, _))) => *ix,
+            _ => tokens.prev_end(),
+        };
+        let lhs = parse_primary_expr(tokens, indent, context)?;
+        parse_expr_with(lhs, start, tokens, precedence, indent, context)
+    })
 }
 
 fn parse_expr_with<'src, I>(
     parsed_expr: AstExpr<'src>,
-    tokens: &mut Peekable<I>,
+    start: usize,
+    tokens: &mut ParseStream<'src, I>,
     precedence: Precedence,
     indent: usize,
     context: ParseContext,
@@ -513,12 +1439,18 @@ where
             if matches!(tok, Token::Semicolon) {
                 break;
             }
+            // Left for whoever opened the parens to eat
             if matches!(tok, Token::RParen) {
-                if !context.is_in_paren_block {
-                    tokens.next();
-                }
                 break;
             }
+            // Binds looser than any operator, so `a + b if c else d` takes all of `a + b`
+            if matches!(tok, Token::If) {
+                if precedence > Precedence::Lowest {
+                    break;
+                }
+                lhs = parse_ternary(lhs, start, tokens, indent)?.into();
+                continue;
+            }
 
             let op = match tok.as_operator() {
                 None => break,
@@ -532,6 +1464,11 @@ where
 
             tokens.next();
 
+            // A trailing operator continues the expression on the next line.
+            // The lexer doesn't treat the continuation line's leading spaces as
+            // an `Indent`, so only the `Newline` needs skipping here.
+            tokens.skip_newlines();
+
             let rhs = parse_expr(tokens, encountered_precedence, indent, context)?;
             lhs = parse_postfix_expr((lhs, op, rhs).into(), tokens)?;
         } else {
@@ -542,12 +1479,33 @@ where
     Ok(parse_postfix_expr(lhs, tokens)?)
 }
 
-fn parse_postfix_expr<'src, I>(lhs: AstExpr<'src>, tokens: &mut Peekable<I>) -> Result<AstExpr<'src>>
+fn parse_postfix_expr<'src, I>(lhs: AstExpr<'src>, tokens: &mut ParseStream<'src, I>) -> Result<AstExpr<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    // Each link nests the expression so far one level deeper, so a long
+    // chain counts against the depth limit like any other nesting
+    let depth = tokens.depth;
+    let res = parse_postfix_links(lhs, tokens);
+    tokens.depth = depth;
+    res
+}
+
+fn parse_postfix_links<'src, I>(lhs: AstExpr<'src>, tokens: &mut ParseStream<'src, I>) -> Result<AstExpr<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
     let mut lhs = lhs;
     loop {
+        if matches!(
+            tokens.peek(),
+            Some(Ok((
+                _,
+                Token::LParen | Token::Dot | Token::Question | Token::LSquareBrace
+            )))
+        ) {
+            tokens.deepen()?;
+        }
         match tokens.peek() {
             Some(Ok((_, Token::LParen))) => {
                 lhs = parse_call_expr(lhs, tokens)?.into();
@@ -555,6 +1513,29 @@ where
             Some(Ok((_, Token::Dot))) => {
                 lhs = parse_attr_access(lhs, tokens)?.into();
             }
+            // `a?.b` and `f?()`
+            Some(Ok((_, Token::Question))) => {
+                tokens.next();
+                lhs = match tokens.peek() {
+                    Some(Ok((_, Token::Dot))) => AttrAccess {
+                        optional: true,
+                        ..parse_attr_access(lhs, tokens)?
+                    }
+                    .into(),
+                    Some(Ok((_, Token::LParen))) => AstCallExpr {
+                        optional: true,
+                        ..parse_call_expr(lhs, tokens)?
+                    }
+                    .into(),
+                    _ => {
+                        let (ix, tok) = get_next_token(tokens)?;
+                        return Err(ParseErr::ExpectedToken(ix, tok.src_len(), "'.' or '('".to_string()));
+                    }
+                };
+            }
+            Some(Ok((_, Token::LSquareBrace))) => {
+                lhs = parse_index_or_slice(lhs, tokens)?;
+            }
             _ => break,
         }
     }
@@ -562,105 +1543,199 @@ where
     Ok(lhs)
 }
 
-fn parse_attr_access<'src, I>(lhs: AstExpr<'src>, tokens: &mut Peekable<I>) -> Result<AttrAccess<'src>>
+fn parse_attr_access<'src, I>(lhs: AstExpr<'src>, tokens: &mut ParseStream<'src, I>) -> Result<AttrAccess<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
-    eat(tokens, Token::Dot)?;
-    let (_, attr_tok) = get_next_token(tokens)?;
-    assert!(matches!(attr_tok, Token::Ident(_)), "Expected Literal After Dot");
+    tokens.eat(Token::Dot)?;
+    let (ix, attr_tok) = get_next_token(tokens)?;
+    if !matches!(attr_tok, Token::Ident(_)) {
+        return Err(ParseErr::ExpectedToken(
+            ix,
+            attr_tok.src_len(),
+            "identifier".to_string(),
+        ));
+    }
 
-    let attribute = AstLiteral::Ident(attr_tok);
+    let attribute = AstLiteral::ident(attr_tok, ix);
     Ok(AttrAccess {
         attribute,
         expr: Box::new(lhs),
+        optional: false,
     })
 }
 
-fn parse_call_expr<'src, I>(fn_expr: AstExpr<'src>, tokens: &mut Peekable<I>) -> Result<AstCallExpr<'src>>
+// `a[i]`, or a slice `a[start:end]` when a colon follows the (optional) start
+fn parse_index_or_slice<'src, I>(lhs: AstExpr<'src>, tokens: &mut ParseStream<'src, I>) -> Result<AstExpr<'src>>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    tokens.eat(Token::LSquareBrace)?;
+    // `a[x:y]` is a slice, not `x` annotated with type `y`
+    let ctx = ParseContext::new().without_annotation_parsing();
+
+    let start = match tokens.peek() {
+        Some(Ok((_, Token::Colon))) => None,
+        _ => Some(Box::new(parse_expr(tokens, Precedence::Lowest, 0, ctx)?)),
+    };
+
+    let target = Box::new(lhs);
+    let (ix, tok) = get_next_token(tokens)?;
+    match (tok, start) {
+        (Token::RSquareBrace, Some(index)) => Ok(AstIndex { target, index }.into()),
+        (Token::Colon, start) => {
+            let end = match tokens.peek() {
+                Some(Ok((_, Token::RSquareBrace))) => None,
+                _ => Some(Box::new(parse_expr(tokens, Precedence::Lowest, 0, ctx)?)),
+            };
+            tokens.eat(Token::RSquareBrace)?;
+            Ok(AstSlice { target, start, end }.into())
+        }
+        (tok, _) => Err(ParseErr::ExpectedToken(ix, tok.src_len(), "]".to_string())),
+    }
+}
+
+// A leading `*` or `**` on a call argument
+fn parse_spread<'src, I>(tokens: &mut ParseStream<'src, I>) -> Option<Spread>
+where
+    I: Iterator<Item = TokenIter<'src>>,
+{
+    if !matches!(tokens.peek(), Some(Ok((_, Token::Mul)))) {
+        return None;
+    }
+    tokens.next();
+
+    if !matches!(tokens.peek(), Some(Ok((_, Token::Mul)))) {
+        return Some(Spread::Positional);
+    }
+    tokens.next();
+    Some(Spread::Keyword)
+}
+
+// Whether a call's arguments ran into the end of their line or the input.
+// Vertical calls have eaten their newlines by the time this is asked.
+fn is_unclosed(tok: Option<&TokenIter<'_>>) -> bool {
+    matches!(tok, None | Some(Ok((_, Token::Newline | Token::Dedent))))
+}
+
+fn parse_call_expr<'src, I>(fn_expr: AstExpr<'src>, tokens: &mut ParseStream<'src, I>) -> Result<AstCallExpr<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
-    eat(tokens, Token::LParen)?;
+    let open_ix = match tokens.peek() {
+        Some(Ok((ix, _))) => *ix,
+        _ => tokens.prev_end(),
+    };
+    tokens.eat(Token::LParen)?;
 
     let mut call_args = Vec::new();
     let mut is_vertical = false;
 
     if matches!(tokens.peek(), Some(Ok((_, Token::Newline)))) {
-        is_vertical = true;
         tokens.next();
+        // An empty `f(\n)` has no indented args to close
+        is_vertical = !matches!(tokens.peek(), Some(Ok((_, Token::RParen))));
     }
 
     if is_vertical {
-        eat(tokens, Token::Indent)?;
+        tokens.eat(Token::Indent)?;
     }
 
+    let mut seen_keyword = false;
+    let mut seen_keyword_spread = false;
     while !matches!(tokens.peek(), Some(Ok((_, Token::RParen | Token::Dedent)))) {
-        let ctx = ParseContext::new().entering_parens();
+        if is_unclosed(tokens.peek()) {
+            return Err(ParseErr::UnclosedParen(open_ix));
+        }
+        let arg_ix = match tokens.peek() {
+            Some(Ok((ix, _))) => *ix,
+            _ => tokens.prev_end(),
+        };
+        let spread = parse_spread(tokens);
+
+        let ctx = ParseContext::new();
         let mut expr = parse_expr(tokens, Precedence::Lowest, 0, ctx)?;
         let mut name = None;
 
-        let is_named_arg = matches!(expr, AstExpr::LitExpr(_)) && matches!(tokens.peek(), Some(Ok((_, Token::Eq))));
+        let is_named_arg = spread.is_none()
+            && matches!(expr, AstExpr::LitExpr(_))
+            && matches!(tokens.peek(), Some(Ok((_, Token::Eq))));
         if is_named_arg {
-            eat(tokens, Token::Eq)?;
+            tokens.eat(Token::Eq)?;
             name = Some(expr);
             expr = parse_expr(tokens, Precedence::Lowest, 0, ctx)?;
         }
 
-        call_args.push(CallArg { expr, name });
+        // `f(1, *rest, x=2, **kwargs)` is the only order allowed
+        let arg_len = tokens.prev_end() - arg_ix;
+        if seen_keyword_spread {
+            return Err(ParseErr::SpreadAfterKeywordSpread(arg_ix, arg_len));
+        }
+        if spread == Some(Spread::Positional) && seen_keyword {
+            return Err(ParseErr::SpreadAfterKeywordArg(arg_ix, arg_len));
+        }
+        seen_keyword |= is_named_arg;
+        seen_keyword_spread |= spread == Some(Spread::Keyword);
+
+        call_args.push(CallArg { expr, name, spread });
 
         if !matches!(tokens.peek(), Some(Ok((_, Token::Comma)))) {
             break;
         }
 
-        eat(tokens, Token::Comma)?;
+        tokens.eat(Token::Comma)?;
 
         if is_vertical {
-            eat(tokens, Token::Newline)?;
+            tokens.eat(Token::Newline)?;
         }
     }
 
     if is_vertical {
-        eat(tokens, Token::Dedent)?;
+        // Without a trailing comma the last arg's newline is still to come
+        if matches!(tokens.peek(), Some(Ok((_, Token::Newline)))) {
+            tokens.next();
+        }
+        tokens.eat(Token::Dedent)?;
     }
-    eat(tokens, Token::RParen)?;
+    if is_unclosed(tokens.peek()) {
+        return Err(ParseErr::UnclosedParen(open_ix));
+    }
+    tokens.eat(Token::RParen)?;
 
     let expr = AstCallExpr {
         called_expr: Box::new(fn_expr),
         args: call_args,
+        optional: false,
     };
 
     Ok(expr)
 }
 
-fn parse_annotation<'src, I>(tokens: &mut Peekable<I>) -> Result<TypeAnnotation<'src>>
+fn parse_annotation<'src, I>(tokens: &mut ParseStream<'src, I>) -> Result<TypeAnnotation<'src>>
 where
     I: Iterator<Item = TokenIter<'src>>,
 {
     // Consume the ':'
-    eat(tokens, Token::Colon)?;
+    tokens.eat(Token::Colon)?;
 
     Ok(parse_type_decl(tokens, true)?)
 }
 
-fn eat<'src, I>(tokens: &mut Peekable<I>, expected_type: Token) -> Result<()>
-where
-    I: Iterator<Item = TokenIter<'src>>,
-{
-    let (ix, tok) = get_next_token(tokens)?;
-    if tok == expected_type {
-        return Ok(());
-    }
-    Err(ParseErr::ExpectedToken(ix, tok.src_len(), expected_type.to_string()))
+// Keywords lex as their own tokens, so using one as a name would otherwise
+// fail with a confusing error further along
+fn reserved_keyword(ix: usize, tok: &Token<'_>) -> Option<ParseErr> {
+    tok.keyword()
+        .map(|kw| ParseErr::ReservedKeyword(ix, tok.src_len(), kw.to_string()))
 }
 
 #[test]
 fn test_parse_add() {
-    let mut toks = vec![Token::Ident("a"), Token::Add, Token::Ident("b")]
-        .into_iter()
-        .enumerate()
-        .map(|t| Ok(t))
-        .peekable();
+    let mut toks = ParseStream::new(
+        vec![Token::Ident("a"), Token::Add, Token::Ident("b")]
+            .into_iter()
+            .enumerate()
+            .map(|t| Ok(t)),
+    );
 
     let expected: AstBinExpr = (Token::Ident("a"), Operator::Add, Token::Ident("b")).into();
     assert_eq!(
@@ -671,21 +1746,22 @@ fn test_parse_add() {
 
 #[test]
 fn test_parse_add_multi() {
-    let mut toks = vec![
-        Token::Ident("a"),
-        Token::Add,
-        Token::Ident("b"),
-        Token::Add,
-        Token::Ident("c"),
-    ]
-    .into_iter()
-    .enumerate()
-    .map(|t| Ok(t))
-    .peekable();
+    let mut toks = ParseStream::new(
+        vec![
+            Token::Ident("a"),
+            Token::Add,
+            Token::Ident("b"),
+            Token::Add,
+            Token::Ident("c"),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|t| Ok(t)),
+    );
 
     let r = AstExpr::BinExpr((Token::Ident("b"), Operator::Add, Token::Ident("c")).into());
     let expected = AstBinExpr {
-        l: Box::new(AstExpr::LitExpr(AstLiteral::Ident(Token::Ident("a")))),
+        l: Box::new(AstExpr::LitExpr(AstLiteral::from(Token::Ident("a")))),
         op: Operator::Add,
         r: Box::new(r),
     };
@@ -695,13 +1771,155 @@ fn test_parse_add_multi() {
     );
 }
 
+#[test]
+fn test_parse_literal_comparisons() {
+    let parse = |src| parse_expression(crate::lexer::Lexer::new(src));
+
+    let expected: AstBinExpr = (Token::Ident("name"), Operator::Equals, Token::str("toki")).into();
+    assert_eq!(parse("name == \"toki\""), Ok(AstExpr::BinExpr(expected)));
+
+    let expected: AstBinExpr = (Token::int(1), Operator::NotEquals, Token::int(2)).into();
+    assert_eq!(parse("1 != 2"), Ok(AstExpr::BinExpr(expected)));
+
+    let Ok(AstExpr::BinExpr(bin)) = parse("\"a\" == 1") else {
+        panic!("Expected a comparison");
+    };
+    assert_eq!(*bin.l, AstExpr::LitExpr(AstLiteral::Str(Token::str("a"))));
+    assert_eq!(*bin.r, AstExpr::LitExpr(AstLiteral::Int(Token::int(1))));
+}
+
+#[test]
+fn test_parse_attr_access_chain() {
+    let attr = |expr: AstExpr<'static>, name| -> AstExpr<'static> {
+        AttrAccess {
+            attribute: AstLiteral::from(Token::Ident(name)),
+            expr: Box::new(expr),
+            optional: false,
+        }
+        .into()
+    };
+    let a = AstExpr::LitExpr(AstLiteral::from(Token::Ident("a")));
+    let a_b_c = attr(attr(a, "b"), "c");
+
+    let parse = |src| parse_expression(crate::lexer::Lexer::new(src));
+    assert_eq!(parse("a.b.c"), Ok(a_b_c));
+
+    let Ok(AstExpr::CallExpr(call)) = parse("a.b.c.d()") else {
+        panic!("Expected a call");
+    };
+    let AstExpr::AttrAccessExpr(d) = *call.called_expr else {
+        panic!("Expected an attribute access");
+    };
+    assert_eq!(d.attribute, AstLiteral::from(Token::Ident("d")));
+    assert_eq!(*d.expr, parse("a.b.c").unwrap());
+}
+
+#[test]
+fn test_parse_optional_chain() {
+    let parse = |src| parse_expression(crate::lexer::Lexer::new(src));
+    let access = |src| match parse(src) {
+        Ok(AstExpr::AttrAccessExpr(aa)) => aa,
+        other => panic!("Expected an attribute access, got {:?}", other),
+    };
+    let obj = AstExpr::LitExpr(AstLiteral::from(Token::Ident("obj")));
+
+    let field = access("obj?.field");
+    assert!(field.optional);
+    assert_eq!(field.attribute, AstLiteral::from(Token::Ident("field")));
+    assert_eq!(*field.expr, obj);
+
+    let Ok(AstExpr::CallExpr(call)) = parse("obj?.method()") else {
+        panic!("Expected a call");
+    };
+    assert!(!call.optional);
+    let AstExpr::AttrAccessExpr(method) = *call.called_expr else {
+        panic!("Expected an attribute access");
+    };
+    assert!(method.optional);
+    assert_eq!(*method.expr, obj);
+
+    let nested = access("obj?.field?.nested");
+    assert!(nested.optional);
+    assert_eq!(*nested.expr, AstExpr::AttrAccessExpr(field));
+    assert!(!access("obj?.field.nested").optional);
+
+    let Ok(AstExpr::CallExpr(call)) = parse("a?(1)") else {
+        panic!("Expected a call");
+    };
+    assert!(call.optional);
+
+    for src in ["obj?.field", "obj?.method()", "obj?.field?.nested", "a?(1)"] {
+        assert_eq!(parse(src).unwrap().to_string(), src);
+    }
+    assert_eq!(
+        parse("obj?[0]"),
+        Err(ParseErr::ExpectedToken(4, 1, "'.' or '('".to_string()))
+    );
+}
+
+#[test]
+fn test_parse_spread_args() {
+    let parse = |src| parse_expression(crate::lexer::Lexer::new(src));
+    let spreads = |src| match parse(src) {
+        Ok(AstExpr::CallExpr(call)) => call.args.iter().map(|a| a.spread).collect::<Vec<_>>(),
+        other => panic!("Expected a call, got {:?}", other),
+    };
+
+    assert_eq!(spreads("f(*args)"), vec![Some(Spread::Positional)]);
+    assert_eq!(spreads("f(1, *rest)"), vec![None, Some(Spread::Positional)]);
+    assert_eq!(spreads("f(**kwargs)"), vec![Some(Spread::Keyword)]);
+    assert_eq!(
+        spreads("f(a, *b, c=1, **d)"),
+        vec![None, Some(Spread::Positional), None, Some(Spread::Keyword)]
+    );
+
+    for src in ["f(*args)", "f(1, *rest)", "f(**kwargs)", "f(*xs, y=2, **kw)"] {
+        assert_eq!(parse(src).unwrap().to_string(), src);
+    }
+
+    assert_eq!(parse("f(x=1, *rest)"), Err(ParseErr::SpreadAfterKeywordArg(7, 5)));
+    assert_eq!(parse("f(**kw, *rest)"), Err(ParseErr::SpreadAfterKeywordSpread(8, 5)));
+    assert_eq!(parse("f(**kw, x=1)"), Err(ParseErr::SpreadAfterKeywordSpread(8, 3)));
+}
+
+#[test]
+fn test_parse_vertical_call() {
+    let parse = |src| match parse_expression(crate::lexer::Lexer::new(src)) {
+        Ok(AstExpr::CallExpr(call)) => call,
+        other => panic!("Expected a call, got {:?}", other),
+    };
+    let ident = |name| AstExpr::from(AstLiteral::from(Token::Ident(name)));
+    let names = |call: &AstCallExpr| {
+        call.args
+            .iter()
+            .map(|a| a.name.as_ref().map(|n| n.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    let call = parse("f(\n    a,\n    b\n)");
+    let args = call.args.iter().map(|a| &a.expr).collect::<Vec<_>>();
+    assert_eq!(args, vec![&ident("a"), &ident("b")]);
+    assert_eq!(names(&call), vec![None, None]);
+
+    let call = parse("f(\n    a,\n    x=1,\n    y=b\n)");
+    assert_eq!(names(&call), vec![None, Some("x".to_string()), Some("y".to_string())]);
+    assert_eq!(call.args[2].expr, ident("b"));
+
+    let call = parse("f(\n    a,\n    b,\n)");
+    assert_eq!(call.args.len(), 2);
+
+    let call = parse("f(\n)");
+    assert!(call.args.is_empty());
+}
+
 #[test]
 fn test_parse_type_def() {
-    let mut toks = vec![Token::Mut, Token::Ident("int"), Token::Bar, Token::Ident("bool")]
-        .into_iter()
-        .enumerate()
-        .map(|t| Ok(t))
-        .peekable();
+    let mut toks = ParseStream::new(
+        vec![Token::Mut, Token::Ident("int"), Token::Bar, Token::Ident("bool")]
+            .into_iter()
+            .enumerate()
+            .map(|t| Ok(t)),
+    );
 
     let out = parse_type_decl(&mut toks, true);
 
@@ -711,18 +1929,19 @@ fn test_parse_type_def() {
 
 #[test]
 fn test_parse_type_def_param() {
-    let mut toks = vec![
-        Token::Ident("Result"),
-        Token::LSquareBrace,
-        Token::Ident("int"),
-        Token::Comma,
-        Token::Ident("str"),
-        Token::RSquareBrace,
-    ]
-    .into_iter()
-    .enumerate()
-    .map(|t| Ok(t))
-    .peekable();
+    let mut toks = ParseStream::new(
+        vec![
+            Token::Ident("Result"),
+            Token::LSquareBrace,
+            Token::Ident("int"),
+            Token::Comma,
+            Token::Ident("str"),
+            Token::RSquareBrace,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|t| Ok(t)),
+    );
 
     let out = parse_type_decl(&mut toks, true);
 
@@ -732,3 +1951,958 @@ fn test_parse_type_def_param() {
     };
     assert_eq!(out, Ok(expected));
 }
+
+#[test]
+fn test_parse_double_mut_param() {
+    let src = "def f(x: mut mut int) -> int:\n    x\n";
+    assert_eq!(
+        try_parse(crate::lexer::Lexer::new(src)),
+        Err(ParseErr::UnexpectedMut(13, 3))
+    );
+}
+
+#[test]
+fn test_parse_fn_type_params() {
+    let src = "def f[T, U](x: T, y: U) -> T:\n    x\n";
+    let mut toks = ParseStream::new(crate::lexer::Lexer::new(src));
+
+    let fn_def = parse_fn_def(&mut toks, 0).unwrap();
+
+    assert_eq!(fn_def.type_params, vec!["T", "U"]);
+    assert_eq!(
+        fn_def.args[0].ident,
+        AstLiteral::typed_ident(Token::Ident("x"), 0, TypeAnnotation::Dynamic("T"))
+    );
+    assert_eq!(fn_def.return_type, TypeAnnotation::Dynamic("T"));
+    assert!(fn_def.to_string().starts_with("def f[T, U](x: T,y: U) -> T:"));
+}
+
+#[test]
+fn test_parse_test_attribute_round_trip() {
+    let src = "#[test]\ndef my_test() -> void:\n    check(1)\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    let AstStmt::FnDef(fn_def) = &parsed.stmts[0].node else {
+        panic!("Expected FnDef, got {:?}", parsed.stmts[0].node);
+    };
+    assert_eq!(
+        fn_def.attributes,
+        vec![AstAttribute {
+            name: "test",
+            args: Vec::new()
+        }]
+    );
+
+    let printed = parsed.to_string();
+    assert!(printed.starts_with("#[test]\ndef my_test() -> void:"));
+
+    let mut toks = ParseStream::new(crate::lexer::Lexer::new(&printed));
+    assert_eq!(parse_stmt(&mut toks, 0).as_ref(), Ok(&parsed.stmts[0].node));
+}
+
+#[test]
+fn test_parse_attribute_args_on_struct() {
+    let src = "#[deprecated(\"use Point3\")]\n#[inline]\nstruct Point:\n    x: int\n\np = 1;\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    let AstStmt::StructDef { attributes, .. } = &parsed.stmts[0].node else {
+        panic!("Expected StructDef, got {:?}", parsed.stmts[0].node);
+    };
+    let names = attributes.iter().map(|a| a.name).collect::<Vec<_>>();
+    assert_eq!(names, vec!["deprecated", "inline"]);
+    assert_eq!(attributes[0].args.len(), 1);
+}
+
+#[test]
+fn test_parse_unknown_attribute() {
+    let src = "#[frobnicate]\ndef f() -> int:\n    1\n";
+    assert_eq!(
+        try_parse(crate::lexer::Lexer::new(src)),
+        Err(ParseErr::UnknownAttribute(2, 10))
+    );
+}
+
+#[test]
+fn test_parse_expr_continued_after_operator() {
+    let src = "def f(a: int, b: int) -> int:\n    x = a +\n        b;\n    x\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    let AstStmt::FnDef(fn_def) = &parsed.stmts[0].node else {
+        panic!("Expected FnDef, got {:?}", parsed.stmts[0].node);
+    };
+    let expected: AstBinExpr = (Token::Ident("a"), Operator::Add, Token::Ident("b")).into();
+    assert_eq!(
+        fn_def.body.stmts.iter().map(|s| &s.node).collect::<Vec<_>>(),
+        vec![
+            &AstStmt::Assignment {
+                target: AstLiteral::from(Token::Ident("x")).into(),
+                assigned: AstExpr::BinExpr(expected),
+            },
+            &AstStmt::Expr {
+                expr: AstLiteral::from(Token::Ident("x")).into(),
+                has_semi: false,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_newline_without_operator_ends_stmt() {
+    let src = "a\n+ b\n";
+    assert_eq!(
+        try_parse(crate::lexer::Lexer::new(src)),
+        Err(ParseErr::UnexpectedStmt(2, 1, Span::new(0, 1)))
+    );
+}
+
+#[test]
+fn test_parse_pragmas() {
+    let src = "#!format: off\n#!indent: 2\n\na = 1;\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    assert_eq!(
+        parsed.pragmas,
+        vec![
+            Pragma {
+                key: "format",
+                value: "off"
+            },
+            Pragma {
+                key: "indent",
+                value: "2"
+            },
+        ]
+    );
+    assert_eq!(parsed.stmts.len(), 1);
+    assert_eq!(parsed.to_string(), "#!format: off\n#!indent: 2\na = 1;\n");
+}
+
+#[test]
+fn test_parse_pragma_after_code() {
+    let src = "a = 1;\n#!format: off\n";
+    assert_eq!(
+        try_parse(crate::lexer::Lexer::new(src)),
+        Err(ParseErr::InvalidExpressionStart(7, 13))
+    );
+}
+
+#[test]
+fn test_parse_expression() {
+    let int = |i| AstExpr::LitExpr(AstLiteral::Int(Token::int(i)));
+    let expected: AstExpr = (int(1), Operator::Add, (int(2), Operator::Mul, int(3)).into()).into();
+
+    assert_eq!(parse_expression(crate::lexer::Lexer::new("1 + 2 * 3")), Ok(expected));
+}
+
+#[test]
+fn test_parse_expression_trailing_input() {
+    assert_eq!(
+        parse_expression(crate::lexer::Lexer::new("1 + 2 3")),
+        Err(ParseErr::TrailingInput(6, 1))
+    );
+}
+
+#[test]
+fn test_parse_fn_docstring() {
+    let src = "def f() -> int:\n    \"Returns one\"\n    1\n";
+    let mut toks = ParseStream::new(crate::lexer::Lexer::new(src));
+    let fn_def = parse_fn_def(&mut toks, 0).unwrap();
+
+    assert_eq!(fn_def.docstring, Some(Token::str("Returns one")));
+    assert_eq!(fn_def.body.stmts.len(), 1);
+    assert_eq!(fn_def.to_string(), "def f() -> int:\n    \"Returns one\"\n    1\n");
+}
+
+#[test]
+fn test_parse_fn_leading_str_expr_is_not_docstring() {
+    let src = "def f() -> int:\n    \"a\".len()\n";
+    let mut toks = ParseStream::new(crate::lexer::Lexer::new(src));
+    let fn_def = parse_fn_def(&mut toks, 0).unwrap();
+
+    assert_eq!(fn_def.docstring, None);
+    assert!(matches!(
+        fn_def.body.stmts[0].node,
+        AstStmt::Expr {
+            expr: AstExpr::CallExpr(_),
+            has_semi: false
+        }
+    ));
+}
+
+// Inputs the fuzzer found panicking, each should now be a plain error
+#[test]
+fn test_parse_fuzz_crashes_are_errors() {
+    use crate::lexer::LexErr;
+
+    let cases = [
+        ("x = \"oops", ParseErr::LexErr(LexErr::UnterminatedString(4))),
+        ("if x:\n   y\n", ParseErr::LexErr(LexErr::InvalidIndent(6, 3))),
+        ("if x:\n        y\n", ParseErr::LexErr(LexErr::UnexpectedIndent(6, 8))),
+        ("x = 99999999999;\n", ParseErr::LexErr(LexErr::IntOverflow(4, 11))),
+        ("x = p.;\n", ParseErr::ExpectedToken(6, 1, "identifier".to_string())),
+    ];
+    for (src, err) in cases {
+        assert_eq!(try_parse(crate::lexer::Lexer::new(src)), Err(err), "{:?}", src);
+    }
+}
+
+#[test]
+fn test_conditional_chain_semicolons() {
+    let cases = [
+        ("if a:\n    1\nelse if b:\n    2\nelse:\n    3\n", false),
+        ("if a:\n    f();\nelse if b:\n    g();\nelse:\n    h();\n", true),
+        (
+            "if a:\n    1\nelse if b:\n    2\nelse if c:\n    3\nelse:\n    4\n",
+            false,
+        ),
+        (
+            "if a:\n    f();\nelse if b:\n    g();\nelse if c:\n    h();\nelse:\n    i();\n",
+            true,
+        ),
+        ("if a:\n    f();\nelse if b:\n    g();\n", true),
+        ("if a:\n    f();\nelse if b:\n    2\n", false),
+    ];
+
+    for (src, has_semi) in cases {
+        let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+        assert!(
+            matches!(parsed.stmts[0].node, AstStmt::Expr { has_semi: h, .. } if h == has_semi),
+            "{}",
+            src
+        );
+
+        // Re-emitting must not add a stray semicolon after the chain
+        let printed = parsed.to_string();
+        assert_eq!(
+            try_parse(crate::lexer::Lexer::new(&printed)).unwrap(),
+            parsed,
+            "{}",
+            printed
+        );
+    }
+}
+
+#[test]
+fn test_parse_do_block() {
+    let src = "x = do:\n    a = 1;\n    a + 1\ny = x;\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    let AstStmt::Assignment {
+        assigned: AstExpr::BlockExpr(block),
+        ..
+    } = &parsed.stmts[0].node
+    else {
+        panic!("Expected BlockExpr, got {:?}", parsed.stmts[0].node);
+    };
+    assert_eq!(block.indent, 1);
+    assert!(block.is_tail_expression());
+    assert!(matches!(block.stmts[0].node, AstStmt::Assignment { .. }));
+    assert_eq!(parsed.stmts.len(), 2);
+
+    let printed = parsed.to_string();
+    assert!(printed.starts_with("x = do:\n    a = 1;\n    (a + 1)\n"), "{}", printed);
+    assert_eq!(try_parse(crate::lexer::Lexer::new(&printed)).unwrap(), parsed);
+}
+
+#[test]
+fn test_parse_fn_def_in_expression_position() {
+    let src = "f = def g(n: int) -> int:\n    g(n - 1)\n;\napply(def double(x: int) -> int:\n    x * 2\n, 3);\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    let AstStmt::Assignment {
+        assigned: AstExpr::FnDefExpr(fn_def),
+        ..
+    } = &parsed.stmts[0].node
+    else {
+        panic!("Expected FnDefExpr, got {:?}", parsed.stmts[0].node);
+    };
+    assert_eq!(fn_def.name, AstLiteral::from(Token::Ident("g")));
+
+    let AstStmt::Expr {
+        expr: AstExpr::CallExpr(call),
+        ..
+    } = &parsed.stmts[1].node
+    else {
+        panic!("Expected CallExpr, got {:?}", parsed.stmts[1].node);
+    };
+    assert!(matches!(call.args[0].expr, AstExpr::FnDefExpr(_)));
+    assert_eq!(call.args[1].expr, AstLiteral::Int(Token::int(3)).into());
+
+    assert_eq!(
+        try_parse(crate::lexer::Lexer::new(&parsed.to_string())).unwrap(),
+        parsed
+    );
+}
+
+#[test]
+fn test_parse_reserved_keyword_as_name() {
+    let cases = [
+        (
+            "struct Jump:\n    return: int\n\n",
+            ParseErr::ReservedKeyword(17, 6, "return".to_string()),
+        ),
+        (
+            "def if() -> int:\n    1\n",
+            ParseErr::ReservedKeyword(4, 2, "if".to_string()),
+        ),
+        (
+            "def f(def: int) -> int:\n    1\n",
+            ParseErr::ReservedKeyword(6, 3, "def".to_string()),
+        ),
+    ];
+    for (src, err) in cases {
+        assert_eq!(try_parse(crate::lexer::Lexer::new(src)), Err(err), "{:?}", src);
+    }
+}
+
+#[test]
+fn test_parse_stream() {
+    let mut toks = ParseStream::new(crate::lexer::Lexer::new("\n\nx = 1"));
+
+    assert!(matches!(toks.peek(), Some(Ok((_, Token::Newline)))));
+    assert!(matches!(toks.peek_skip_newlines(), Some(Ok((2, Token::Ident("x"))))));
+    assert_eq!(toks.eat(Token::Eq), Err(ParseErr::ExpectedToken(2, 1, "=".to_string())));
+    assert_eq!(toks.eat(Token::Eq), Ok(()));
+    assert!(matches!(toks.next(), Some(Ok((_, Token::IntLiteral { value: 1, .. })))));
+    toks.skip_newlines();
+    assert!(toks.peek().is_none());
+}
+
+#[test]
+fn test_parse_recursion_limit() {
+    let depth = 1000;
+    let mut src = String::new();
+    for i in 0..depth {
+        src.push_str(&format!("{}if true:\n", "    ".repeat(i)));
+    }
+    src.push_str(&format!("{}1\n", "    ".repeat(depth)));
+
+    assert!(matches!(
+        try_parse(crate::lexer::Lexer::new(&src)),
+        Err(ParseErr::RecursionLimitExceeded(_))
+    ));
+
+    let parens = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    assert!(matches!(
+        try_parse(crate::lexer::Lexer::new(&parens)),
+        Err(ParseErr::RecursionLimitExceeded(_))
+    ));
+    assert!(parse_with_max_depth(crate::lexer::Lexer::new("((1));"), 4).is_ok());
+
+    let else_ifs = format!("if false:\n    1\n{}", "else if false:\n    1\n".repeat(depth));
+    assert!(matches!(
+        try_parse(crate::lexer::Lexer::new(&else_ifs)),
+        Err(ParseErr::RecursionLimitExceeded(_))
+    ));
+
+    // Postfix chains are parsed in a loop, but still nest the AST
+    for link in [".b", "()", "?.b", "[0]"] {
+        let chain = format!("x = a{};\n", link.repeat(100_000));
+        assert!(
+            matches!(
+                try_parse(crate::lexer::Lexer::new(&chain)),
+                Err(ParseErr::RecursionLimitExceeded(_))
+            ),
+            "{}",
+            link
+        );
+    }
+    assert!(parse_with_max_depth(crate::lexer::Lexer::new("a.b().c;"), 4).is_ok());
+    assert!(parse_with_max_depth(crate::lexer::Lexer::new("a.b().c.d;"), 4).is_err());
+}
+
+#[test]
+fn test_parse_index_and_slice() {
+    fn parse_one(src: &str) -> AstExpr<'_> {
+        parse_expression(crate::lexer::Lexer::new(src)).unwrap()
+    }
+
+    assert!(matches!(parse_one("a[0]"), AstExpr::IndexExpr(_)));
+    assert!(matches!(parse_one("a[i + 1]"), AstExpr::IndexExpr(_)));
+
+    for src in ["a[1:3]", "a[:2]", "a[1:]", "a[:]", "a[x:y]", "f(a)[i:j].b"] {
+        assert_eq!(parse_one(src).to_string(), src);
+    }
+    let AstExpr::SliceExpr(slice) = parse_one("a[:n]") else {
+        panic!("Expected SliceExpr");
+    };
+    assert!(slice.start.is_none());
+    assert_eq!(*slice.end.unwrap(), AstLiteral::from(Token::Ident("n")).into());
+
+    assert_eq!(
+        parse_expression(crate::lexer::Lexer::new("a[1 2]")),
+        Err(ParseErr::ExpectedToken(4, 1, "]".to_string()))
+    );
+}
+
+#[test]
+fn test_parse_assignment_targets() {
+    assert_eq!(
+        try_parse(crate::lexer::Lexer::new("1 + 2 = 3;\n")),
+        Err(ParseErr::InvalidAssignmentTarget(0, 5))
+    );
+    assert_eq!(
+        try_parse(crate::lexer::Lexer::new("x = 1;\nf(x) = 3;\n")),
+        Err(ParseErr::InvalidAssignmentTarget(7, 4))
+    );
+
+    for src in ["obj.field = 3;\n", "x = 3;\n", "x: int = 3;\n", "xs[0] = 3;\n"] {
+        let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+        assert!(matches!(parsed.stmts[0].node, AstStmt::Assignment { .. }), "{:?}", src);
+    }
+}
+
+#[test]
+fn test_parse_stmt_spans() {
+    let src = "x = a +\n    b;\ndef f() -> int:\n    \"doc\"\n    \"s\" + 1\nf()\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    let text = |stmt: &Spanned<AstStmt<'_>>| &src[stmt.span.start..stmt.span.end];
+
+    assert_eq!(text(&parsed.stmts[0]), "x = a +\n    b;");
+    assert_eq!(text(&parsed.stmts[2]), "f()");
+
+    let AstStmt::FnDef(fn_def) = &parsed.stmts[1].node else {
+        panic!("Expected FnDef, got {:?}", parsed.stmts[1].node);
+    };
+    assert_eq!(text(&fn_def.body.stmts[0]), "\"s\" + 1");
+}
+
+#[test]
+fn test_parse_empty_inputs() {
+    let cases = [
+        "",
+        "    ",
+        "\n\n\n",
+        "  \n\t\n",
+        "# just a comment\n",
+        "# a\n    # b\n# c",
+        "    \n        \n",
+    ];
+    for src in cases {
+        let parsed = try_parse(crate::lexer::Lexer::new(src));
+        assert!(
+            matches!(&parsed, Ok(block) if block.is_empty() && block.has_semi),
+            "{:?} parsed to {:?}",
+            src,
+            parsed
+        );
+    }
+}
+
+#[test]
+fn test_parse_tokens() {
+    let tokens = vec![
+        (0, Token::Ident("x")),
+        (2, Token::Eq),
+        (4, Token::int(1)),
+        (5, Token::Semicolon),
+        (6, Token::Newline),
+    ];
+    assert_eq!(parse_tokens(tokens), try_parse(crate::lexer::Lexer::new("x = 1;\n")));
+
+    assert_eq!(
+        parse_tokens(vec![(0, Token::Ident("x")), (2, Token::Eq)]),
+        Err(ParseErr::UnexpectedEnd)
+    );
+}
+
+#[test]
+fn test_parse_negative_default_param() {
+    let src = "def f(x: int = -1, y: int) -> int:\n    x\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    let AstStmt::FnDef(fn_def) = &parsed.stmts[0].node else {
+        panic!("Expected FnDef, got {:?}", parsed.stmts[0].node);
+    };
+    let expected = AstUnaryExpr {
+        op: Operator::Sub,
+        expr: Box::new(AstLiteral::Int(Token::int(1)).into()),
+    };
+    assert_eq!(fn_def.args[0].default, Some(expected.into()));
+    assert_eq!(fn_def.args[1].default, None);
+    assert_eq!(fn_def.args[0].to_string(), "x: int = -1");
+
+    let pretty = crate::pretty::Formatter::default().fmt_stmt(&parsed.stmts[0], 0);
+    assert!(pretty.starts_with("def f(x: int = -1, y: int) -> int:"), "{}", pretty);
+}
+
+#[test]
+fn test_parse_struct_field_defaults() {
+    let src = "struct Point:\n    x: int = 0\n    y: int = 0\n    label: str\n\np = Point();\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    let AstStmt::StructDef { fields, .. } = &parsed.stmts[0].node else {
+        panic!("Expected StructDef, got {:?}", parsed.stmts[0].node);
+    };
+    let zero = AstExpr::LitExpr(AstLiteral::Int(Token::int(0)));
+    assert_eq!(fields[0].name, Token::Ident("x"));
+    assert_eq!(fields[0].default, Some(zero));
+    assert_eq!(fields[1].to_string(), "y: int = 0");
+    assert_eq!(fields[2].default, None);
+
+    assert_eq!(try_parse(crate::lexer::Lexer::new(&parsed.to_string())), Ok(parsed));
+}
+
+#[test]
+fn test_parse_match() {
+    let src = "def f(x: int) -> int:\n    match x:\n        case 1:\n            10\n\n        case \"one\":\n            y = 1;\n            y\n        case n:\n            n\n        case _:\n            0\n    x\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    let AstStmt::FnDef(fn_def) = &parsed.stmts[0].node else {
+        panic!("Expected FnDef, got {:?}", parsed.stmts[0].node);
+    };
+    let AstStmt::Match { scrutinee, arms } = &fn_def.body.stmts[0].node else {
+        panic!("Expected Match, got {:?}", fn_def.body.stmts[0].node);
+    };
+    assert_eq!(*scrutinee, AstExpr::LitExpr(AstLiteral::from(Token::Ident("x"))));
+
+    let patterns = arms.iter().map(|arm| &arm.pattern).collect::<Vec<_>>();
+    assert_eq!(
+        patterns,
+        vec![
+            &Pattern::Literal(AstLiteral::Int(Token::int(1))),
+            &Pattern::Literal(AstLiteral::Str(Token::str("one"))),
+            &Pattern::Binding(Spanned::new(Token::Ident("n"), Span::default())),
+            &Pattern::Wildcard,
+        ]
+    );
+    assert_eq!(arms[1].body.stmts.len(), 2);
+    assert!(arms.iter().all(|arm| arm.body.indent == 3));
+    assert_eq!(fn_def.body.stmts.len(), 2);
+}
+
+#[test]
+fn test_parse_match_bad_pattern() {
+    let src = "match x:\n    case (1):\n        1\n";
+    assert_eq!(
+        try_parse(crate::lexer::Lexer::new(src)),
+        Err(ParseErr::ExpectedPattern(18, 1))
+    );
+}
+
+#[test]
+fn test_parse_not() {
+    let parse = |src| parse_expression(crate::lexer::Lexer::new(src)).unwrap();
+
+    let parsed = parse("not a == b and c");
+    assert_eq!(parsed.to_string(), "(not (a == b) and c)");
+    let AstExpr::BinExpr(bin) = parsed else {
+        panic!("Expected BinExpr, got {:?}", parsed);
+    };
+    assert_eq!(bin.op, Operator::And);
+    assert!(matches!(*bin.l, AstExpr::NotExpr(ref inner) if matches!(**inner, AstExpr::BinExpr(_))));
+
+    assert_eq!(parse("not not x").to_string(), "not not x");
+    assert_eq!(parse("not -x").to_string(), "not -x");
+    assert_eq!(parse("!a or b").to_string(), "(not a or b)");
+}
+
+#[test]
+fn test_parse_dangling_not() {
+    let parse = |src| parse_expression(crate::lexer::Lexer::new(src));
+
+    assert_eq!(parse("not ;"), Err(ParseErr::ExpectedExpressionAfterUnary(0, 3)));
+    assert_eq!(parse("!)"), Err(ParseErr::ExpectedExpressionAfterUnary(0, 1)));
+    assert_eq!(parse("a and not"), Err(ParseErr::ExpectedExpressionAfterUnary(6, 3)));
+
+    // Only a missing operand is blamed on the `not`, not one further in
+    assert_eq!(parse("not (;)"), Err(ParseErr::InvalidExpressionStart(5, 1)));
+}
+
+#[test]
+fn test_parse_unary_minus_binds_tightest() {
+    let parsed = parse_expression(crate::lexer::Lexer::new("-a.b * -2")).unwrap();
+    assert_eq!(parsed.to_string(), "(-a.b * -2)");
+
+    let AstExpr::BinExpr(bin) = parsed else {
+        panic!("Expected BinExpr, got {:?}", parsed);
+    };
+    assert!(
+        matches!(*bin.l, AstExpr::UnaryExpr(AstUnaryExpr { ref expr, .. }) if matches!(**expr, AstExpr::AttrAccessExpr(_)))
+    );
+}
+
+#[test]
+fn test_parse_prefix_operator_binding() {
+    let parse = |src| parse_expression(crate::lexer::Lexer::new(src)).unwrap();
+    let top_op = |src| match parse(src) {
+        AstExpr::BinExpr(bin) => (bin.op, bin.l),
+        other => panic!("Expected BinExpr, got {:?}", other),
+    };
+
+    for (src, op) in [
+        ("-a * b", Operator::Mul),
+        ("-a + b", Operator::Add),
+        ("-a == b", Operator::Equals),
+    ] {
+        let (top, l) = top_op(src);
+        assert_eq!(top, op, "{}", src);
+        assert!(matches!(*l, AstExpr::UnaryExpr(_)), "{}", src);
+    }
+    assert_eq!(parse("-a * b").to_string(), "(-a * b)");
+    assert_eq!(parse("-a + b").to_string(), "(-a + b)");
+
+    // `not` follows Python instead, applying to whole comparisons
+    let (top, l) = top_op("not a and b");
+    assert_eq!(top, Operator::And);
+    assert!(matches!(*l, AstExpr::NotExpr(ref inner) if matches!(**inner, AstExpr::LitExpr(_))));
+    assert_eq!(parse("not a and b").to_string(), "(not a and b)");
+}
+
+#[test]
+fn test_parse_call_and_attr_access_semis() {
+    let cases = [
+        ("foo()", false),
+        ("foo();", true),
+        ("foo.bar", false),
+        ("foo.bar;", true),
+        ("x = 1;\nfoo().bar()", false),
+    ];
+    for (src, has_semi) in cases {
+        let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+        assert_eq!(parsed.has_semi, has_semi, "{}", src);
+    }
+}
+
+#[test]
+fn test_parse_floor_div_grouping() {
+    let cases = [
+        ("a + b // c", "(a + (b // c))"),
+        ("a // b or c", "((a // b) or c)"),
+        // `//` is always floor division, comments start with `#`
+        ("a // b # c", "(a // b)"),
+    ];
+    for (src, grouped) in cases {
+        let parsed = parse_expression(crate::lexer::Lexer::new(src)).unwrap();
+        assert_eq!(parsed.to_string(), grouped, "{}", src);
+    }
+
+    // Shares a level with `*`, `/` and `%`, so groups exactly like `/` does
+    let print = |src: &str| parse_expression(crate::lexer::Lexer::new(src)).unwrap().to_string();
+    for src in ["a // b * c", "a * b // c", "a // b % c", "a % b // c"] {
+        assert_eq!(print(src), print(&src.replace("//", "/")).replace('/', "//"), "{}", src);
+    }
+}
+
+#[test]
+fn test_parse_parenthesized_expr() {
+    let parse = |src| parse_expression(crate::lexer::Lexer::new(src)).map(|e| e.to_string());
+    assert_eq!(parse("(a + b) * c"), Ok("((a + b) * c)".to_string()));
+    assert_eq!(parse("c * (a + b)"), Ok("(c * (a + b))".to_string()));
+    assert_eq!(parse("((a + b)) * (c)"), Ok("((a + b) * c)".to_string()));
+    assert_eq!(parse("(a + b).c(d)"), Ok("(a + b).c(d)".to_string()));
+
+    assert_eq!(parse("(a + b"), Err(ParseErr::UnexpectedEnd));
+    assert!(matches!(parse("a + b) * c"), Err(ParseErr::TrailingInput(5, 1))));
+}
+
+#[test]
+fn test_parse_inline_conditionals() {
+    let src = "def f(x: int) -> int:\n    if x != 0: return x;\n    0\n\ny = a if c else b;\nz = a + 1 if c else b if d else e;\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    let AstStmt::FnDef(f) = &parsed.stmts[0].node else {
+        panic!("Expected FnDef, got {:?}", parsed.stmts[0].node);
+    };
+    let AstStmt::Expr {
+        expr: AstExpr::ConditionalExpr(cond),
+        ..
+    } = &f.body.stmts[0].node
+    else {
+        panic!("Expected conditional, got {:?}", f.body.stmts[0].node);
+    };
+    assert!(cond.inline && cond.else_block.is_none());
+    assert!(matches!(cond.if_block.stmts[0].node, AstStmt::Return(_)));
+    assert_eq!(f.body.stmts.len(), 2);
+
+    let AstStmt::Assignment {
+        assigned: AstExpr::ConditionalExpr(ternary),
+        ..
+    } = &parsed.stmts[1].node
+    else {
+        panic!("Expected ternary, got {:?}", parsed.stmts[1].node);
+    };
+    assert!(ternary.is_ternary());
+
+    let printed = parsed.to_string();
+    assert!(printed.contains("    if (x != 0): return x;\n"), "{}", printed);
+    assert!(printed.contains("y = (a if c else b);\n"), "{}", printed);
+    assert!(
+        printed.contains("z = ((a + 1) if c else (b if d else e));\n"),
+        "{}",
+        printed
+    );
+    assert_eq!(try_parse(crate::lexer::Lexer::new(&printed)).unwrap(), parsed);
+}
+
+#[test]
+fn test_parse_elif_chain() {
+    let src = "if a:\n    1\nelif b:\n    2\nelif c:\n    3\nelse:\n    4\n";
+    let parsed = try_parse(crate::lexer::Lexer::new(src)).unwrap();
+
+    let AstStmt::Expr {
+        expr: AstExpr::ConditionalExpr(cond),
+        has_semi: false,
+    } = &parsed.stmts[0].node
+    else {
+        panic!("Expected conditional, got {:?}", parsed.stmts[0].node);
+    };
+    let conditions: Vec<_> = cond.elif_branches.iter().map(|(c, _)| c.to_string()).collect();
+    assert_eq!(conditions, vec!["b", "c"]);
+    assert!(matches!(cond.else_block.as_deref(), Some(AstExpr::BlockExpr(_))));
+
+    let printed = parsed.to_string();
+    assert!(printed.starts_with(src), "{}", printed);
+    assert_eq!(try_parse(crate::lexer::Lexer::new(&printed)).unwrap(), parsed);
+    assert!(matches!(
+        try_parse(crate::lexer::Lexer::new("if a:\n    1\nelif b\n    2\n")),
+        Err(ParseErr::ExpectedColon(18, 1, "Condition"))
+    ));
+}
+
+#[test]
+fn test_error_codes_are_unique() {
+    let span = Span::new(0, 1);
+    // One of every variant; `code` matching exhaustively means a new variant
+    // can't go without a code, and adding it here checks it isn't taken
+    let lex_errs = [
+        LexErr::UnknownToken(0, None),
+        LexErr::UnterminatedString(0),
+        LexErr::InvalidIndent(0, 1),
+        LexErr::UnexpectedIndent(0, 1),
+        LexErr::IntOverflow(0, 1),
+        LexErr::InvalidSuffix(0, 1),
+        LexErr::UnbalancedIndent(0),
+    ];
+    let parse_errs = [
+        ParseErr::InvalidExpressionStart(0, 1),
+        ParseErr::UnexpectedEnd,
+        ParseErr::UnexpectedIndent(0, 1, 0),
+        ParseErr::UnexpectedStmt(0, 1, span),
+        ParseErr::TrailingInput(0, 1),
+        ParseErr::ExpectedTypeAnnotation(0, 1),
+        ParseErr::UnexpectedMut(0, 1),
+        ParseErr::ExpectedNewline(0, 1),
+        ParseErr::ExpectedSemi(0, 1),
+        ParseErr::ExpectedColon(0, 1, "Function Signature"),
+        ParseErr::ExpectedFnName(0, 1),
+        ParseErr::ExpectedDeclaration(0, 1),
+        ParseErr::ExpectedPattern(0, 1),
+        ParseErr::UnknownAttribute(0, 1),
+        ParseErr::ReservedKeyword(0, 1, "def".to_string()),
+        ParseErr::RecursionLimitExceeded(0),
+        ParseErr::InvalidAssignmentTarget(0, 1),
+        ParseErr::SpreadAfterKeywordArg(0, 1),
+        ParseErr::SpreadAfterKeywordSpread(0, 1),
+        ParseErr::ExpectedToken(0, 1, ")".to_string()),
+        ParseErr::UnclosedParen(0),
+        ParseErr::UnclosedBlock(span),
+        ParseErr::ExpectedExpressionAfterUnary(0, 1),
+        ParseErr::OptionalChainOnNonNullable(span),
+    ];
+
+    let codes = lex_errs
+        .iter()
+        .map(LexErr::code)
+        .chain(parse_errs.iter().map(ParseErr::code))
+        .collect::<Vec<_>>();
+    let unique = codes.iter().collect::<std::collections::HashSet<_>>();
+    assert_eq!(unique.len(), codes.len(), "{:?}", codes);
+    assert!(codes.iter().all(|c| c.len() == 5 && c.starts_with('E')), "{:?}", codes);
+
+    assert_eq!(ParseErr::InvalidExpressionStart(0, 1).code(), "E0001");
+    assert_eq!(ParseErr::LexErr(LexErr::UnterminatedString(0)).code(), "E0102");
+}
+
+#[test]
+fn test_parse_stmts_sharing_a_line() {
+    let parsed = try_parse(crate::lexer::Lexer::new(
+        "a = 1; b = 2;\ndef f() -> int:\n    f(); x = 1; x\n",
+    ))
+    .unwrap();
+
+    assert!(matches!(
+        parsed.stmts().collect::<Vec<_>>().as_slice(),
+        [
+            AstStmt::Assignment { .. },
+            AstStmt::Assignment { .. },
+            AstStmt::FnDef(_)
+        ]
+    ));
+    let AstStmt::FnDef(f) = &parsed.stmts[2].node else {
+        unreachable!()
+    };
+    assert_eq!(f.body.stmts.len(), 3);
+    assert!(f.body.is_tail_expression());
+}
+
+#[test]
+fn test_parse_missing_header_colon() {
+    let cases = [
+        ("def f() -> int\n    1\n", 14, "Function Signature"),
+        ("struct Point\n    x: int\n", 12, "Struct Name"),
+        ("match x\n    case 1:\n        2\n", 7, "Match Subject"),
+        ("match x:\n    case 1\n        2\n", 19, "Case Pattern"),
+        ("if a\n    1\n", 4, "Condition"),
+        ("if a:\n    1\nelse\n    2\n", 16, "`else`"),
+    ];
+    for (src, ix, after) in cases {
+        assert_eq!(
+            try_parse(crate::lexer::Lexer::new(src)),
+            Err(ParseErr::ExpectedColon(ix, 1, after)),
+            "{}",
+            src
+        );
+    }
+}
+
+#[test]
+fn test_parse_fn_optional_return_type() {
+    let src = "def f(x: int) -> int:\n    x\n";
+    let fn_def = parse_fn_def(&mut ParseStream::new(crate::lexer::Lexer::new(src)), 0).unwrap();
+    assert_eq!(fn_def.return_type, TypeAnnotation::Dynamic("int"));
+    assert_eq!(fn_def.to_string(), src);
+
+    let src = "def f():\n    print(1);\n";
+    let fn_def = parse_fn_def(&mut ParseStream::new(crate::lexer::Lexer::new(src)), 0).unwrap();
+    assert_eq!(fn_def.return_type, TypeAnnotation::Unit);
+    assert_eq!(fn_def.to_string(), src);
+}
+
+#[cfg(test)]
+fn fn_name<'src>(block: &AstBlock<'src>, ix: usize) -> &'src str {
+    match &block.stmts[ix].node {
+        AstStmt::FnDef(FnDef {
+            name:
+                AstLiteral::Ident(Spanned {
+                    node: Token::Ident(name),
+                    ..
+                }),
+            ..
+        }) => name,
+        stmt => panic!("Expected FnDef, got {:?}", stmt),
+    }
+}
+
+#[test]
+fn test_reparse_incremental_only_reparses_edited_fn() {
+    let old_src = "def f(x: int) -> int:\n    x + 1\n\ndef g() -> int:\n    2\n";
+    let offset = old_src.find('1').unwrap();
+    let edit = TextEdit {
+        offset,
+        delete_len: 1,
+        insert: "10",
+    };
+    let new_src = old_src.replacen('1', "10", 1);
+
+    let old_ast = try_parse(Lexer::new(old_src)).unwrap();
+    let reparsed = reparse_incremental(old_ast, old_src, &new_src, edit).unwrap();
+    let full = try_parse(Lexer::new(&new_src)).unwrap();
+    assert_eq!(reparsed, full);
+
+    let spans = |b: &AstBlock<'_>| b.stmts.iter().map(|s| s.span).collect::<Vec<_>>();
+    assert_eq!(spans(&reparsed), spans(&full));
+    assert_eq!(reparsed.span, full.span);
+    let AstStmt::FnDef(g) = &reparsed.stmts[1].node else {
+        unreachable!()
+    };
+    let AstStmt::FnDef(full_g) = &full.stmts[1].node else {
+        unreachable!()
+    };
+    assert_eq!(spans(&g.body), spans(&full_g.body));
+
+    // Names borrow from whichever source was lexed to find them, so `g`
+    // still pointing into the old source means it wasn't parsed again
+    let within = |name: &str, src: &str| src.as_bytes().as_ptr_range().contains(&name.as_ptr());
+    assert!(within(fn_name(&reparsed, 0), &new_src));
+    assert!(within(fn_name(&reparsed, 1), old_src));
+}
+
+#[test]
+fn test_reparse_incremental_falls_back_to_full_parse() {
+    // Edits outside any definition, and ones that split a definition in two
+    let old_src = "x = 1;\ndef f() -> int:\n    1\n";
+    for (offset, delete_len, insert) in [(4, 1, "2"), (28, 0, "\ndef g() -> int:\n    3")] {
+        let mut new_src = old_src.to_string();
+        new_src.replace_range(offset..offset + delete_len, insert);
+        let edit = TextEdit {
+            offset,
+            delete_len,
+            insert,
+        };
+
+        let old_ast = try_parse(Lexer::new(old_src)).unwrap();
+        let reparsed = reparse_incremental(old_ast, old_src, &new_src, edit).unwrap();
+        assert_eq!(reparsed, try_parse(Lexer::new(&new_src)).unwrap(), "{}", new_src);
+    }
+}
+
+#[test]
+fn test_parse_unclosed_call_paren() {
+    for (src, open_ix) in [("f(1, 2", 1), ("f(1, 2\nx = 1;\n", 1), ("y = g(h(1), ", 5)] {
+        assert_eq!(
+            try_parse(Lexer::new(src)),
+            Err(ParseErr::UnclosedParen(open_ix)),
+            "{}",
+            src
+        );
+    }
+
+    // A stray token is still reported as itself
+    assert_eq!(
+        try_parse(Lexer::new("f(1 2)")),
+        Err(ParseErr::ExpectedToken(4, 1, ")".to_string()))
+    );
+}
+
+#[test]
+fn test_parse_let_and_const() {
+    let src = "let a = 10;\nlet mut b: int = a;\nconst PI = 3;\na = 11;\n";
+    let block = try_parse(Lexer::new(src)).unwrap();
+
+    let decls: Vec<_> = block
+        .stmts()
+        .filter_map(|s| match s {
+            AstStmt::Let {
+                name,
+                mutable,
+                constant,
+                ..
+            } => Some((name.to_string(), *mutable, *constant)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        decls,
+        vec![
+            ("a".to_string(), false, false),
+            ("b: int".to_string(), true, false),
+            ("PI".to_string(), false, true),
+        ]
+    );
+    assert!(matches!(block.stmts[3].node, AstStmt::Assignment { .. }));
+    assert_eq!(block.to_string(), src);
+
+    assert_eq!(
+        try_parse(Lexer::new("let if = 1;\n")),
+        Err(ParseErr::ReservedKeyword(4, 2, "if".to_string()))
+    );
+}
+
+#[test]
+fn test_parse_unclosed_block() {
+    for (src, colon) in [
+        ("def f() -> int:\n", 14),
+        ("def f() -> int:", 14),
+        ("x = 1;\nstruct P:\n\n", 15),
+        ("if a:\n    1\nelse:\n", 16),
+        ("match x:\n", 7),
+    ] {
+        assert_eq!(
+            try_parse(Lexer::new(src)),
+            Err(ParseErr::UnclosedBlock(Span::new(colon, colon + 1))),
+            "{}",
+            src
+        );
+    }
+}
@@ -0,0 +1,490 @@
+use crate::ast::{
+    AstAttribute, AstBlock, AstConditional, AstExpr, AstLiteral, AstStmt, AstStructField, CallArg, FnDef,
+    TypeAnnotation,
+};
+use crate::token::Token;
+use std::fmt::Write;
+
+/// Prints the AST back out as source. All indentation is worked out here; the
+/// `Display` impls in `ast.rs` are `Formatter::default()`.
+///
+/// The other settings only kick in when changed from their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Formatter {
+    pub indent_width: usize,
+    /// Calls with more than one argument that would run past this width are
+    /// broken into the vertical, one-arg-per-line form. `0` means no limit.
+    pub max_line_width: usize,
+    /// Emit a trailing comma after the last argument of a vertical call.
+    pub trailing_commas: bool,
+    /// Order struct fields alphabetically instead of in source order.
+    pub sort_struct_fields: bool,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Formatter {
+            indent_width: 4,
+            max_line_width: 0,
+            trailing_commas: false,
+            sort_struct_fields: false,
+        }
+    }
+}
+
+impl Formatter {
+    fn spaces(&self, indent: usize) -> String {
+        " ".repeat(indent * self.indent_width)
+    }
+
+    pub fn fmt_block(&self, block: &AstBlock<'_>) -> String {
+        let spaces = self.spaces(block.indent);
+        let mut out = String::new();
+        for pragma in &block.pragmas {
+            out.push_str(&format!("{}\n", pragma));
+        }
+        for stmt in &block.stmts {
+            out.push_str(&spaces);
+            out.push_str(&self.fmt_stmt(stmt, block.indent));
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn fmt_stmt(&self, stmt: &AstStmt<'_>, indent: usize) -> String {
+        match stmt {
+            AstStmt::StructDef {
+                attributes,
+                name,
+                fields,
+                methods,
+            } => self.fmt_struct_def(attributes, name, fields, methods, indent),
+            AstStmt::FnDef(fn_def) => self.fmt_def(fn_def, ", "),
+            AstStmt::Assignment { target, assigned } => {
+                let mut out = format!(
+                    "{} = {}",
+                    self.fmt_expr(target, indent),
+                    self.fmt_expr(assigned, indent)
+                );
+                if !assigned.ends_in_block() {
+                    out.push(';');
+                }
+                out
+            }
+            AstStmt::Let {
+                name,
+                mutable,
+                constant,
+                value,
+            } => {
+                let keyword = match (constant, mutable) {
+                    (true, _) => "const",
+                    (false, true) => "let mut",
+                    (false, false) => "let",
+                };
+                let mut out = format!("{} {} = {}", keyword, name, self.fmt_expr(value, indent));
+                if !value.ends_in_block() {
+                    out.push(';');
+                }
+                out
+            }
+            AstStmt::Expr { expr, has_semi } => {
+                let mut out = self.fmt_expr(expr, indent);
+                if *has_semi && !expr.ends_in_block() {
+                    out.push(';');
+                }
+                out
+            }
+            AstStmt::Return(e) => format!("return {};", self.fmt_expr(e, indent)),
+            AstStmt::Match { scrutinee, arms } => {
+                let mut out = format!("match {}:\n", self.fmt_expr(scrutinee, indent));
+                for arm in arms {
+                    let case_spaces = self.spaces(arm.body.indent.saturating_sub(1));
+                    out.push_str(&format!("{}case {}:\n", case_spaces, arm.pattern));
+                    out.push_str(&self.fmt_block(&arm.body));
+                }
+                out
+            }
+        }
+    }
+
+    /// `indent` is the nesting level of the line the expression starts on,
+    /// used to lay out vertical calls and `else` branches.
+    pub fn fmt_expr(&self, expr: &AstExpr<'_>, indent: usize) -> String {
+        match expr {
+            AstExpr::BinExpr(bin) => format!(
+                "({} {} {})",
+                self.fmt_expr(&bin.l, indent),
+                bin.op,
+                self.fmt_expr(&bin.r, indent)
+            ),
+            AstExpr::UnaryExpr(unary) => format!("{}{}", unary.op, self.fmt_expr(&unary.expr, indent)),
+            AstExpr::NotExpr(expr) => format!("not {}", self.fmt_expr(expr, indent)),
+            AstExpr::LitExpr(lit) => lit.to_string(),
+            AstExpr::ConditionalExpr(cond) => self.fmt_conditional(cond, indent),
+            AstExpr::BlockExpr(block) => format!("do:\n{}", self.fmt_block(block)),
+            AstExpr::CallExpr(call) => {
+                let called = self.fmt_expr(&call.called_expr, indent) + if call.optional { "?" } else { "" };
+                self.fmt_call(&called, &call.args, indent)
+            }
+            AstExpr::AttrAccessExpr(aa) => {
+                format!("{}{}{}", self.fmt_expr(&aa.expr, indent), aa.accessor(), aa.attribute)
+            }
+            AstExpr::IndexExpr(index) => format!(
+                "{}[{}]",
+                self.fmt_expr(&index.target, indent),
+                self.fmt_expr(&index.index, indent)
+            ),
+            AstExpr::SliceExpr(slice) => {
+                let bound =
+                    |b: &Option<Box<AstExpr<'_>>>| b.as_ref().map(|e| self.fmt_expr(e, indent)).unwrap_or_default();
+                format!(
+                    "{}[{}:{}]",
+                    self.fmt_expr(&slice.target, indent),
+                    bound(&slice.start),
+                    bound(&slice.end)
+                )
+            }
+            AstExpr::StructInitExpr(si) => self.fmt_call(&si.name.to_string(), &si.fields, indent),
+            AstExpr::FnDefExpr(fn_def) => self.fmt_fn_def(fn_def),
+        }
+    }
+
+    /// A `def` in expression position, see `AstExpr::FnDefExpr`
+    pub fn fmt_fn_def(&self, fn_def: &FnDef<'_>) -> String {
+        // These have always had their params joined without a space
+        self.fmt_def(fn_def, ",")
+    }
+
+    fn fmt_def(&self, fn_def: &FnDef<'_>, arg_sep: &str) -> String {
+        let mut args = String::new();
+        for (i, arg) in fn_def.args.iter().enumerate() {
+            if i > 0 {
+                args.push_str(arg_sep);
+            }
+            write!(args, "{}", arg).expect("Writing to a String can't fail");
+        }
+        // Attributes line up with the `def`, one level out from its body
+        let def_indent = fn_def.body.indent.saturating_sub(1);
+        let return_type = match fn_def.return_type {
+            TypeAnnotation::Unit => String::new(),
+            ref ty => format!(" -> {}", ty),
+        };
+        format!(
+            "{}def {}{}({}){}:\n{}{}",
+            fmt_attributes(&fn_def.attributes, &self.spaces(def_indent)),
+            fn_def.name,
+            fmt_type_params(&fn_def.type_params),
+            args,
+            return_type,
+            fmt_docstring(fn_def.docstring.as_ref(), &self.spaces(fn_def.body.indent)),
+            self.fmt_block(&fn_def.body)
+        )
+    }
+
+    pub fn fmt_struct_def(
+        &self,
+        attributes: &[AstAttribute<'_>],
+        name: &AstLiteral<'_>,
+        fields: &[AstStructField<'_>],
+        methods: &[FnDef<'_>],
+        indent: usize,
+    ) -> String {
+        let spaces = self.spaces(indent + 1);
+        let mut fields = fields.iter().collect::<Vec<_>>();
+        if self.sort_struct_fields {
+            fields.sort_by_key(|f| f.name.to_string());
+        }
+        let fields = fields
+            .iter()
+            .map(|a| {
+                let comments: String = a.comments.iter().map(|c| format!("{}#{}\n", spaces, c)).collect();
+                format!("{}{}{}", comments, spaces, a)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        // Methods are set apart by a blank line, like in a Python class
+        let methods = methods
+            .iter()
+            .map(|m| format!("{}{}", spaces, self.fmt_fn_def(m)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!(
+            "{}struct {}:\n{}\n\n{}",
+            fmt_attributes(attributes, ""),
+            name,
+            fields,
+            methods
+        )
+    }
+
+    pub fn fmt_conditional(&self, cond: &AstConditional<'_>, indent: usize) -> String {
+        if cond.inline {
+            return self.fmt_inline_conditional(cond, indent);
+        }
+
+        // `elif` and `else` line up with the `if`, on the line the conditional starts on
+        let spaces = self.spaces(indent);
+        let elifs: String = cond
+            .elif_branches
+            .iter()
+            .map(|(condition, block)| {
+                format!(
+                    "{}elif {}:\n{}",
+                    spaces,
+                    self.fmt_expr(condition, indent),
+                    self.fmt_block(block)
+                )
+            })
+            .collect();
+        let else_ = match &cond.else_block {
+            Some(expr) => match expr.as_ref() {
+                AstExpr::ConditionalExpr(_) => format!("{}else {}", spaces, self.fmt_expr(expr, indent)),
+                AstExpr::BlockExpr(block) => format!("{}else:\n{}", spaces, self.fmt_block(block)),
+                _ => format!("{}else:\n{}", spaces, self.fmt_expr(expr, indent)),
+            },
+            None => String::new(),
+        };
+
+        format!(
+            "if {}:\n{}{}{}",
+            self.fmt_expr(&cond.condition, indent),
+            self.fmt_block(&cond.if_block),
+            elifs,
+            else_
+        )
+    }
+
+    fn fmt_inline_conditional(&self, cond: &AstConditional<'_>, indent: usize) -> String {
+        let condition = self.fmt_expr(&cond.condition, indent);
+        let body = cond.if_block.stmts.iter().map(|s| self.fmt_stmt(s, indent + 1));
+        match &cond.else_block {
+            // Parenthesized like binary expressions, so it keeps its grouping
+            // wherever it ends up
+            Some(fallback) => format!(
+                "({} if {} else {})",
+                body.collect::<Vec<_>>().join(" "),
+                condition,
+                self.fmt_expr(fallback, indent)
+            ),
+            None => format!("if {}: {}", condition, body.collect::<Vec<_>>().join(" ")),
+        }
+    }
+
+    fn fmt_call(&self, called: &str, args: &[CallArg<'_>], indent: usize) -> String {
+        let args = args
+            .iter()
+            .map(|a| match (&a.name, a.spread) {
+                (Some(name), _) => format!(
+                    "{}={}",
+                    self.fmt_expr(name, indent + 1),
+                    self.fmt_expr(&a.expr, indent + 1)
+                ),
+                (None, Some(spread)) => format!("{}{}", spread, self.fmt_expr(&a.expr, indent + 1)),
+                (None, None) => self.fmt_expr(&a.expr, indent + 1),
+            })
+            .collect::<Vec<_>>();
+
+        let inline = format!("{}({})", called, args.join(", "));
+        let too_wide = self.max_line_width > 0 && self.spaces(indent).len() + inline.len() > self.max_line_width;
+        if args.len() < 2 || !too_wide {
+            return inline;
+        }
+
+        // Matches the vertical call syntax accepted by `parse_call_expr`
+        let arg_spaces = self.spaces(indent + 1);
+        let mut out = format!("{}(\n", called);
+        for (i, arg) in args.iter().enumerate() {
+            let is_last = i == args.len() - 1;
+            out.push_str(&arg_spaces);
+            out.push_str(arg);
+            if !is_last || self.trailing_commas {
+                out.push(',');
+            }
+            out.push('\n');
+        }
+        out.push_str(&self.spaces(indent));
+        out.push(')');
+        out
+    }
+}
+
+// Each attribute goes on its own line, so everything after the first one needs
+// to be re-indented to where the declaration starts
+fn fmt_attributes(attributes: &[AstAttribute<'_>], indent: &str) -> String {
+    attributes.iter().map(|a| format!("{}\n{}", a, indent)).collect()
+}
+
+fn fmt_docstring(docstring: Option<&Token<'_>>, indent: &str) -> String {
+    match docstring {
+        Some(&Token::StrLiteral { value, triple }) => format!("{}{}\n", indent, fmt_str(value, triple)),
+        _ => String::new(),
+    }
+}
+
+/// A string literal's value back between the quotes it was written with
+pub fn fmt_str(value: &str, triple: bool) -> String {
+    let quotes = if triple { "\"\"\"" } else { "\"" };
+    format!("{}{}{}", quotes, value, quotes)
+}
+
+fn fmt_type_params(type_params: &[&str]) -> String {
+    if type_params.is_empty() {
+        return String::new();
+    }
+    format!("[{}]", type_params.join(", "))
+}
+
+#[cfg(test)]
+fn parse_src(src: &str) -> AstBlock<'_> {
+    crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap()
+}
+
+#[test]
+fn test_printed_source_round_trips() {
+    let sources = [
+        "a = (b + c) * d;\n",
+        "f(1, x=2).y;\n",
+        "f(\"x\", y=\"\"\"say \"hi\" now\"\"\");\n",
+        "def f(x: str) -> int:\n    match x:\n        case \"a\":\n            1\n        case _:\n            0\n",
+        "xs[i] = ys[1:n];\n",
+        "def f[T](x: T, y: mut int = -1) -> T:\n    \"Picks x\"\n    if y == 0:\n        x\n    else:\n        g(x);\n",
+        "def f() -> str:\n    \"\"\"Say \"hi\".\n    Then go.\"\"\"\n    g()\n",
+        "def f() -> int:\n    #[inline]\n    def g() -> int:\n        1\n    g()\n",
+        "def f(x: int) -> int:\n    match x:\n        case 1:\n            x\n        case _:\n            0\n    x\n",
+        "struct Point:\n    x: int\n    y: int\n\n    def norm(self: Self) -> int:\n        return self.x;\n\np = Point(x=1, y=2);\n",
+    ];
+
+    for src in sources {
+        let block = parse_src(src);
+        let printed = Formatter::default().fmt_block(&block);
+        assert_eq!(parse_src(&printed), block, "{}", printed);
+        assert_eq!(block.to_string(), printed);
+    }
+}
+
+#[test]
+fn test_nested_conditional_keywords_line_up() {
+    let src = "def f(a: int, b: int) -> int:\n    if a == 1:\n        if b == 1:\n            a\n        elif b == 2:\n            b\n        else:\n            0\n    else if a == 2:\n        x = if b == 1:\n            1\n        else:\n            2\n        x\n    else:\n        -1\n";
+    let block = parse_src(src);
+
+    let printed = Formatter::default().fmt_block(&block);
+    assert_eq!(
+        printed,
+        "def f(a: int, b: int) -> int:\n    if (a == 1):\n        if (b == 1):\n            a\n        elif (b == 2):\n            b\n        else:\n            0\n\n    else if (a == 2):\n        x = if (b == 1):\n            1\n        else:\n            2\n\n        x\n    else:\n        -1\n\n\n"
+    );
+    assert_eq!(parse_src(&printed), block);
+
+    let formatter = Formatter {
+        indent_width: 2,
+        ..Formatter::default()
+    };
+    assert_eq!(
+        formatter.fmt_block(&block),
+        "def f(a: int, b: int) -> int:\n  if (a == 1):\n    if (b == 1):\n      a\n    elif (b == 2):\n      b\n    else:\n      0\n\n  else if (a == 2):\n    x = if (b == 1):\n      1\n    else:\n      2\n\n    x\n  else:\n    -1\n\n\n"
+    );
+}
+
+#[test]
+fn test_formatter_breaks_wide_calls() {
+    let block = parse_src("result = some_function(first_argument, second_argument);\n");
+    let formatter = Formatter {
+        max_line_width: 40,
+        trailing_commas: true,
+        ..Formatter::default()
+    };
+
+    let out = formatter.fmt_block(&block);
+    assert_eq!(
+        out,
+        "result = some_function(\n    first_argument,\n    second_argument,\n);\n"
+    );
+    assert_eq!(parse_src(&out), block);
+
+    let formatter = Formatter {
+        trailing_commas: false,
+        ..formatter
+    };
+    let out = formatter.fmt_block(&block);
+    assert!(out.ends_with("    second_argument\n);\n"), "{}", out);
+    assert_eq!(parse_src(&out), block);
+}
+
+#[test]
+fn test_formatter_keeps_narrow_calls_inline() {
+    let block = parse_src("f(a, b);\n");
+    let formatter = Formatter {
+        max_line_width: 40,
+        ..Formatter::default()
+    };
+
+    assert_eq!(formatter.fmt_block(&block), "f(a, b);\n");
+
+    // How the call was written doesn't matter, only how wide it prints
+    let vertical = parse_src("f(\n    a,\n    b\n);\n");
+    assert_eq!(formatter.fmt_block(&vertical), "f(a, b);\n");
+}
+
+#[test]
+fn test_formatter_sorts_struct_fields() {
+    let a = parse_src("struct Point:\n    y: int\n    x: mut int\n    z: str\n\np = 1;\n");
+    let b = parse_src("struct Point:\n    z: str\n    x: mut int\n    y: int\n\np = 1;\n");
+    let formatter = Formatter {
+        sort_struct_fields: true,
+        ..Formatter::default()
+    };
+
+    assert_ne!(Formatter::default().fmt_block(&a), Formatter::default().fmt_block(&b));
+    assert_eq!(formatter.fmt_block(&a), formatter.fmt_block(&b));
+    assert!(formatter
+        .fmt_block(&a)
+        .starts_with("struct Point:\n    x: mut int\n    y: int\n    z: str\n"));
+}
+
+#[test]
+fn test_formatter_keeps_struct_field_comments() {
+    let src = "# Not about x\nstruct Point:\n    # the x coordinate\n    x: int\n    #   the y one,\n    # over two lines\n    y: int = 0\n\np = 1;\n";
+    let parse = |src| crate::parser::try_parse(crate::lexer::Lexer::new(src).with_comments()).unwrap();
+    let block = parse(src);
+
+    let AstStmt::StructDef { fields, .. } = &block.stmts[0].node else {
+        panic!("Expected StructDef, got {:?}", block.stmts[0].node);
+    };
+    assert_eq!(fields[0].comments, vec![" the x coordinate"]);
+    assert_eq!(fields[1].comments, vec!["   the y one,", " over two lines"]);
+
+    let printed = block.to_string();
+    let body =
+        "struct Point:\n    # the x coordinate\n    x: int\n    #   the y one,\n    # over two lines\n    y: int = 0\n";
+    assert!(printed.starts_with(body), "{}", printed);
+    assert_eq!(parse(&printed), block);
+}
+
+#[test]
+fn test_struct_methods_round_trip() {
+    let src = "struct Point:\n    x: int\n    y: int\n\n    def sum(self: Self) -> int:\n        self.x + self.y\n\n    #[inline]\n    def double(self: Self) -> int:\n        self.sum() * 2\n\np = Point(x=1, y=2);\n";
+    let block = parse_src(src);
+    let AstStmt::StructDef { methods, .. } = &block.stmts[0].node else {
+        panic!("Expected StructDef, got {:?}", block.stmts[0].node);
+    };
+    assert_eq!(methods.len(), 2);
+
+    let printed = block.to_string();
+    assert!(
+        printed.contains("        (self.x + self.y)\n\n    #[inline]\n    def double(self: Self) -> int:\n"),
+        "{}",
+        printed
+    );
+    assert_eq!(parse_src(&printed), block);
+}
+
+#[test]
+fn test_optional_chain_round_trip() {
+    let src = "x = obj?.field?.nested;
+y = obj?.method(1);
+z = f?(a, b);
+";
+    let block = parse_src(src);
+    assert_eq!(block.to_string(), src);
+    assert_eq!(parse_src(&block.to_string()), block);
+}
@@ -1,116 +1,497 @@
 use crate::ast::AstBlock;
 use crate::lexer::LexErr;
 use crate::parser::ParseErr;
+use crate::source::SourceFile;
+use crate::span::Span;
 
 // TODO: Extract the print formatting stuff
 
-pub fn report<'src>(parsed: Result<AstBlock<'src>, ParseErr>, src: &'src str) -> Result<AstBlock<'src>, String> {
-    let err = match parsed {
-        Err(e) => e,
-        Ok(r) => return Ok(r),
-    };
+pub fn report<'src>(parsed: Result<AstBlock<'src>, ParseErr>, src: &'src SourceFile) -> Result<AstBlock<'src>, String> {
+    parsed.map_err(|err| render(&err, src))
+}
 
-    Err(match err {
-        ParseErr::InvalidExpressionStart(ix, len) => print_err(src, "Expected Expression at Position", ix, len),
-        ParseErr::ExpectedSemi(ix, len) => print_err(src, "Expected Semicolon at Position", ix, len),
+/// `err` pointed out in the source, formatted for a terminal
+pub fn render(err: &ParseErr, src: &SourceFile) -> String {
+    match err {
+        ParseErr::LexErr(err) => {
+            let (msg, ix, len) = describe_lex_err(err, src);
+            print_err(src, err.code(), msg, ix, len)
+        }
+        ParseErr::UnexpectedStmt(_, _, prev) => {
+            let note = print_span(src, "Note", "Expression Missing A Semicolon", prev.start, prev.len());
+            let (msg, span) = describe(err, src);
+            let (ix, len) = span.expect("Statements have a span");
+            print_err(src, err.code(), &msg, ix, len) + &note
+        }
+        ParseErr::UnclosedBlock(colon) => {
+            let (msg, _) = describe(err, src);
+            // Just past the last byte, where the block's body should have been
+            let end = Span::new(src.content.len(), src.content.len() + 1);
+            print_multiline_err(src, err.code(), &msg, *colon, end)
+        }
+        err => match describe(err, src) {
+            (msg, Some((ix, len))) => print_err(src, err.code(), &msg, ix, len),
+            (msg, None) => msg,
+        },
+    }
+}
+
+/// The message for `err` and the `(ix, len)` span it points at, if any
+pub fn describe(err: &ParseErr, src: &SourceFile) -> (String, Option<(usize, usize)>) {
+    let (msg, ix, len) = match err {
+        ParseErr::InvalidExpressionStart(ix, len) => ("Expected Expression at Position".to_string(), ix, len),
+        ParseErr::ExpectedSemi(ix, len) => ("Expected Semicolon at Position".to_string(), ix, len),
+        ParseErr::ExpectedExpressionAfterUnary(ix, len) => {
+            ("Expected Expression After Operator at Position".to_string(), ix, len)
+        }
         ParseErr::ExpectedTypeAnnotation(ix, len) => {
-            print_err(src, "Expected Valid Type In Annotation at Position", ix, len)
+            ("Expected Valid Type In Annotation at Position".to_string(), ix, len)
         }
-        ParseErr::ExpectedColon(ix, len) => print_err(src, "Expected Colon Starting Block", ix, len),
-        ParseErr::UnexpectedStmt(ix, len) => print_err(
-            src,
-            "Unexpected Statement (Previous statement may be missing a semicolon)",
+        ParseErr::ExpectedColon(ix, len, after) => (format!("Expected ':' After {} at Position", after), ix, len),
+        ParseErr::UnexpectedStmt(ix, len, prev) => {
+            let (_, prev_line, prev_col) = extract_line(src, prev.start);
+            let msg = format!(
+                "Unexpected Statement (The Expression At {}:{} Is Missing A Semicolon) at Position",
+                prev_line + 1,
+                prev_col + 1
+            );
+            (msg, ix, len)
+        }
+        ParseErr::TrailingInput(ix, len) => ("Unexpected Input After Expression at Position".to_string(), ix, len),
+        ParseErr::UnexpectedIndent(ix, len, expected_level) => (
+            format!("Unexpected Indent Level At Position (Expected {})", expected_level),
             ix,
             len,
         ),
-        ParseErr::UnexpectedIndent(ix, len, expected_level) => print_err(
-            src,
-            format!("Unexpected Indent Level At Position (Expected {})", expected_level).as_str(),
+        ParseErr::ExpectedToken(ix, len, t) => (format!("Expected '{}' at Position", t), ix, len),
+        ParseErr::UnclosedParen(ix) => ("Unclosed '(' Opened at Position".to_string(), ix, &1),
+        ParseErr::UnclosedBlock(colon) => {
+            return (
+                "Block Opened But Never Closed at Position".to_string(),
+                Some((colon.start, colon.len())),
+            )
+        }
+        ParseErr::OptionalChainOnNonNullable(span) => {
+            return (
+                "Optional Chain On A Value That Can't Be Nil at Position".to_string(),
+                Some((span.start, span.len())),
+            )
+        }
+        ParseErr::UnexpectedMut(ix, len) => (
+            "Unexpected `mut` - Only One Is Allowed Per Type. Encountered at Position".to_string(),
             ix,
             len,
         ),
-        ParseErr::ExpectedToken(ix, len, t) => {
-            print_err(src, format!("Expected '{}' at Position", t).as_str(), ix, len)
-        }
-        ParseErr::UnexpectedMut(ix, len) => {
-            let msg = format!("Unexpected `mut` - Only One Is Allowed Per Type. Encountered at Position");
-            print_err(src, msg.as_str(), ix, len)
+        ParseErr::InvalidAssignmentTarget(ix, len) => {
+            ("Cannot Assign To This Expression at Position".to_string(), ix, len)
         }
-        ParseErr::UnexpectedEnd => "Reached Unexpected End Of Input".to_string(),
-        ParseErr::LexErr(err) => match err {
-            LexErr::UnknownToken(ix, _) | LexErr::UnterminatedString(ix) => print_err(src, "Lex Err", ix, 1),
-        },
-        ParseErr::ExpectedFnName(ix, len) => {
-            let msg = format!("Expected Function Name at Position");
-            print_err(src, msg.as_str(), ix, len)
+        ParseErr::SpreadAfterKeywordArg(ix, len) => (
+            "`*` Spread Must Come Before Keyword Arguments at Position".to_string(),
+            ix,
+            len,
+        ),
+        ParseErr::SpreadAfterKeywordSpread(ix, len) => {
+            ("`**` Spread Must Be The Last Argument at Position".to_string(), ix, len)
         }
-        ParseErr::ExpectedNewline(ix, len) => {
-            let msg = format!("Eepected Newline at Position");
-            print_err(src, msg.as_str(), ix, len)
+        ParseErr::RecursionLimitExceeded(ix) => ("Nesting Too Deep at Position".to_string(), ix, &1),
+        ParseErr::UnexpectedEnd => return ("Reached Unexpected End Of Input".to_string(), None),
+        ParseErr::LexErr(err) => {
+            let (msg, ix, len) = describe_lex_err(err, src);
+            return (msg.to_string(), Some((ix, len)));
         }
-    })
+        ParseErr::ExpectedFnName(ix, len) => ("Expected Function Name at Position".to_string(), ix, len),
+        ParseErr::ExpectedDeclaration(ix, len) => (
+            "Expected `def` Or `struct` After Attribute at Position".to_string(),
+            ix,
+            len,
+        ),
+        ParseErr::ExpectedPattern(ix, len) => (
+            "Expected A Literal, Name Or `_` Pattern at Position".to_string(),
+            ix,
+            len,
+        ),
+        ParseErr::UnknownAttribute(ix, len) => ("Unknown Attribute at Position".to_string(), ix, len),
+        ParseErr::ReservedKeyword(ix, len, kw) => (
+            format!(
+                "'{}' Is A Reserved Keyword And Cannot Be Used As A Name. Encountered at Position",
+                kw
+            ),
+            ix,
+            len,
+        ),
+        ParseErr::ExpectedNewline(ix, len) => ("Eepected Newline at Position".to_string(), ix, len),
+    };
+    (msg, Some((*ix, *len)))
 }
 
-fn print_err(src: &str, err_msg: &str, ix: usize, len: usize) -> String {
+pub fn report_lex_err(err: LexErr, src: &SourceFile) -> String {
+    let (msg, ix, len) = describe_lex_err(&err, src);
+    print_err(src, err.code(), msg, ix, len)
+}
+
+/// The message for `err` and the `(ix, len)` span it points at
+pub fn describe_lex_err(err: &LexErr, src: &SourceFile) -> (&'static str, usize, usize) {
+    match *err {
+        LexErr::UnknownToken(ix, _) => ("Lex Err", ix, 1),
+        // The string runs on to the end of the input
+        LexErr::UnterminatedString(ix) => ("Unterminated String at Position", ix, src.content.len() - ix),
+        LexErr::InvalidIndent(ix, len) => ("Indent Must Be A Multiple Of 4 Spaces at Position", ix, len),
+        LexErr::UnexpectedIndent(ix, len) => ("Indented More Than One Level at Position", ix, len),
+        LexErr::UnbalancedIndent(ix) => ("Unbalanced Indentation at Position", ix, 1),
+        LexErr::IntOverflow(ix, len) => ("Integer Literal Too Large at Position", ix, len),
+        LexErr::InvalidSuffix(ix, len) => ("Unknown Integer Suffix at Position", ix, len),
+    }
+}
+
+// Headed with the error's code, e.g. `Error[E0001]`
+fn print_err(src: &SourceFile, code: &str, err_msg: &str, ix: usize, len: usize) -> String {
+    print_span(src, &format!("Error[{}]", code), err_msg, ix, len)
+}
+
+// `kind` heads the message, e.g. `Error` or `Note`
+fn print_span(src: &SourceFile, kind: &str, err_msg: &str, ix: usize, len: usize) -> String {
+    let (ix, end) = clamp_to_chars(&src.content, ix, ix + len);
+    let len = end - ix;
     let (line, line_no, ix_in_line) = extract_line(src, ix);
 
-    let line_w_underline = underline_line(&line, ix_in_line, len);
-    let highlight_line = highlight_line(&line, ix_in_line, len);
+    // Line numbers are 1-based for display
+    let line_no = line_no + 1;
+    let end = (ix + len).min(src.content.len());
+    let n_lines = src.content[ix..end].trim_end_matches('\n').matches('\n').count() + 1;
+    let width = (line_no + n_lines - 1).to_string().len();
+
+    let excerpt = if n_lines == 1 {
+        excerpt_line(line, line_no, ix_in_line, len, width, src.tab_width)
+    } else {
+        // Spans that cross newlines (e.g. a string left open until EOF) get
+        // every line they touch, underlined up to where the span stops
+        let mut excerpt = String::new();
+        let mut ix_in_line = ix_in_line;
+        for n in line_no - 1..line_no - 1 + n_lines {
+            let (Some(line), Some(line_start)) = (src.line(n), src.line_start(n)) else {
+                break;
+            };
+            let span_len = (end - line_start).min(line.len()) - ix_in_line;
+            excerpt.push_str(&excerpt_line(line, n + 1, ix_in_line, span_len, width, src.tab_width));
+            ix_in_line = 0;
+        }
+        excerpt
+    };
 
     format!(
-        "\n\x1b[1mError: {} {}:{}:\x1b[0m\n\n\t{}\n\t{}\n\n",
-        err_msg, line_no, ix, highlight_line, line_w_underline
+        "\n\x1b[1m{}: {} {}:{}:{}:\x1b[0m\n\n{}\n",
+        kind,
+        err_msg,
+        src.path.display(),
+        line_no,
+        ix,
+        excerpt
     )
 }
 
-fn extract_line(s: &str, ix: usize) -> (&str, usize, usize) {
-    // Find the slice before the index
-    let before_ix = &s[..ix];
+// Points at both ends of an error that spans lines, e.g. a block opened at
+// `start` that the input ended before closing at `end`
+fn print_multiline_err(src: &SourceFile, code: &str, err_msg: &str, start: Span, end: Span) -> String {
+    let (start_line, start_no, start_col) = extract_line(src, start.start);
+    let (end_line, end_no, end_col) = extract_line(src, end.start);
+    let width = (end_no + 1).to_string().len();
+
+    let mut excerpt = String::new();
+    if start_no == end_no {
+        let span = Span::new(start.start, end.end);
+        excerpt.push_str(&labelled_line(
+            start_line,
+            start_no + 1,
+            start_col,
+            span.len(),
+            width,
+            "Never Closed",
+            src.tab_width,
+        ));
+    } else {
+        excerpt.push_str(&labelled_line(
+            start_line,
+            start_no + 1,
+            start_col,
+            start.len(),
+            width,
+            "Opened Here",
+            src.tab_width,
+        ));
+        match end_no - start_no {
+            1 => {}
+            // A single line between is shown rather than elided
+            2 => excerpt.push_str(&format!(
+                "{}{}\n",
+                gutter(Some(start_no + 2), width),
+                expand_tabs(src.line(start_no + 1).unwrap_or(""), 0, 0, src.tab_width).0
+            )),
+            _ => excerpt.push_str(&format!("{}...\n", gutter(None, width))),
+        }
+        excerpt.push_str(&labelled_line(
+            end_line,
+            end_no + 1,
+            end_col,
+            end.len(),
+            width,
+            "Never Closed",
+            src.tab_width,
+        ));
+    }
 
-    // Find the slice after the index
-    let after_ix = &s[ix..];
+    format!(
+        "\n\x1b[1mError[{}]: {} {}:{}:{}:\x1b[0m\n\n{}\n",
+        code,
+        err_msg,
+        src.path.display(),
+        start_no + 1,
+        start.start,
+        excerpt
+    )
+}
 
-    // Find the last newline before the index, or the start of the string
-    let start = before_ix.rfind('\n').map_or(0, |n| n + 1);
+// An excerpt line whose underline ends in `label`
+fn labelled_line(
+    line: &str,
+    line_no: usize,
+    ix_in_line: usize,
+    len: usize,
+    width: usize,
+    label: &str,
+    tab_width: usize,
+) -> String {
+    let (line, ix_in_line, len) = expand_tabs(line, ix_in_line, len, tab_width);
+    let line = line.as_str();
+    format!(
+        "{}{}\n{}{} {}\n",
+        gutter(Some(line_no), width),
+        highlight_line(line, ix_in_line, len),
+        gutter(None, width),
+        underline_line(line, ix_in_line, len).trim_end(),
+        label
+    )
+}
 
-    // Find the first newline after the index, or the end of the string
-    let end = after_ix.find('\n').map_or(s.len(), |n| ix + n);
+fn excerpt_line(line: &str, line_no: usize, ix_in_line: usize, len: usize, width: usize, tab_width: usize) -> String {
+    let (line, ix_in_line, len) = expand_tabs(line, ix_in_line, len, tab_width);
+    let line = line.as_str();
+    format!(
+        "{}{}\n{}{}\n",
+        gutter(Some(line_no), width),
+        highlight_line(line, ix_in_line, len),
+        gutter(None, width),
+        underline_line(line, ix_in_line, len)
+    )
+}
 
-    let line_no = before_ix.split('\n').collect::<Vec<&str>>().len() - 1;
+// ` 42 | ` for a source line, `    | ` for the underline beneath it
+fn gutter(line_no: Option<usize>, width: usize) -> String {
+    match line_no {
+        Some(n) => format!(" {:>width$} | ", n),
+        None => format!(" {:>width$} | ", ""),
+    }
+}
 
-    // Return the slice between the found newlines
-    let line = &s[start..end];
+fn extract_line(src: &SourceFile, ix: usize) -> (&str, usize, usize) {
+    let line_no = src.line_index(ix);
+    let start = src.line_start(line_no).expect("Index is within the source");
+    let line = src.line(line_no).expect("Index is within the source");
     (line, line_no, ix - start)
 }
 
-fn highlight_line(line: &str, ix: usize, token_len: usize) -> String {
-    let mut s = line.to_string();
+// Tabs are shown as spaces up to the next multiple of `tab_width` columns,
+// so the underline lines up no matter how the terminal sets its tab stops.
+// Returns the expanded line with `ix` and `len` moved to match.
+fn expand_tabs(line: &str, ix: usize, len: usize, tab_width: usize) -> (String, usize, usize) {
+    let mut expanded = String::with_capacity(line.len());
+    let (mut start, mut end) = (None, None);
+    let mut col = 0;
+    for (i, c) in line.char_indices() {
+        if i == ix {
+            start = Some(expanded.len());
+        }
+        if i == ix + len {
+            end = Some(expanded.len());
+        }
+        if c == '\t' {
+            let n = tab_width.max(1) - col % tab_width.max(1);
+            expanded.push_str(&" ".repeat(n));
+            col += n;
+        } else {
+            expanded.push(c);
+            col += 1;
+        }
+    }
 
-    let start = line.char_indices().nth(ix).map(|(pos, _)| pos).unwrap_or(ix);
+    // Spans may start or run past the end of the line (e.g. at a newline)
+    let past_end = |i: usize| expanded.len() + i.saturating_sub(line.len());
+    let start = start.unwrap_or_else(|| past_end(ix));
+    let end = end.unwrap_or_else(|| past_end(ix + len)).max(start);
+    (expanded, start, end - start)
+}
 
-    let end = line
-        .char_indices()
-        .nth(ix + token_len)
-        .map(|(pos, _)| pos)
-        .unwrap_or(line.len());
+// `ix` and `token_len` are in bytes, like every span
+fn highlight_line(line: &str, ix: usize, token_len: usize) -> String {
+    let (start, end) = clamp_to_line(line, ix, token_len);
 
+    let mut s = line.to_string();
     s.replace_range(start..end, format!("\x1b[91m{}\x1b[0m", &line[start..end]).as_str());
     return s;
 }
 
 fn underline_line(line: &str, ix: usize, token_len: usize) -> String {
-    let mut s = std::iter::repeat(" ").take(line.chars().count()).collect::<String>();
-    let highlight = std::iter::repeat("^").take(token_len).collect::<String>();
-    let red = format!("\x1b[91m{}\x1b[0m", highlight);
+    let (start, end) = clamp_to_line(line, ix, token_len);
 
-    let start = line.char_indices().nth(ix).map(|(pos, _)| pos).unwrap_or(ix);
-
-    let end = line
-        .char_indices()
-        .nth(ix + token_len)
-        .map(|(pos, _)| pos)
-        .unwrap_or(line.len());
+    // The underline is all ASCII, so it's indexed by the characters above it
+    let col = line[..start].chars().count();
+    let width = match &line[start..end] {
+        // Spans past the end of the line (e.g. a newline) still get marked
+        "" => token_len,
+        spanned => spanned.chars().count(),
+    };
 
-    s.replace_range(start..end, red.as_str());
+    let mut s = " ".repeat(line.chars().count());
+    let red = format!("\x1b[91m{}\x1b[0m", "^".repeat(width));
+    s.replace_range(col..(col + width).min(s.len()), red.as_str());
     return s;
 }
+
+// Widens spans that stop partway through a character, e.g. a one byte
+// unknown token, to the whole character
+fn clamp_to_line(line: &str, ix: usize, len: usize) -> (usize, usize) {
+    clamp_to_chars(line, ix, ix + len)
+}
+
+// Keeps `start..end` within `s`, widened out to whole characters so it can
+// always be sliced
+fn clamp_to_chars(s: &str, start: usize, end: usize) -> (usize, usize) {
+    let mut start = start.min(s.len());
+    while !s.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = end.clamp(start, s.len());
+    while !s.is_char_boundary(end) {
+        end += 1;
+    }
+    (start, end)
+}
+
+#[test]
+fn test_print_err_gutter() {
+    let src = SourceFile::new("test.toki", "a = 1;\n".repeat(11) + "b = ?;\n");
+    let ix = src.content.find('?').unwrap();
+    let err = print_err(&src, "E0101", "Lex Err", ix, 1);
+
+    assert!(err.contains("Error[E0101]: Lex Err test.toki:12:"));
+    assert!(err.contains(" 12 | b = \x1b[91m?\x1b[0m;\n    |     \x1b[91m^\x1b[0m "));
+}
+
+#[test]
+fn test_print_err_unterminated_string_spans_lines() {
+    let src = SourceFile::new("test.toki", "a = 1;\nb = \"oops;\nc = 2;\n".to_string());
+    let err = report(crate::parser::try_parse(src.lexer()), &src).unwrap_err();
+
+    assert!(err.contains("Error[E0102]: Unterminated String at Position test.toki:2:"));
+    assert!(!err.contains(" 1 | "));
+    assert!(err.contains(" 2 | b = \x1b[91m\"oops;\x1b[0m\n   |     \x1b[91m^^^^^^\x1b[0m\n"));
+    assert!(err.contains(" 3 | \x1b[91mc = 2;\x1b[0m\n   | \x1b[91m^^^^^^\x1b[0m\n"));
+    assert!(!err.contains(" 4 | "));
+}
+
+#[test]
+fn test_report_missing_semicolon_points_at_previous_expr() {
+    let src = SourceFile::new("test.toki", "x = 1;\nf(x)\ny = 2;\n".to_string());
+    let err = report(crate::parser::try_parse(src.lexer()), &src).unwrap_err();
+
+    assert!(err.contains(
+        "Error[E0004]: Unexpected Statement (The Expression At 2:1 Is Missing A Semicolon) at Position test.toki:3:"
+    ));
+    assert!(err.contains(" 3 | \x1b[91my\x1b[0m = 2;"));
+    assert!(err.contains("Note: Expression Missing A Semicolon test.toki:2:"));
+    assert!(err.contains(" 2 | \x1b[91mf(x)\x1b[0m\n"));
+}
+
+#[test]
+fn test_print_err_after_multi_byte_chars() {
+    let src = SourceFile::new("test.toki", "é = ?;\n".to_string());
+    let err = print_err(&src, "E0101", "Lex Err", src.content.find('?').unwrap(), 1);
+    assert!(
+        err.contains(" 1 | é = \x1b[91m?\x1b[0m;\n   |     \x1b[91m^\x1b[0m "),
+        "{}",
+        err
+    );
+
+    // A multi-byte character under the span gets one caret
+    let src = SourceFile::new("test.toki", "x = é;\n".to_string());
+    let err = print_err(&src, "E0101", "Lex Err", 4, 'é'.len_utf8());
+    assert!(
+        err.contains(" 1 | x = \x1b[91mé\x1b[0m;\n   |     \x1b[91m^\x1b[0m "),
+        "{}",
+        err
+    );
+
+    // Spans that start or end partway through one are widened to cover it
+    for (ix, len) in [(5, 1), (4, 1), (5, 0)] {
+        let err = print_err(&src, "E0101", "Lex Err", ix, len);
+        assert!(err.contains(" 1 | x = \x1b[91mé\x1b[0m;\n"), "{:?} {}", (ix, len), err);
+    }
+
+    // Triple-quoted strings span all six quotes
+    let src = SourceFile::new("test.toki", "do\"\"\"é\"\"\" int\n".to_string());
+    let err = report(crate::parser::try_parse(src.lexer()), &src).unwrap_err();
+    assert!(err.contains(" 1 | do\x1b[91m\"\"\"é\"\"\"\x1b[0m int\n"), "{}", err);
+}
+
+#[test]
+fn test_report_unclosed_block_shows_both_ends() {
+    let src = SourceFile::new("test.toki", "x = 1;\ndef f() -> int:\n\n\n".to_string());
+    let err = report(crate::parser::try_parse(src.lexer()), &src).unwrap_err();
+
+    assert!(
+        err.contains("Error[E0022]: Block Opened But Never Closed at Position test.toki:2:"),
+        "{}",
+        err
+    );
+    assert!(err.contains(" 2 | def f() -> int\x1b[91m:\x1b[0m\n   |               \x1b[91m^\x1b[0m Opened Here\n"));
+    assert!(err.contains("   | ...\n 5 | \x1b[91m\x1b[0m\n   | \x1b[91m^\x1b[0m Never Closed\n"));
+    assert!(!err.contains(" 1 | "));
+
+    // Both ends on one line share an underline
+    let src = SourceFile::new("test.toki", "if a:".to_string());
+    let err = report(crate::parser::try_parse(src.lexer()), &src).unwrap_err();
+    assert!(
+        err.contains(" 1 | if a\x1b[91m:\x1b[0m\n   |     \x1b[91m^\x1b[0m Never Closed\n"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_print_err_expands_tabs() {
+    let src = SourceFile::new("test.toki", "if a:\n\tb = ?;\n".to_string());
+    let err = print_err(&src, "E0101", "Lex Err", src.content.find('?').unwrap(), 1);
+    assert!(
+        err.contains(" 2 |     b = \x1b[91m?\x1b[0m;\n   |         \x1b[91m^\x1b[0m "),
+        "{}",
+        err
+    );
+
+    // Tabs partway through a line only go as far as the next tab stop
+    let src = SourceFile::new("test.toki", "ab\t= ?;\n".to_string()).with_tab_width(8);
+    let err = print_err(&src, "E0101", "Lex Err", src.content.find('?').unwrap(), 1);
+    assert!(
+        err.contains(" 1 | ab      = \x1b[91m?\x1b[0m;\n   |           \x1b[91m^\x1b[0m "),
+        "{}",
+        err
+    );
+
+    // A zero width would make tabs vanish, so it's treated as one
+    let src = SourceFile::new("test.toki", "\t= ?;\n".to_string()).with_tab_width(0);
+    assert_eq!(src.tab_width, 1);
+    let err = print_err(&src, "E0101", "Lex Err", src.content.find('?').unwrap(), 1);
+    assert!(
+        err.contains(" 1 |  = \x1b[91m?\x1b[0m;\n   |    \x1b[91m^\x1b[0m "),
+        "{}",
+        err
+    );
+}
@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+
+use crate::ast::{
+    AstBlock, AstCallExpr, AstConditional, AstExpr, AstIndex, AstLiteral, AstSlice, AstStmt, AstStructInit,
+    AstUnaryExpr, AttrAccess, CallArg, TypeAnnotation,
+};
+use crate::span::{Span, Spanned};
+use crate::token::Token;
+
+/// Rewrites calls to declared struct names (`Point(x=1, y=2)`) into
+/// `AstExpr::StructInitExpr`. Struct definitions are visible throughout the
+/// block that declares them, including before the definition.
+pub fn resolve_struct_inits(block: AstBlock<'_>) -> AstBlock<'_> {
+    StructScopes::default().resolve_block(block)
+}
+
+#[derive(Default)]
+struct StructScopes<'src> {
+    scopes: Vec<Vec<&'src str>>,
+}
+
+impl<'src> StructScopes<'src> {
+    fn is_struct(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(&name))
+    }
+
+    fn resolve_block(&mut self, block: AstBlock<'src>) -> AstBlock<'src> {
+        let declared = block
+            .stmts
+            .iter()
+            .filter_map(|s| match &s.node {
+                AstStmt::StructDef {
+                    name:
+                        AstLiteral::Ident(Spanned {
+                            node: Token::Ident(name),
+                            ..
+                        }),
+                    ..
+                } => Some(*name),
+                _ => None,
+            })
+            .collect();
+
+        self.scopes.push(declared);
+        let stmts = block
+            .stmts
+            .into_iter()
+            .map(|s| s.map(|s| self.resolve_stmt(s)))
+            .collect();
+        self.scopes.pop();
+
+        AstBlock { stmts, ..block }
+    }
+
+    fn resolve_stmt(&mut self, stmt: AstStmt<'src>) -> AstStmt<'src> {
+        match stmt {
+            AstStmt::Expr { expr, has_semi } => AstStmt::Expr {
+                expr: self.resolve_expr(expr),
+                has_semi,
+            },
+            AstStmt::Return(expr) => AstStmt::Return(self.resolve_expr(expr)),
+            AstStmt::Assignment { target, assigned } => AstStmt::Assignment {
+                target: self.resolve_expr(target),
+                assigned: self.resolve_expr(assigned),
+            },
+            AstStmt::Let {
+                name,
+                mutable,
+                constant,
+                value,
+            } => AstStmt::Let {
+                name,
+                mutable,
+                constant,
+                value: self.resolve_expr(value),
+            },
+            AstStmt::FnDef(mut fn_def) => {
+                fn_def.body = self.resolve_block(fn_def.body);
+                AstStmt::FnDef(fn_def)
+            }
+            AstStmt::StructDef {
+                attributes,
+                name,
+                fields,
+                methods,
+            } => AstStmt::StructDef {
+                attributes,
+                name,
+                fields,
+                methods: methods
+                    .into_iter()
+                    .map(|mut m| {
+                        m.body = self.resolve_block(m.body);
+                        m
+                    })
+                    .collect(),
+            },
+            AstStmt::Match { scrutinee, arms } => AstStmt::Match {
+                scrutinee: self.resolve_expr(scrutinee),
+                arms: arms
+                    .into_iter()
+                    .map(|mut arm| {
+                        arm.body = self.resolve_block(arm.body);
+                        arm
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    fn resolve_args(&mut self, args: Vec<CallArg<'src>>) -> Vec<CallArg<'src>> {
+        args.into_iter()
+            .map(|a| CallArg {
+                expr: self.resolve_expr(a.expr),
+                ..a
+            })
+            .collect()
+    }
+
+    fn resolve_expr(&mut self, expr: AstExpr<'src>) -> AstExpr<'src> {
+        match expr {
+            AstExpr::CallExpr(AstCallExpr {
+                called_expr,
+                args,
+                optional,
+            }) => match *called_expr {
+                AstExpr::LitExpr(
+                    name @ AstLiteral::Ident(Spanned {
+                        node: Token::Ident(ident),
+                        ..
+                    }),
+                ) if !optional && self.is_struct(ident) => AstStructInit {
+                    name,
+                    fields: self.resolve_args(args),
+                }
+                .into(),
+                called_expr => AstCallExpr {
+                    called_expr: Box::new(self.resolve_expr(called_expr)),
+                    args: self.resolve_args(args),
+                    optional,
+                }
+                .into(),
+            },
+            AstExpr::BinExpr(bin) => (self.resolve_expr(*bin.l), bin.op, self.resolve_expr(*bin.r)).into(),
+            AstExpr::UnaryExpr(unary) => AstUnaryExpr {
+                op: unary.op,
+                expr: Box::new(self.resolve_expr(*unary.expr)),
+            }
+            .into(),
+            AstExpr::NotExpr(expr) => AstExpr::NotExpr(Box::new(self.resolve_expr(*expr))),
+            AstExpr::ConditionalExpr(cond) => AstConditional {
+                condition: Box::new(self.resolve_expr(*cond.condition)),
+                if_block: self.resolve_block(cond.if_block),
+                elif_branches: cond
+                    .elif_branches
+                    .into_iter()
+                    .map(|(c, b)| (self.resolve_expr(c), self.resolve_block(b)))
+                    .collect(),
+                else_block: cond.else_block.map(|e| Box::new(self.resolve_expr(*e))),
+                inline: cond.inline,
+            }
+            .into(),
+            AstExpr::BlockExpr(block) => AstExpr::BlockExpr(self.resolve_block(block)),
+            AstExpr::AttrAccessExpr(aa) => AttrAccess {
+                attribute: aa.attribute,
+                expr: Box::new(self.resolve_expr(*aa.expr)),
+                optional: aa.optional,
+            }
+            .into(),
+            AstExpr::IndexExpr(index) => AstIndex {
+                target: Box::new(self.resolve_expr(*index.target)),
+                index: Box::new(self.resolve_expr(*index.index)),
+            }
+            .into(),
+            AstExpr::SliceExpr(slice) => AstSlice {
+                target: Box::new(self.resolve_expr(*slice.target)),
+                start: slice.start.map(|e| Box::new(self.resolve_expr(*e))),
+                end: slice.end.map(|e| Box::new(self.resolve_expr(*e))),
+            }
+            .into(),
+            AstExpr::StructInitExpr(si) => AstStructInit {
+                name: si.name,
+                fields: self.resolve_args(si.fields),
+            }
+            .into(),
+            AstExpr::FnDefExpr(mut fn_def) => {
+                fn_def.body = self.resolve_block(fn_def.body);
+                AstExpr::FnDefExpr(fn_def)
+            }
+            lit @ AstExpr::LitExpr(_) => lit,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResolveErr<'src> {
+    // Carries the name of the second definition
+    DuplicateStruct(&'src str, Span),
+}
+
+impl std::fmt::Display for ResolveErr<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateStruct(name, span) => write!(f, "Struct {} is defined again at {}", name, span.start),
+        }
+    }
+}
+
+/// Each struct's fields, in declaration order
+pub type StructLayouts<'a, 'src> = HashMap<&'src str, Vec<(&'src str, &'a TypeAnnotation<'src>)>>;
+
+/// The field names and types of every struct in `block`, including ones
+/// nested in functions, methods and other blocks. Names have to be unique
+/// across the whole program, not just within a scope.
+pub fn collect_struct_layouts<'a, 'src>(
+    block: &'a AstBlock<'src>,
+) -> Result<StructLayouts<'a, 'src>, ResolveErr<'src>> {
+    let mut layouts = HashMap::new();
+    collect_block(block, &mut layouts)?;
+    Ok(layouts)
+}
+
+fn collect_block<'a, 'src>(
+    block: &'a AstBlock<'src>,
+    layouts: &mut StructLayouts<'a, 'src>,
+) -> Result<(), ResolveErr<'src>> {
+    for stmt in block.stmts() {
+        match stmt {
+            AstStmt::StructDef {
+                name: AstLiteral::Ident(name),
+                fields,
+                methods,
+                ..
+            } => {
+                let Token::Ident(ident) = name.node else {
+                    continue;
+                };
+                if layouts.contains_key(ident) {
+                    return Err(ResolveErr::DuplicateStruct(ident, name.span));
+                }
+                let layout = fields.iter().filter_map(|f| match f.name {
+                    Token::Ident(field) => Some((field, &f.type_annotation)),
+                    _ => None,
+                });
+                layouts.insert(ident, layout.collect());
+                for method in methods {
+                    collect_block(&method.body, layouts)?;
+                }
+            }
+            AstStmt::FnDef(fn_def) => collect_block(&fn_def.body, layouts)?,
+            AstStmt::Match { arms, .. } => {
+                for arm in arms {
+                    collect_block(&arm.body, layouts)?;
+                }
+            }
+            AstStmt::Expr { expr, .. }
+            | AstStmt::Return(expr)
+            | AstStmt::Assignment { assigned: expr, .. }
+            | AstStmt::Let { value: expr, .. } => collect_expr(expr, layouts)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Only expressions that own a block can have a `struct` inside
+fn collect_expr<'a, 'src>(
+    expr: &'a AstExpr<'src>,
+    layouts: &mut StructLayouts<'a, 'src>,
+) -> Result<(), ResolveErr<'src>> {
+    match expr {
+        AstExpr::ConditionalExpr(cond) => {
+            collect_block(&cond.if_block, layouts)?;
+            for (_, block) in &cond.elif_branches {
+                collect_block(block, layouts)?;
+            }
+            if let Some(else_block) = &cond.else_block {
+                collect_expr(else_block, layouts)?;
+            }
+            Ok(())
+        }
+        AstExpr::BlockExpr(block) => collect_block(block, layouts),
+        AstExpr::FnDefExpr(fn_def) => collect_block(&fn_def.body, layouts),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+fn resolve_src(src: &str) -> AstBlock<'_> {
+    let block = crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    resolve_struct_inits(block)
+}
+
+#[test]
+fn test_resolve_struct_init() {
+    let block = resolve_src("p = Point(x=1, y=2);\nstruct Point:\n    x: int\n    y: int\n\nq = point(1);\n");
+
+    let AstStmt::Assignment {
+        assigned: AstExpr::StructInitExpr(init),
+        ..
+    } = &block.stmts[0].node
+    else {
+        panic!("Expected StructInitExpr, got {:?}", block.stmts[0].node);
+    };
+    assert_eq!(init.name, AstLiteral::from(Token::Ident("Point")));
+    let field_names = init
+        .fields
+        .iter()
+        .map(|f| f.name.as_ref().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(field_names, vec!["x", "y"]);
+
+    assert!(matches!(
+        &block.stmts[2].node,
+        AstStmt::Assignment {
+            assigned: AstExpr::CallExpr(_),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_resolve_struct_init_respects_scope() {
+    let src = "def f() -> int:\n    struct Inner:\n        x: int\n\n    Inner(x=1)\n\nInner(x=2);\n";
+    let block = resolve_src(src);
+
+    let AstStmt::FnDef(fn_def) = &block.stmts[0].node else {
+        panic!("Expected FnDef, got {:?}", block.stmts[0].node);
+    };
+    assert!(matches!(
+        fn_def.body.stmts[1].node,
+        AstStmt::Expr {
+            expr: AstExpr::StructInitExpr(_),
+            ..
+        }
+    ));
+    assert!(matches!(
+        block.stmts[1].node,
+        AstStmt::Expr {
+            expr: AstExpr::CallExpr(_),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_resolve_keeps_identifier_positions() {
+    let src = "struct Point:\n    x: int\n\np = Point(x=1);\nq = p;\n";
+    let block = resolve_src(src);
+
+    let AstStmt::Assignment {
+        assigned: AstExpr::StructInitExpr(init),
+        ..
+    } = &block.stmts[1].node
+    else {
+        panic!("Expected StructInitExpr, got {:?}", block.stmts[1].node);
+    };
+    let AstLiteral::Ident(name) = &init.name else {
+        panic!("Expected Ident, got {:?}", init.name);
+    };
+    assert_eq!(
+        name.span,
+        crate::span::Span::new(src.find("Point(").unwrap(), src.find("(x").unwrap())
+    );
+
+    let AstStmt::Assignment {
+        assigned: AstExpr::LitExpr(AstLiteral::Ident(p)),
+        ..
+    } = &block.stmts[2].node
+    else {
+        panic!("Expected Ident, got {:?}", block.stmts[2].node);
+    };
+    assert_eq!(p.span.start, src.rfind('p').unwrap());
+}
+
+#[test]
+fn test_collect_struct_layouts() {
+    let src = "struct Point:\n    x: int\n    y: mut int\n\ndef f() -> int:\n    struct Named:\n        name: str\n        at: Point\n\n    1\n";
+    let block = crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    let layouts = collect_struct_layouts(&block).unwrap();
+
+    let layout = |name| {
+        layouts[name]
+            .iter()
+            .map(|(field, ty)| format!("{}: {}", field, ty))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(layouts.len(), 2);
+    assert_eq!(layout("Point"), vec!["x: int", "y: mut int"]);
+    assert_eq!(layout("Named"), vec!["name: str", "at: Point"]);
+
+    let src = "struct P:\n    x: int\n\nif a:\n    struct P:\n        y: int\n\n    1\n";
+    let block = crate::parser::try_parse(crate::lexer::Lexer::new(src)).unwrap();
+    let second = src.rfind("P:").unwrap();
+    assert_eq!(
+        collect_struct_layouts(&block),
+        Err(ResolveErr::DuplicateStruct("P", Span::new(second, second + 1)))
+    );
+}
@@ -0,0 +1,79 @@
+use crate::lexer::Lexer;
+use std::path::{Path, PathBuf};
+
+/// How many columns a tab advances to when pointing at errors
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// A source file's path and contents. Tokens and AST nodes borrow from the
+/// contents, so they can't outlive it.
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub content: String,
+    // Byte offset each line starts at
+    line_starts: Vec<usize>,
+    pub tab_width: usize,
+}
+
+impl SourceFile {
+    pub fn new(path: impl Into<PathBuf>, content: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(ix, _)| ix + 1))
+            .collect();
+        SourceFile {
+            path: path.into(),
+            content,
+            line_starts,
+            tab_width: DEFAULT_TAB_WIDTH,
+        }
+    }
+
+    /// Tabs always take up at least one column
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width.max(1);
+        self
+    }
+
+    pub fn open(path: &Path) -> std::io::Result<SourceFile> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(SourceFile::new(path, content))
+    }
+
+    pub fn lexer(&self) -> Lexer<'_> {
+        Lexer::new(&self.content)
+    }
+
+    /// The `n`th line (counting from 0), without its newline
+    pub fn line(&self, n: usize) -> Option<&str> {
+        let start = *self.line_starts.get(n)?;
+        let end = match self.line_starts.get(n + 1) {
+            Some(next) => next - 1,
+            None => self.content.len(),
+        };
+        Some(&self.content[start..end])
+    }
+
+    /// The line (counting from 0) that byte `ix` is on
+    pub fn line_index(&self, ix: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= ix) - 1
+    }
+
+    pub fn line_start(&self, n: usize) -> Option<usize> {
+        self.line_starts.get(n).copied()
+    }
+}
+
+#[test]
+fn test_source_file_lines() {
+    let src = SourceFile::new("test.toki", "a = 1;\n\nb = 2;".to_string());
+
+    assert_eq!(src.line(0), Some("a = 1;"));
+    assert_eq!(src.line(1), Some(""));
+    assert_eq!(src.line(2), Some("b = 2;"));
+    assert_eq!(src.line(3), None);
+
+    assert_eq!(src.line_index(0), 0);
+    assert_eq!(src.line_index(6), 0);
+    assert_eq!(src.line_index(7), 1);
+    assert_eq!(src.line_index(8), 2);
+    assert_eq!(src.line_start(2), Some(8));
+}
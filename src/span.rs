@@ -0,0 +1,80 @@
+/// A byte range `start..end` in the source
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The same range, moved `by` bytes
+    pub fn shifted(&self, by: isize) -> Self {
+        Span::new(self.start.saturating_add_signed(by), self.end.saturating_add_signed(by))
+    }
+}
+
+/// An AST node along with the source it was parsed from. Derefs to the node,
+/// so most code can treat it as a plain `T`.
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Spanned { node, span }
+    }
+
+    /// Transforms the node, keeping its span
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Spanned<U> {
+        Spanned {
+            node: f(self.node),
+            span: self.span,
+        }
+    }
+}
+
+impl<T> std::ops::Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+// Spans are ignored, so the same program formatted differently (e.g. after a
+// round trip through the pretty printer) still compares equal
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for Spanned<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.node.fmt(f)
+    }
+}
+
+#[test]
+fn test_spanned_delegates_to_node() {
+    let a = Spanned::new(42, Span::new(0, 2));
+    let b = Spanned::new(42, Span::new(10, 12));
+
+    assert_eq!(a.to_string(), "42");
+    assert_eq!(*a + 1, 43);
+    assert_eq!(a, b);
+    assert_eq!(a.map(|n| n * 2).span, Span::new(0, 2));
+}
@@ -1,19 +1,30 @@
 use crate::parser::Precedence;
 
 pub type SpannedToken<'src> = (usize, Token<'src>);
+/// A spanned token along with the exact source text it was lexed from
+pub type TextToken<'src> = (usize, Token<'src>, &'src str);
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token<'src> {
     // Have to give special treatment to sequences of spaces b/c
     Indent,
     Dedent,
 
-    IntLiteral(i32),
+    // `suffix` is one of `INT_SUFFIXES`, as in `255u8`. Without one the
+    // value is in `i32` range.
+    IntLiteral { value: i64, suffix: Option<&'src str> },
     FloatLiteral(f32),
-    StrLiteral(&'src str),
+    // `triple` for a `"""`-quoted string, whose quotes take up 6 bytes
+    StrLiteral { value: &'src str, triple: bool },
     Ident(&'src str),
+    // The text of a `#!` line, without the leading `#!`
+    Pragma(&'src str),
+    // The text of a whole-line `#` comment, without the `#`. Only lexed when
+    // asked for with `Lexer::with_comments`.
+    Comment(&'src str),
 
     Bar,
+    Hash,
 
     Newline,
 
@@ -26,6 +37,9 @@ pub enum Token<'src> {
     Sub,
     Mul,
     Div,
+    // `//`, `#` starts comments so this is never one
+    FloorDiv,
+    Percent,
 
     AddEq,
     SubEq,
@@ -46,22 +60,55 @@ pub enum Token<'src> {
     Colon,
     Semicolon,
     Walrus,
+    // Only as `?.` or `?(`, for optional chaining
+    Question,
     Comma,
 
     // Non-Operator Keywords
     Mut,
     If,
+    Elif,
     Else,
     Return,
     Def,
     Struct,
+    Match,
+    Case,
+    Do,
+    Let,
+    Const,
     // Enum,
     Dot,
 }
 
 impl Token<'_> {
+    /// How many bytes the token takes up in source. Exact for everything but
+    /// literals, which lose their `_` separators and triple quotes when lexed.
     pub fn src_len(&self) -> usize {
-        format!("{}", self).chars().count()
+        if let Some(keyword) = self.keyword() {
+            return keyword.len();
+        }
+        match self {
+            Self::IntLiteral { .. } | Self::FloatLiteral(_) => self.to_string().len(),
+            Self::StrLiteral { value, triple: false } => value.len() + 2,
+            Self::StrLiteral { value, triple: true } => value.len() + 6,
+            Self::Ident(id) => id.len(),
+            Self::Pragma(p) => p.len() + 2,
+            Self::Comment(c) => c.len() + 1,
+            Self::Indent => 4,
+            // Emitted where the next line's code starts, taking up nothing
+            Self::Dedent => 0,
+            Self::Arrow
+            | Self::DoubleEq
+            | Self::BangEq
+            | Self::AddEq
+            | Self::SubEq
+            | Self::MulEq
+            | Self::DivEq
+            | Self::Walrus
+            | Self::FloorDiv => 2,
+            _ => 1,
+        }
     }
 }
 
@@ -71,14 +118,17 @@ impl std::fmt::Display for Token<'_> {
             f,
             "{}",
             match self {
-                Self::IntLiteral(i) => return write!(f, "{}", i),
+                Self::IntLiteral { value, suffix } => return write!(f, "{}{}", value, suffix.unwrap_or("")),
                 Self::FloatLiteral(fl) => return write!(f, "{}", fl),
-                Self::StrLiteral(s) => return write!(f, "{}", s),
+                Self::StrLiteral { value, .. } => return write!(f, "{}", value),
                 Self::Ident(id) => return write!(f, "{}", id),
+                Self::Pragma(p) => return write!(f, "#!{}", p),
+                Self::Comment(c) => return write!(f, "#{}", c),
                 Self::Indent => return write!(f, "INDENT",),
                 Self::Dedent => return write!(f, "DEDENT",),
 
                 Self::Bar => "|",
+                Self::Hash => "#",
 
                 Self::LParen => "(",
                 Self::RParen => ")",
@@ -89,6 +139,8 @@ impl std::fmt::Display for Token<'_> {
                 Self::Sub => "-",
                 Self::Mul => "*",
                 Self::Div => "/",
+                Self::FloorDiv => "//",
+                Self::Percent => "%",
 
                 Self::AddEq => "+=",
                 Self::SubEq => "-=",
@@ -98,6 +150,7 @@ impl std::fmt::Display for Token<'_> {
                 Self::Eq => "=",
                 Self::DoubleEq => "==",
                 Self::Bang => "!",
+                Self::Question => "?",
                 Self::BangEq => "!=",
 
                 Self::Not => "not",
@@ -114,11 +167,17 @@ impl std::fmt::Display for Token<'_> {
                 Self::Walrus => ":=",
 
                 Self::Mut => "mut",
-                Self::If => "If",
-                Self::Else => "Else",
+                Self::If => "if",
+                Self::Elif => "elif",
+                Self::Else => "else",
                 Self::Return => "return",
                 Self::Def => "def",
                 Self::Struct => "struct",
+                Self::Match => "match",
+                Self::Case => "case",
+                Self::Do => "do",
+                Self::Let => "let",
+                Self::Const => "const",
 
                 Self::Dot => ".",
             }
@@ -126,49 +185,193 @@ impl std::fmt::Display for Token<'_> {
     }
 }
 
+/// Type suffixes an int literal may end in, with the range each allows
+pub const INT_SUFFIXES: &[(&str, i64, i64)] = &[
+    ("i8", i8::MIN as i64, i8::MAX as i64),
+    ("i16", i16::MIN as i64, i16::MAX as i64),
+    ("i32", i32::MIN as i64, i32::MAX as i64),
+    ("i64", i64::MIN, i64::MAX),
+    ("u8", 0, u8::MAX as i64),
+    ("u16", 0, u16::MAX as i64),
+    ("u32", 0, u32::MAX as i64),
+    ("u64", 0, i64::MAX),
+];
+
+impl<'src> Token<'src> {
+    /// An int literal without a suffix
+    pub fn int(value: i32) -> Self {
+        Token::IntLiteral {
+            value: value.into(),
+            suffix: None,
+        }
+    }
+
+    pub fn str(value: &'src str) -> Self {
+        Token::StrLiteral { value, triple: false }
+    }
+}
+
 impl Token<'_> {
     pub fn as_operator(&self) -> Option<Operator> {
-        Some(match self {
-            Self::Add => Operator::Add,
-            Self::Sub => Operator::Sub,
-            Self::Mul => Operator::Mul,
-            Self::Div => Operator::Div,
-            Self::DoubleEq => Operator::Equals,
-            _ => return None,
-        })
+        Operator::from_token(self)
+    }
+
+    pub fn keywords() -> impl Iterator<Item = &'static Token<'static>> {
+        KEYWORDS.iter().map(|(tok, _)| tok)
+    }
+
+    /// How a keyword token is spelled in source, `None` for everything else
+    pub fn keyword(&self) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .find(|(tok, _)| tok == self)
+            .map(|&(_, spelling)| spelling)
     }
 }
 
-#[derive(Debug, PartialEq)]
+// Every keyword and its spelling, the one list the lexer, `Token::keyword` and
+// the highlighting grammar all read from
+const KEYWORDS: &[(Token<'static>, &str)] = &[
+    (Token::And, "and"),
+    (Token::Or, "or"),
+    (Token::Not, "not"),
+    (Token::Mut, "mut"),
+    (Token::Return, "return"),
+    (Token::If, "if"),
+    (Token::Elif, "elif"),
+    (Token::Else, "else"),
+    (Token::Def, "def"),
+    (Token::Struct, "struct"),
+    (Token::Match, "match"),
+    (Token::Case, "case"),
+    (Token::Do, "do"),
+    (Token::Let, "let"),
+    (Token::Const, "const"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Operator {
     Add,
     Sub,
     Div,
+    FloorDiv,
+    Mod,
     Mul,
     Equals,
+    NotEquals,
+    And,
+    Or,
 }
 
 impl Operator {
+    pub fn all() -> &'static [Operator] {
+        &[
+            Self::Add,
+            Self::Sub,
+            Self::Div,
+            Self::FloorDiv,
+            Self::Mod,
+            Self::Mul,
+            Self::Equals,
+            Self::NotEquals,
+            Self::And,
+            Self::Or,
+        ]
+    }
+
+    pub fn from_token(tok: &Token<'_>) -> Option<Operator> {
+        Some(match tok {
+            Token::Add => Self::Add,
+            Token::Sub => Self::Sub,
+            Token::Mul => Self::Mul,
+            Token::Div => Self::Div,
+            Token::FloorDiv => Self::FloorDiv,
+            Token::Percent => Self::Mod,
+            Token::DoubleEq => Self::Equals,
+            Token::BangEq => Self::NotEquals,
+            Token::And => Self::And,
+            Token::Or => Self::Or,
+            _ => return None,
+        })
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::FloorDiv => "//",
+            Self::Mod => "%",
+            Self::Equals => "==",
+            Self::NotEquals => "!=",
+            Self::And => "and",
+            Self::Or => "or",
+        }
+    }
+
     pub fn precedence(&self) -> Precedence {
         match self {
+            Self::Or => Precedence::Or,
+            Self::And => Precedence::And,
             Self::Add | Self::Sub => Precedence::AddSub,
-            Self::Mul | Self::Div => Precedence::MulDiv,
-            Self::Equals => Precedence::Equality,
+            Self::Mul | Self::Div | Self::FloorDiv | Self::Mod => Precedence::MulDiv,
+            Self::Equals | Self::NotEquals => Precedence::Equality,
         }
     }
 }
 impl std::fmt::Display for Operator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::Add => "+",
-                Self::Sub => "-",
-                Self::Mul => "*",
-                Self::Div => "/",
-                Self::Equals => "==",
-            }
-        )
+        write!(f, "{}", self.symbol())
     }
 }
+
+#[test]
+fn test_operator_symbols_unique() {
+    let mut symbols = Operator::all().iter().map(|op| op.symbol()).collect::<Vec<_>>();
+    symbols.sort();
+    symbols.dedup();
+    assert_eq!(symbols.len(), Operator::all().len());
+}
+
+#[test]
+fn test_operator_from_token_round_trip() {
+    for op in Operator::all() {
+        let (_, tok) = crate::lexer::Lexer::new(op.symbol()).next().unwrap().unwrap();
+        assert_eq!(Operator::from_token(&tok).as_ref(), Some(op));
+    }
+}
+
+#[test]
+fn test_src_len_multi_char_tokens() {
+    let src = "-> == != += -= *= /= // and or not mut return if else def struct match case do \"é\" \"\"\"é\"\"\"";
+    for (ix, tok) in crate::lexer::Lexer::new(src).map(|t| t.unwrap()) {
+        let end = src[ix..].find(' ').map_or(src.len(), |n| ix + n);
+        assert_eq!(tok.src_len(), end - ix, "{:?}", tok);
+    }
+}
+
+#[test]
+fn test_src_len_of_literals() {
+    let src = "foo \"a b\" 1234 \n";
+    let lens: Vec<_> = crate::lexer::Lexer::new(src).map(|t| t.unwrap().1.src_len()).collect();
+    assert_eq!(lens, vec![3, 5, 4, 1]);
+}
+
+#[test]
+fn test_keywords_display_round_trip() {
+    for keyword in Token::keywords() {
+        let printed = keyword.to_string();
+        assert_eq!(keyword.keyword(), Some(printed.as_str()));
+
+        let (_, tok) = crate::lexer::Lexer::new(&printed).next().unwrap().unwrap();
+        assert_eq!(&tok, keyword);
+    }
+}
+
+#[test]
+fn test_operators_are_hashable() {
+    let ops: std::collections::HashSet<Operator> = Operator::all().iter().copied().collect();
+    assert_eq!(ops.len(), Operator::all().len());
+    assert_eq!(ops.len(), 10);
+}
@@ -0,0 +1,206 @@
+use std::process::Command;
+
+fn toki() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_interp-rs"))
+}
+
+#[test]
+fn test_output_c_to_stdout() {
+    let out = toki()
+        .args(["tests/fixtures/sample.toki", "--output-c", "-"])
+        .output()
+        .unwrap();
+
+    assert!(out.status.success());
+    assert!(String::from_utf8(out.stdout).unwrap().starts_with("#include"));
+}
+
+#[test]
+fn test_output_c_refuses_overwrite_without_force() {
+    let path = std::env::temp_dir().join(format!("toki-output-c-{}.c", std::process::id()));
+    std::fs::write(&path, "existing").unwrap();
+    let path_str = path.to_str().unwrap();
+
+    let out = toki()
+        .args(["tests/fixtures/sample.toki", "--output-c", path_str])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "existing");
+
+    let out = toki()
+        .args(["tests/fixtures/sample.toki", "--output-c", path_str, "--force"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(std::fs::read_to_string(&path).unwrap().starts_with("#include"));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_parse_errors_fail_without_output() {
+    for flag in ["--output-c", "--output-wasm-text"] {
+        let out = toki()
+            .args(["tests/fixtures/invalid.toki", flag, "-"])
+            .output()
+            .unwrap();
+
+        assert!(!out.status.success(), "{}", flag);
+        assert!(out.stdout.is_empty(), "{}", String::from_utf8_lossy(&out.stdout));
+        assert!(String::from_utf8(out.stderr).unwrap().contains("Error[E0001]"));
+    }
+}
+
+#[test]
+fn test_output_wasm_text_to_stdout() {
+    let out = toki()
+        .args(["tests/fixtures/sample.toki", "--output-wasm-text", "-"])
+        .output()
+        .unwrap();
+
+    assert!(out.status.success());
+    assert!(String::from_utf8(out.stdout).unwrap().starts_with("(module"));
+}
+
+#[test]
+fn test_toki_log_traces_parser() {
+    let out = toki()
+        .arg("tests/fixtures/sample.toki")
+        .env("TOKI_LOG", "trace")
+        .output()
+        .unwrap();
+
+    assert!(out.status.success());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("parse_block depth=0"), "{}", stderr);
+    assert!(
+        stderr.contains("parse_fn_def depth=0 token=Def span=0..3"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_tokens_flag() {
+    let out = toki()
+        .args(["tests/fixtures/sample.toki", "--tokens"])
+        .output()
+        .unwrap();
+
+    assert!(out.status.success());
+    assert!(String::from_utf8(out.stdout)
+        .unwrap()
+        .starts_with("[def][square][(][x][:][int][)]"));
+}
+
+#[test]
+fn test_lsp_over_stdio() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = toki()
+        .arg("--lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    for body in [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ] {
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    }
+    drop(stdin);
+
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.starts_with("Content-Length: "));
+    assert!(stdout.contains(r#""hoverProvider":true"#));
+}
+
+#[test]
+fn test_lsp_stdout_is_only_messages() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = toki()
+        .arg("--lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    for body in [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.toki","languageId":"toki","version":1,"text":"x = ];\n"}}}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ] {
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    }
+    drop(stdin);
+
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+
+    // Every byte belongs to a framed message, with nothing between them
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let mut rest = stdout.as_str();
+    let mut methods = Vec::new();
+    while !rest.is_empty() {
+        let (header, after) = rest.split_once("\r\n\r\n").expect("Header ends in a blank line");
+        let len: usize = header
+            .strip_prefix("Content-Length: ")
+            .and_then(|len| len.parse().ok())
+            .unwrap_or_else(|| panic!("Expected a Content-Length header, got {:?}", header));
+        let msg: serde_json::Value = serde_json::from_str(&after[..len]).unwrap();
+        methods.push(msg["method"].clone());
+        rest = &after[len..];
+    }
+    assert_eq!(methods.len(), 2);
+    assert_eq!(methods[1], "textDocument/publishDiagnostics");
+}
+
+#[test]
+fn test_source_from_stdin() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    for arg in ["--stdin", "-"] {
+        let mut child = toki()
+            .arg(arg)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"1 + 2\n").unwrap();
+
+        let out = child.wait_with_output().unwrap();
+        assert!(out.status.success());
+        assert_eq!(String::from_utf8(out.stdout).unwrap().trim_end(), "(1 + 2)");
+    }
+}
+
+#[test]
+fn test_stdin_errors_name_stdin() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = toki()
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"x = ;\n").unwrap();
+
+    let out = child.wait_with_output().unwrap();
+    assert!(!out.status.success());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("<stdin>:1:4:"), "{}", stderr);
+}